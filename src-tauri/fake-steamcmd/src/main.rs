@@ -0,0 +1,306 @@
+//! A stand-in for SteamCMD.
+//!
+//! It understands the same `+command` arguments, runscripts and interactive
+//! `Steam>` prompt as the real client, and prints what the scenario tells it
+//! to instead of talking to Valve's servers. The scenario is taken from
+//! `FAKE_STEAMCMD_SCENARIO` (a file or a bundled name), then from a
+//! `scenario.txt` next to the executable, and defaults to `success`.
+
+mod scenario;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use scenario::{GuardKind, Scenario, Step};
+
+const VERSION: &str = "1716584665";
+
+fn main() {
+    let scenario = match Scenario::load(&scenario_name()) {
+        Ok(scenario) => scenario,
+        Err(e) => {
+            eprintln!("fake-steamcmd: {}", e);
+            process::exit(2);
+        }
+    };
+
+    let mut console = Console::new(scenario);
+    console.banner();
+
+    for command in split_arguments(env::args().skip(1)) {
+        console.execute(&command);
+    }
+    console.interactive();
+}
+
+fn scenario_name() -> String {
+    if let Ok(name) = env::var("FAKE_STEAMCMD_SCENARIO") {
+        return name;
+    }
+    let beside = executable_dir().join("scenario.txt");
+    if beside.is_file() {
+        return beside.display().to_string();
+    }
+    "success".to_string()
+}
+
+fn executable_dir() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|path| path.parent().map(PathBuf::from))
+        .unwrap_or_default()
+}
+
+/// Groups `+login user pass +app_update 740 +quit` into one command per `+`.
+fn split_arguments(args: impl Iterator<Item = String>) -> Vec<Vec<String>> {
+    let mut commands: Vec<Vec<String>> = Vec::new();
+    for arg in args {
+        match arg.strip_prefix('+') {
+            Some(command) => commands.push(vec![command.to_string()]),
+            None => match commands.last_mut() {
+                Some(command) => command.push(arg),
+                None => eprintln!("fake-steamcmd: ignoring argument {:?}", arg),
+            },
+        }
+    }
+    commands
+}
+
+/// Splits a console line into words, keeping double-quoted words together.
+fn split_line(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+struct Console {
+    scenario: Scenario,
+    user: Option<String>,
+    install_dir: Option<String>,
+    app_id: Option<String>,
+    /// Exit code once SteamCMD quits: that of the last failed command.
+    status: i32,
+}
+
+impl Console {
+    fn new(scenario: Scenario) -> Self {
+        Console { scenario, user: None, install_dir: None, app_id: None, status: 0 }
+    }
+
+    fn banner(&self) {
+        println!("Redirecting stderr to '{}'", executable_dir().join("logs").join("stderr.txt").display());
+        println!("[  0%] Checking for available updates...");
+        println!("[----] Verifying installation...");
+        println!("Steam Console Client (c) Valve Corporation - version {}", VERSION);
+        println!("-- type 'quit' to exit --");
+        println!("Loading Steam API...OK");
+    }
+
+    /// Reads commands from stdin at the `Steam>` prompt until `quit` or EOF.
+    fn interactive(&mut self) {
+        loop {
+            print!("\nSteam>");
+            flush();
+            match read_line() {
+                Some(line) => {
+                    let words = split_line(&line);
+                    if !words.is_empty() {
+                        self.execute(&words);
+                    }
+                }
+                None => self.quit(),
+            }
+        }
+    }
+
+    fn execute(&mut self, words: &[String]) {
+        let Some((command, args)) = words.split_first() else {
+            return;
+        };
+
+        match command.to_ascii_lowercase().as_str() {
+            "login" => self.login(args),
+            "logout" => self.user = None,
+            "force_install_dir" => self.install_dir = args.first().cloned(),
+            "app_update" => self.app_update(args),
+            "runscript" => self.runscript(args),
+            "quit" | "exit" => self.quit(),
+            // Settings such as @sSteamCmdForcePlatformType
+            setting if setting.starts_with('@') => {}
+            other => println!("Command not found: {}", other),
+        }
+    }
+
+    fn login(&mut self, args: &[String]) {
+        let Some(user) = args.first() else {
+            println!("Usage: login <username> [<password>] [<Steam guard code>]");
+            return;
+        };
+
+        let section = if user == "anonymous" { "anonymous" } else { "login" };
+        self.user = Some(user.clone());
+        if !self.play(section, args.get(2).map(String::as_str)) {
+            self.user = None;
+        }
+    }
+
+    fn app_update(&mut self, args: &[String]) {
+        let Some(app_id) = args.first() else {
+            println!("Usage: app_update <appid> [-beta <betaname>] [-betapassword <password>] [validate]");
+            return;
+        };
+        if self.user.is_none() {
+            println!("ERROR! Not logged on.");
+            self.status = 8;
+            return;
+        }
+
+        self.app_id = Some(app_id.clone());
+        if args.iter().any(|arg| arg == "validate") && !self.play("app_validate", None) {
+            return;
+        }
+        self.play("app_update", None);
+    }
+
+    fn runscript(&mut self, args: &[String]) {
+        let Some(path) = args.first() else {
+            println!("Usage: runscript <filename>");
+            return;
+        };
+        let script = match fs::read_to_string(path) {
+            Ok(script) => script,
+            Err(_) => {
+                println!("Couldn't load script file '{}'", path);
+                return;
+            }
+        };
+
+        println!("Executing script file '{}'", path);
+        for line in script.lines() {
+            if line.trim_start().starts_with("//") {
+                continue;
+            }
+            let words = split_line(line);
+            if !words.is_empty() {
+                self.execute(&words);
+            }
+        }
+    }
+
+    fn quit(&mut self) -> ! {
+        println!("Unloading Steam API...OK");
+        flush();
+        process::exit(self.status);
+    }
+
+    /// Plays a scenario section; false if it failed the command.
+    fn play(&mut self, section: &str, provided_code: Option<&str>) -> bool {
+        let steps = self.scenario.section(section).to_vec();
+        for step in steps {
+            match step {
+                Step::Print(line) => println!("{}", self.fill(&line)),
+                Step::Guard { kind, code } => {
+                    if !self.guard(kind, code.as_deref(), provided_code) {
+                        self.status = 5;
+                        return false;
+                    }
+                }
+                Step::Progress { state, from, to, total } => {
+                    for percent in (from..=to).step_by(10) {
+                        println!(
+                            " Update state (0x{:x}) {}, progress: {:.2} ({} / {})",
+                            scenario::state_code(&state),
+                            state,
+                            percent as f64,
+                            total * percent as u64 / 100,
+                            total
+                        );
+                    }
+                }
+                Step::Sleep(ms) => {
+                    flush();
+                    thread::sleep(Duration::from_millis(ms));
+                }
+                Step::Fail(code) => {
+                    self.status = code;
+                    return false;
+                }
+                Step::Exit(code) => {
+                    flush();
+                    process::exit(code);
+                }
+            }
+        }
+        true
+    }
+
+    /// Checks the code from the login command, or asks for one.
+    fn guard(&self, kind: GuardKind, expected: Option<&str>, provided: Option<&str>) -> bool {
+        let code = match provided {
+            Some(code) => code.to_string(),
+            None => {
+                print!("{}", kind.prompt());
+                flush();
+                match read_line() {
+                    Some(code) => code.trim().to_string(),
+                    None => {
+                        println!("\nFAILED (Account Logon Denied)");
+                        return false;
+                    }
+                }
+            }
+        };
+
+        if expected.is_some_and(|expected| !expected.eq_ignore_ascii_case(&code)) {
+            println!("FAILED (Invalid Login Auth Code)");
+            return false;
+        }
+        true
+    }
+
+    fn fill(&self, line: &str) -> String {
+        line.replace("{user}", self.user.as_deref().unwrap_or_default())
+            .replace("{app_id}", self.app_id.as_deref().unwrap_or_default())
+            .replace("{install_dir}", self.install_dir.as_deref().unwrap_or_default())
+    }
+}
+
+fn read_line() -> Option<String> {
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end().to_string()),
+    }
+}
+
+fn flush() {
+    let _ = io::stdout().flush();
+}
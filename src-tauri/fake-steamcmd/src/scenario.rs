@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Sections a scenario leaves out are taken from here.
+const FALLBACK: &str = include_str!("../scenarios/success.txt");
+
+/// One line of a scenario section.
+///
+/// Plain lines are printed as they are, with `{user}`, `{app_id}` and
+/// `{install_dir}` filled in. Lines starting with `@` are directives:
+///
+/// - `@guard email|mobile [code]` asks for a Steam Guard code, unless the
+///   login already carried one; with `code` given anything else is rejected
+/// - `@progress <state> <from> <to> <total>` prints "Update state" lines in
+///   steps of ten percent, `_` in the state stands for a space
+/// - `@sleep <ms>` pauses the output
+/// - `@fail <code>` fails the command and skips the rest of the section
+/// - `@exit <code>` ends the process on the spot, like a crash or a dropped connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    Print(String),
+    Guard { kind: GuardKind, code: Option<String> },
+    Progress { state: String, from: u32, to: u32, total: u64 },
+    Sleep(u64),
+    Fail(i32),
+    Exit(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuardKind {
+    Email,
+    Mobile,
+}
+
+impl GuardKind {
+    pub fn prompt(self) -> &'static str {
+        match self {
+            GuardKind::Email => "Steam Guard code:",
+            GuardKind::Mobile => "Two-factor code:",
+        }
+    }
+}
+
+/// What the fake prints for each command, keyed by `[section]`:
+/// `anonymous` and `login` for the two kinds of login, `app_update` for updates
+/// and `app_validate`, played before `app_update` when it is asked to validate.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    sections: HashMap<String, Vec<Step>>,
+}
+
+impl Scenario {
+    /// Loads a scenario file, or one of the bundled ones by name.
+    pub fn load(name: &str) -> Result<Self, String> {
+        let path = Path::new(name);
+        let text = if path.is_file() {
+            fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", name, e))?
+        } else {
+            let bundled = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios").join(format!("{}.txt", name));
+            fs::read_to_string(&bundled).map_err(|_| format!("Unknown scenario {}", name))?
+        };
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut sections = parse_sections(FALLBACK)?;
+        sections.extend(parse_sections(text)?);
+        Ok(Scenario { sections })
+    }
+
+    pub fn section(&self, name: &str) -> &[Step] {
+        self.sections.get(name).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+fn parse_sections(text: &str) -> Result<HashMap<String, Vec<Step>>, String> {
+    let mut sections = HashMap::new();
+    let mut current: Option<(String, Vec<Step>)> = None;
+
+    for line in text.lines() {
+        if line.starts_with('#') || (line.trim().is_empty() && current.is_none()) {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            if let Some((name, steps)) = current.take() {
+                sections.insert(name, steps);
+            }
+            current = Some((name.to_string(), Vec::new()));
+            continue;
+        }
+
+        let Some((_, steps)) = current.as_mut() else {
+            return Err(format!("Line outside of a section: {:?}", line));
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        steps.push(parse_step(line)?);
+    }
+
+    if let Some((name, steps)) = current {
+        sections.insert(name, steps);
+    }
+    Ok(sections)
+}
+
+fn parse_step(line: &str) -> Result<Step, String> {
+    let Some(directive) = line.strip_prefix('@') else {
+        return Ok(Step::Print(line.to_string()));
+    };
+
+    let invalid = || format!("Invalid directive: {:?}", line);
+    let words: Vec<&str> = directive.split_whitespace().collect();
+    let number = |index: usize| -> Result<u64, String> {
+        words.get(index).and_then(|word| word.parse().ok()).ok_or_else(invalid)
+    };
+
+    match words.first().copied() {
+        Some("guard") => {
+            let kind = match words.get(1).copied() {
+                Some("email") => GuardKind::Email,
+                Some("mobile") => GuardKind::Mobile,
+                _ => return Err(invalid()),
+            };
+            Ok(Step::Guard { kind, code: words.get(2).map(|code| code.to_string()) })
+        }
+        Some("progress") => Ok(Step::Progress {
+            state: words.get(1).ok_or_else(invalid)?.replace('_', " "),
+            from: number(2)? as u32,
+            to: number(3)? as u32,
+            total: number(4)?,
+        }),
+        Some("sleep") => Ok(Step::Sleep(number(1)?)),
+        Some("fail") => Ok(Step::Fail(number(1)? as i32)),
+        Some("exit") => Ok(Step::Exit(number(1)? as i32)),
+        _ => Err(invalid()),
+    }
+}
+
+/// SteamCMD's code for each "Update state" label.
+pub fn state_code(state: &str) -> u32 {
+    match state {
+        "reconfiguring" => 0x3,
+        "verifying install" => 0x5,
+        "preallocating" => 0x11,
+        "downloading" => 0x61,
+        "verifying update" => 0x81,
+        "committing" => 0x101,
+        _ => 0x0,
+    }
+}
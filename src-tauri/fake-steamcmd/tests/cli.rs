@@ -0,0 +1,160 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn fake(scenario: &str) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_fake-steamcmd"));
+    command.env("FAKE_STEAMCMD_SCENARIO", scenario).stdin(Stdio::null());
+    command
+}
+
+fn run(scenario: &str, args: &[&str]) -> (Option<i32>, String) {
+    let output = fake(scenario).args(args).output().unwrap();
+    (output.status.code(), String::from_utf8(output.stdout).unwrap())
+}
+
+/// Runs the fake without arguments and types `input` at its prompt.
+fn interactive(scenario: &str, input: &str) -> (Option<i32>, String) {
+    let mut child = fake(scenario).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let Output { status, stdout, .. } = child.wait_with_output().unwrap();
+    (status.code(), String::from_utf8(stdout).unwrap())
+}
+
+fn script(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("fake-steamcmd-{}-{}.txt", name, std::process::id()));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn anonymous_update_succeeds() {
+    let (code, output) = run("success", &["+force_install_dir", "/srv/games/740", "+login", "anonymous", "+app_update", "740", "validate", "+quit"]);
+
+    assert_eq!(code, Some(0));
+    assert!(output.contains("Steam Console Client (c) Valve Corporation - version"));
+    assert!(output.contains("Connecting anonymously to Steam Public...OK"));
+    assert!(output.contains(" Update state (0x61) downloading, progress: 50.00 (590545981 / 1181091962)"));
+    assert!(output.contains("Success! App '740' fully installed."));
+    assert!(output.ends_with("Unloading Steam API...OK\n"));
+}
+
+#[test]
+fn update_without_a_login_fails() {
+    let (code, output) = run("success", &["+app_update", "740", "+quit"]);
+
+    assert_eq!(code, Some(8));
+    assert!(output.contains("ERROR! Not logged on."));
+}
+
+#[test]
+fn runscript_logs_in() {
+    let path = script("login", "login venue_bot \"hunter2 with spaces\"\nquit\n");
+
+    let (code, output) = run("success", &["+runscript", path.to_str().unwrap()]);
+
+    assert_eq!(code, Some(0));
+    assert!(output.contains("Logging in user 'venue_bot' [U:1:90123456] to Steam Public...OK"));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn guard_prompt_without_an_answer_denies_the_logon() {
+    let path = script("guard", "login venue_bot hunter2\nquit\n");
+
+    let (code, output) = run("steam_guard_mobile", &["+runscript", path.to_str().unwrap()]);
+
+    assert_eq!(code, Some(5));
+    assert!(output.contains("Two-factor code:"));
+    assert!(output.contains("FAILED (Account Logon Denied)"));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn guard_code_on_the_login_line_is_checked() {
+    let (accepted, output) = run("steam_guard_email", &["+login", "venue_bot", "hunter2", "F7K2M", "+quit"]);
+    assert_eq!(accepted, Some(0));
+    assert!(output.contains("Waiting for user info...OK"));
+
+    let (rejected, output) = run("steam_guard_email", &["+login", "venue_bot", "hunter2", "AAAAA", "+quit"]);
+    assert_eq!(rejected, Some(5));
+    assert!(output.contains("FAILED (Invalid Login Auth Code)"));
+}
+
+#[test]
+fn interactive_prompt_answers_guard_codes_from_stdin() {
+    let (code, output) = interactive("steam_guard_mobile", "login venue_bot hunter2\n2bxq7\napp_update 740\nquit\n");
+
+    assert_eq!(code, Some(0));
+    assert!(output.contains("\nSteam>Logging in user 'venue_bot'"));
+    assert!(output.contains("Two-factor code:OK"));
+    assert!(output.contains("Success! App '740' fully installed."));
+}
+
+#[test]
+fn rate_limited_login_fails() {
+    let (code, output) = run("rate_limit", &["+login", "venue_bot", "hunter2", "+app_update", "740", "+quit"]);
+
+    assert_eq!(code, Some(8));
+    assert!(output.contains("FAILED (Rate Limit Exceeded)"));
+    assert!(output.contains("ERROR! Not logged on."));
+}
+
+#[test]
+fn validate_checks_the_install_first() {
+    let (code, output) = run("corrupt_files", &["+login", "anonymous", "+app_update", "740", "validate", "+quit"]);
+
+    assert_eq!(code, Some(0));
+    let verified = output.find("Update state (0x5) verifying install, progress: 100.00").unwrap();
+    let corrupted = output.find("3 files failed to validate and will be reacquired.").unwrap();
+    let installed = output.find("Success! App '740' fully installed.").unwrap();
+    assert!(verified < corrupted && corrupted < installed);
+}
+
+#[test]
+fn update_without_validate_skips_the_check() {
+    let (_, output) = run("corrupt_files", &["+login", "anonymous", "+app_update", "740", "+quit"]);
+
+    assert!(!output.contains("verifying install"));
+    assert!(!output.contains("failed to validate"));
+}
+
+#[test]
+fn disk_full_fails_the_update() {
+    let (code, output) = run("disk_full", &["+login", "anonymous", "+app_update", "740", "+quit"]);
+
+    assert_eq!(code, Some(8));
+    assert!(output.contains("Error! App '740' state is 0x202 after update job."));
+}
+
+#[test]
+fn network_drop_kills_the_process_halfway() {
+    let (code, output) = run("network_drop", &["+login", "anonymous", "+app_update", "740", "+quit"]);
+
+    assert_eq!(code, Some(8));
+    assert!(output.contains("progress: 50.00"));
+    assert!(!output.contains("progress: 60.00"));
+    assert!(output.contains("(No Connection)"));
+    assert!(!output.contains("Unloading Steam API"));
+}
+
+#[test]
+fn scenario_file_overrides_only_its_own_sections() {
+    let path = script("scenario", "[anonymous]\nConnecting anonymously to Steam Public...FAILED (No Connection)\n@fail 6\n");
+
+    let (code, output) = run(path.to_str().unwrap(), &["+login", "anonymous", "+quit"]);
+    assert_eq!(code, Some(6));
+    assert!(output.contains("FAILED (No Connection)"));
+
+    let (code, _) = run(path.to_str().unwrap(), &["+login", "venue_bot", "hunter2", "+quit"]);
+    assert_eq!(code, Some(0));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn unknown_scenario_is_an_error() {
+    let (code, _) = run("no_such_scenario", &["+quit"]);
+
+    assert_eq!(code, Some(2));
+}
@@ -0,0 +1,37 @@
+-- Baseline schema. Databases created before versioning already have some of
+-- these tables, so every statement has to be idempotent.
+
+CREATE TABLE IF NOT EXISTS steam_credentials (
+    id INTEGER PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS update_jobs (
+    id INTEGER PRIMARY KEY,
+    app_id INTEGER NOT NULL,
+    username TEXT,
+    status TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    message TEXT,
+    created_at INTEGER NOT NULL,
+    started_at INTEGER,
+    finished_at INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS app_accounts (
+    app_id INTEGER PRIMARY KEY,
+    username TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS vault_meta (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    mode TEXT NOT NULL,
+    salt TEXT,
+    check_value TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS app_settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
@@ -0,0 +1,14 @@
+CREATE TABLE update_history (
+    id INTEGER PRIMARY KEY,
+    app_id INTEGER NOT NULL,
+    username TEXT,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER,
+    result TEXT NOT NULL,
+    bytes_downloaded INTEGER NOT NULL DEFAULT 0,
+    exit_code INTEGER,
+    error_code TEXT,
+    error_message TEXT
+);
+
+CREATE INDEX idx_update_history_app ON update_history (app_id, started_at);
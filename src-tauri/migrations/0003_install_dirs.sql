@@ -0,0 +1,4 @@
+CREATE TABLE app_install_dirs (
+    app_id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL
+);
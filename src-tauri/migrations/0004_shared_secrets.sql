@@ -0,0 +1,3 @@
+-- Steam Guard shared secret for accounts whose codes we generate ourselves,
+-- encrypted by the vault like the password. NULL means codes are typed in.
+ALTER TABLE steam_credentials ADD COLUMN shared_secret TEXT;
@@ -0,0 +1,4 @@
+-- Per-job update options. Validation used to run on every update and is now
+-- opt-in; jobs still waiting in the queue keep the behaviour they were queued with.
+ALTER TABLE update_jobs ADD COLUMN validate INTEGER NOT NULL DEFAULT 0;
+UPDATE update_jobs SET validate = 1 WHERE status = 'queued';
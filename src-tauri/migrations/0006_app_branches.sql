@@ -0,0 +1,7 @@
+-- Beta branch an app is kept on instead of public. The branch password is
+-- encrypted by the vault like account passwords; NULL when it needs none.
+CREATE TABLE app_branches (
+    app_id INTEGER PRIMARY KEY,
+    branch TEXT NOT NULL,
+    password TEXT
+);
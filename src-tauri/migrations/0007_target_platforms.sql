@@ -0,0 +1,8 @@
+-- Platform and bitness forced on a job's downloads; NULL is the host's own.
+ALTER TABLE update_jobs ADD COLUMN platform TEXT;
+ALTER TABLE update_jobs ADD COLUMN bitness INTEGER;
+
+-- Platform every attempt downloaded for. Attempts before this are NULL,
+-- they always ran for the host.
+ALTER TABLE update_history ADD COLUMN platform TEXT;
+ALTER TABLE update_history ADD COLUMN bitness INTEGER;
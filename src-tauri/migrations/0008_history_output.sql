@@ -0,0 +1,3 @@
+-- The last lines SteamCMD or a custom command printed before a failed
+-- attempt. NULL for successes and for failures recorded before this.
+ALTER TABLE update_history ADD COLUMN output TEXT;
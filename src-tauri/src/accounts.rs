@@ -0,0 +1,156 @@
+use tauri::{AppHandle, Manager};
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::error::SteamError;
+use crate::steam::SteamCredentials;
+use crate::totp;
+use crate::vault::Vault;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SteamAccount {
+    pub id: i64,
+    pub username: String,
+    /// Steam Guard codes for this account are generated, not typed in.
+    pub has_shared_secret: bool,
+}
+
+/// Which stored account owns an app.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppAccount {
+    pub app_id: u32,
+    pub username: String,
+}
+
+/// Checks that credentials can be typed at the `Steam>` prompt.
+///
+/// SteamCMD has no way to escape a quote, so a password containing one
+/// would reach Steam cut short.
+pub(crate) fn validate(username: &str, password: &str) -> Result<(), SteamError> {
+    if username.trim().is_empty() {
+        return Err(SteamError::InvalidInput { reason: "Username must not be empty".to_string() });
+    }
+    if username.contains(|c: char| c.is_whitespace() || c == '"') {
+        return Err(SteamError::InvalidInput {
+            reason: format!("Username {:?} must not contain spaces or quotes", username),
+        });
+    }
+    if password.contains('"') {
+        return Err(SteamError::InvalidInput {
+            reason: "Password must not contain double quotes, SteamCMD cannot pass them on".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Loads a stored account and decrypts its password.
+pub(crate) fn load_account(app: &AppHandle, username: &str) -> Result<SteamCredentials, SteamError> {
+    let password = db::get_account_secret(username)?
+        .ok_or_else(|| SteamError::CredentialsNotFound { username: username.to_string() })?;
+
+    Ok(SteamCredentials {
+        username: username.to_string(),
+        password: app.state::<Vault>().decrypt(&password)?,
+        two_factor_code: None,
+        shared_secret: load_shared_secret(app, username)?,
+    })
+}
+
+/// Decrypts the account's stored shared secret, if it has one.
+pub(crate) fn load_shared_secret(app: &AppHandle, username: &str) -> Result<Option<String>, SteamError> {
+    db::get_shared_secret(username)?
+        .map(|shared_secret| app.state::<Vault>().decrypt(&shared_secret))
+        .transpose()
+}
+
+/// Encrypts and stores a shared secret for an existing account.
+pub(crate) fn store_shared_secret(app: &AppHandle, username: &str, shared_secret: &str) -> Result<(), SteamError> {
+    totp::decode_secret(shared_secret)?;
+    let encrypted = app.state::<Vault>().encrypt(shared_secret.trim())?;
+    if !db::set_shared_secret(username, Some(&encrypted))? {
+        return Err(SteamError::CredentialsNotFound { username: username.to_string() });
+    }
+    Ok(())
+}
+
+/// Picks the account mapped to `app_id`; `None` means the app is updated anonymously.
+pub(crate) fn credentials_for_app(app: &AppHandle, app_id: u32) -> Result<Option<SteamCredentials>, SteamError> {
+    match db::get_app_account(app_id)? {
+        Some(username) => load_account(app, &username).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub(crate) async fn list_accounts() -> Result<Vec<SteamAccount>, SteamError> {
+    Ok(db::list_accounts()?)
+}
+
+pub(crate) async fn save_account(
+    app: AppHandle,
+    username: String,
+    password: String
+) -> Result<SteamAccount, SteamError> {
+    validate(&username, &password)?;
+
+    let password = app.state::<Vault>().encrypt(&password)?;
+    db::save_credentials(&username, &password)?;
+
+    list_accounts().await?
+        .into_iter()
+        .find(|account| account.username == username)
+        .ok_or(SteamError::CredentialsNotFound { username })
+}
+
+/// Sets or, with `None`, removes the shared secret used to generate Steam Guard codes.
+pub(crate) async fn set_shared_secret(
+    app: AppHandle,
+    username: String,
+    shared_secret: Option<String>
+) -> Result<(), SteamError> {
+    match shared_secret.filter(|shared_secret| !shared_secret.trim().is_empty()) {
+        Some(shared_secret) => store_shared_secret(&app, &username, &shared_secret),
+        None if db::set_shared_secret(&username, None)? => Ok(()),
+        None => Err(SteamError::CredentialsNotFound { username }),
+    }
+}
+
+pub(crate) async fn delete_account(username: String) -> Result<(), SteamError> {
+    if !db::delete_account(&username)? {
+        return Err(SteamError::CredentialsNotFound { username });
+    }
+    Ok(())
+}
+
+pub(crate) async fn list_app_accounts() -> Result<Vec<AppAccount>, SteamError> {
+    Ok(db::list_app_accounts()?)
+}
+
+pub(crate) async fn set_app_account(app_id: u32, username: String) -> Result<AppAccount, SteamError> {
+    if db::get_account_secret(&username)?.is_none() {
+        return Err(SteamError::CredentialsNotFound { username });
+    }
+
+    db::set_app_account(app_id, &username)?;
+    Ok(AppAccount { app_id, username })
+}
+
+pub(crate) async fn clear_app_account(app_id: u32) -> Result<(), SteamError> {
+    Ok(db::clear_app_account(app_id)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_passwords_with_spaces_and_symbols() {
+        assert!(validate("gaben", "hunter2").is_ok());
+        assert!(validate("gaben", "correct horse 'battery' $taple\\").is_ok());
+    }
+
+    #[test]
+    fn rejects_what_the_console_cannot_pass_on() {
+        assert!(matches!(validate("gaben", "say \"when\""), Err(SteamError::InvalidInput { .. })));
+        assert!(matches!(validate("", "hunter2"), Err(SteamError::InvalidInput { .. })));
+        assert!(matches!(validate("gabe newell", "hunter2"), Err(SteamError::InvalidInput { .. })));
+    }
+}
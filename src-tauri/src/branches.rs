@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+use tauri::{AppHandle, Manager};
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::error::SteamError;
+use crate::vault::Vault;
+
+/// The branch every app is on unless told otherwise.
+pub(crate) const PUBLIC: &str = "public";
+
+/// Which beta branch an app is kept on.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppBranch {
+    pub app_id: u32,
+    pub branch: String,
+    /// A branch password is stored. The password itself is never sent back.
+    pub has_password: bool,
+}
+
+/// A configured branch with its password decrypted, ready for `app_update`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BetaBranch {
+    pub name: String,
+    pub password: Option<String>,
+}
+
+/// The branch `app_id` is updated from; `None` means public.
+pub(crate) fn for_app(app: &AppHandle, app_id: u32) -> Result<Option<BetaBranch>, SteamError> {
+    let Some((name, password)) = db::get_app_branch(app_id)? else {
+        return Ok(None);
+    };
+    let password = password
+        .map(|password| app.state::<Vault>().decrypt(&password))
+        .transpose()?;
+    Ok(Some(BetaBranch { name, password }))
+}
+
+/// The configured branch of every app that isn't on public.
+pub(crate) fn configured() -> Result<HashMap<u32, String>, SteamError> {
+    Ok(db::list_app_branches()?
+        .into_iter()
+        .map(|branch| (branch.app_id, branch.branch))
+        .collect())
+}
+
+/// Checks that a branch and its password can be typed at the `Steam>` prompt.
+///
+/// SteamCMD has no way to escape a quote, and branch names never contain
+/// spaces, so either would turn into different arguments.
+fn validate(branch: &str, password: Option<&str>) -> Result<(), SteamError> {
+    if branch.is_empty() {
+        return Err(SteamError::InvalidInput { reason: "Branch name must not be empty".to_string() });
+    }
+    if branch.contains(|c: char| c.is_whitespace() || c == '"') {
+        return Err(SteamError::InvalidInput {
+            reason: format!("Branch name {:?} must not contain spaces or quotes", branch),
+        });
+    }
+    if password.is_some_and(|password| password.contains('"')) {
+        return Err(SteamError::InvalidInput { reason: "Branch password must not contain quotes".to_string() });
+    }
+    Ok(())
+}
+
+pub(crate) async fn list_app_branches() -> Result<Vec<AppBranch>, SteamError> {
+    Ok(db::list_app_branches()?)
+}
+
+/// Keeps `app_id` on `branch` from its next update on. Choosing `public`
+/// forgets the app's branch and password.
+pub(crate) async fn set_app_branch(
+    app: AppHandle,
+    app_id: u32,
+    branch: String,
+    password: Option<String>
+) -> Result<AppBranch, SteamError> {
+    let branch = branch.trim().to_string();
+    let password = password.filter(|password| !password.is_empty());
+    validate(&branch, password.as_deref())?;
+
+    if branch.eq_ignore_ascii_case(PUBLIC) {
+        db::clear_app_branch(app_id)?;
+        return Ok(AppBranch { app_id, branch: PUBLIC.to_string(), has_password: false });
+    }
+
+    let encrypted = password
+        .as_deref()
+        .map(|password| app.state::<Vault>().encrypt(password))
+        .transpose()?;
+    db::set_app_branch(app_id, &branch, encrypted.as_deref())?;
+    Ok(AppBranch { app_id, branch, has_password: encrypted.is_some() })
+}
+
+pub(crate) async fn clear_app_branch(app_id: u32) -> Result<(), SteamError> {
+    Ok(db::clear_app_branch(app_id)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_branches_with_and_without_passwords() {
+        assert!(validate("previous_build", None).is_ok());
+        assert!(validate("tournament", Some("open sesame")).is_ok());
+    }
+
+    #[test]
+    fn rejects_what_the_console_would_split_up() {
+        assert!(validate("", None).is_err());
+        assert!(validate("rc 2", None).is_err());
+        assert!(validate("\"beta\"", None).is_err());
+        assert!(validate("beta", Some("say \"when\"")).is_err());
+    }
+}
@@ -1,78 +1,796 @@
-use rusqlite::{Connection, Result};
+use rusqlite::{params, Connection, OptionalExtension, Result};
 use std::path::PathBuf;
 use std::env;
+use crate::migrations;
+use crate::accounts::{AppAccount, SteamAccount};
+use crate::branches::AppBranch;
+use crate::error::SteamError;
+use crate::history::{HistoryEntry, Outcome, UpdateResult};
+use crate::install_dirs::InstallDir;
+use crate::queue::{JobStatus, UpdateJob};
+use crate::steam::{Platform, UpdateOptions};
+use crate::vault::{VaultMeta, VaultMode};
 
-pub fn get_db_path() -> Result<PathBuf, String> {
+pub fn get_db_path() -> Result<PathBuf, SteamError> {
     let exe_path = env::current_exe()
-        .map_err(|e| format!("Failed to get executable path: {}", e))?;
+        .map_err(|e| SteamError::io("Failed to get executable path", e))?;
     
     let app_dir = exe_path
         .parent()
-        .ok_or_else(|| "Failed to get parent directory".to_string())?;
+        .ok_or_else(|| SteamError::Internal { reason: "Failed to get parent directory".to_string() })?;
     
     Ok(app_dir.join("steam.db"))
 }
 
-pub fn init_db() -> Result<()> {
+fn open() -> Result<Connection> {
     let db_path = get_db_path().map_err(|e| rusqlite::Error::SqliteFailure(
-        rusqlite::ffi::Error::new(1), Some(e)
+        rusqlite::ffi::Error::new(1), Some(e.to_string())
     ))?;
 
-    let conn = Connection::open(db_path)?;
+    Connection::open(db_path)
+}
+
+/// Opens `steam.db` and applies any pending schema migrations.
+pub fn init_db() -> Result<(), SteamError> {
+    let mut conn = open()?;
+    migrations::run(&mut conn)
+}
+
+pub fn save_credentials(username: &str, password: &str) -> Result<()> {
+    let conn = open()?;
 
     conn.execute(
-        "CREATE TABLE IF NOT EXISTS steam_credentials (
-            id INTEGER PRIMARY KEY,
-            username TEXT NOT NULL UNIQUE,
-            password TEXT NOT NULL
-        )",
+        "INSERT INTO steam_credentials (username, password) VALUES (?1, ?2)
+         ON CONFLICT(username) DO UPDATE SET password = excluded.password",
+        [username, password],
+    )?;
+
+    Ok(())
+}
+
+/// The account the single-account commands work with: the first one stored.
+pub fn get_credentials() -> Result<Option<(String, String)>> {
+    get_credentials_in(&open()?)
+}
+
+fn get_credentials_in(conn: &Connection) -> Result<Option<(String, String)>> {
+    conn.query_row(
+        "SELECT username, password FROM steam_credentials ORDER BY id LIMIT 1",
         [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()
+}
+
+/// Deletes the account `get_credentials` returns, together with the apps mapped to it.
+pub fn clear_credentials() -> Result<()> {
+    clear_credentials_in(&mut open()?)
+}
+
+fn clear_credentials_in(conn: &mut Connection) -> Result<()> {
+    let Some((username, _)) = get_credentials_in(conn)? else {
+        return Ok(());
+    };
+
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM app_accounts WHERE username = ?1", [&username])?;
+    tx.execute("DELETE FROM steam_credentials WHERE username = ?1", [&username])?;
+    tx.commit()
+}
+
+pub fn list_accounts() -> Result<Vec<SteamAccount>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, username, shared_secret IS NOT NULL FROM steam_credentials ORDER BY username"
+    )?;
+    let accounts = stmt.query_map([], |row| {
+        Ok(SteamAccount {
+            id: row.get(0)?,
+            username: row.get(1)?,
+            has_shared_secret: row.get(2)?,
+        })
+    })?;
+    accounts.collect()
+}
+
+pub fn get_account_secret(username: &str) -> Result<Option<String>> {
+    let conn = open()?;
+    conn.query_row(
+        "SELECT password FROM steam_credentials WHERE username = ?1",
+        [username],
+        |row| row.get(0),
+    ).optional()
+}
+
+pub fn get_shared_secret(username: &str) -> Result<Option<String>> {
+    let conn = open()?;
+    conn.query_row(
+        "SELECT shared_secret FROM steam_credentials WHERE username = ?1",
+        [username],
+        |row| row.get(0),
+    ).optional().map(Option::flatten)
+}
+
+/// Stores or clears an account's encrypted shared secret; `false` if there is no such account.
+pub fn set_shared_secret(username: &str, shared_secret: Option<&str>) -> Result<bool> {
+    let conn = open()?;
+    let updated = conn.execute(
+        "UPDATE steam_credentials SET shared_secret = ?1 WHERE username = ?2",
+        params![shared_secret, username],
     )?;
+    Ok(updated > 0)
+}
 
+/// Deletes an account together with the apps mapped to it.
+pub fn delete_account(username: &str) -> Result<bool> {
+    let mut conn = open()?;
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM app_accounts WHERE username = ?1", [username])?;
+    let deleted = tx.execute("DELETE FROM steam_credentials WHERE username = ?1", [username])?;
+    tx.commit()?;
+    Ok(deleted > 0)
+}
+
+pub fn get_app_account(app_id: u32) -> Result<Option<String>> {
+    let conn = open()?;
+    conn.query_row(
+        "SELECT username FROM app_accounts WHERE app_id = ?1",
+        [app_id],
+        |row| row.get(0),
+    ).optional()
+}
+
+pub fn list_app_accounts() -> Result<Vec<AppAccount>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare("SELECT app_id, username FROM app_accounts ORDER BY app_id")?;
+    let mappings = stmt.query_map([], |row| {
+        Ok(AppAccount {
+            app_id: row.get(0)?,
+            username: row.get(1)?,
+        })
+    })?;
+    mappings.collect()
+}
+
+pub fn set_app_account(app_id: u32, username: &str) -> Result<()> {
+    let conn = open()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_accounts (app_id, username) VALUES (?1, ?2)",
+        params![app_id, username],
+    )?;
     Ok(())
 }
 
-pub fn save_credentials(username: &str, password: &str) -> Result<()> {
-    let db_path = get_db_path().map_err(|e| rusqlite::Error::SqliteFailure(
-        rusqlite::ffi::Error::new(1), Some(e)
-    ))?;
+pub fn clear_app_account(app_id: u32) -> Result<()> {
+    let conn = open()?;
+    conn.execute("DELETE FROM app_accounts WHERE app_id = ?1", [app_id])?;
+    Ok(())
+}
 
-    let conn = Connection::open(db_path)?;
+/// Returns every stored password as `(row id, stored value)`, encrypted or not.
+pub fn list_credential_secrets() -> Result<Vec<(i64, String)>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare("SELECT id, password FROM steam_credentials")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Returns every stored shared secret as `(row id, encrypted value)`.
+pub fn list_shared_secrets() -> Result<Vec<(i64, String)>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare("SELECT id, shared_secret FROM steam_credentials WHERE shared_secret IS NOT NULL")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
 
+pub fn update_credential_secret(id: i64, password: &str) -> Result<()> {
+    let conn = open()?;
     conn.execute(
-        "INSERT OR REPLACE INTO steam_credentials (username, password) VALUES (?1, ?2)",
-        [username, password],
+        "UPDATE steam_credentials SET password = ?1 WHERE id = ?2",
+        params![password, id],
+    )?;
+    Ok(())
+}
+
+pub fn get_vault_meta() -> Result<Option<VaultMeta>> {
+    let conn = open()?;
+    conn.query_row(
+        "SELECT mode, salt, check_value FROM vault_meta WHERE id = 1",
+        [],
+        |row| {
+            let mode: String = row.get(0)?;
+            Ok(VaultMeta {
+                mode: VaultMode::parse(&mode).ok_or_else(|| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        rusqlite::types::Type::Text,
+                        format!("Unknown vault mode: {}", mode).into(),
+                    )
+                })?,
+                salt: row.get(1)?,
+                check_value: row.get(2)?,
+            })
+        },
+    ).optional()
+}
+
+pub fn save_vault_meta(meta: &VaultMeta) -> Result<()> {
+    replace_vault_meta(meta, &[], &[], &[])
+}
+
+/// Stores new vault metadata and the secrets re-encrypted under it in one transaction.
+pub fn replace_vault_meta(
+    meta: &VaultMeta,
+    secrets: &[(i64, String)],
+    shared_secrets: &[(i64, String)],
+    branch_passwords: &[(i64, String)]
+) -> Result<()> {
+    let mut conn = open()?;
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "INSERT OR REPLACE INTO vault_meta (id, mode, salt, check_value) VALUES (1, ?1, ?2, ?3)",
+        params![meta.mode.as_str(), meta.salt, meta.check_value],
+    )?;
+    for (id, password) in secrets {
+        tx.execute(
+            "UPDATE steam_credentials SET password = ?1 WHERE id = ?2",
+            params![password, id],
+        )?;
+    }
+    for (id, shared_secret) in shared_secrets {
+        tx.execute(
+            "UPDATE steam_credentials SET shared_secret = ?1 WHERE id = ?2",
+            params![shared_secret, id],
+        )?;
+    }
+    for (app_id, password) in branch_passwords {
+        tx.execute(
+            "UPDATE app_branches SET password = ?1 WHERE app_id = ?2",
+            params![password, app_id],
+        )?;
+    }
+
+    tx.commit()
+}
+
+/// Stores new vault metadata and forgets every secret encrypted under the old one.
+pub fn reset_vault(meta: &VaultMeta) -> Result<()> {
+    let mut conn = open()?;
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "INSERT OR REPLACE INTO vault_meta (id, mode, salt, check_value) VALUES (1, ?1, ?2, ?3)",
+        params![meta.mode.as_str(), meta.salt, meta.check_value],
     )?;
+    tx.execute("DELETE FROM app_accounts", [])?;
+    tx.execute("DELETE FROM steam_credentials", [])?;
+    tx.execute("UPDATE app_branches SET password = NULL", [])?;
 
+    tx.commit()
+}
+
+pub fn get_setting(key: &str) -> Result<Option<String>> {
+    let conn = open()?;
+    conn.query_row(
+        "SELECT value FROM app_settings WHERE key = ?1",
+        [key],
+        |row| row.get(0),
+    ).optional()
+}
+
+pub fn set_setting(key: &str, value: &str) -> Result<()> {
+    let conn = open()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_settings (key, value) VALUES (?1, ?2)",
+        [key, value],
+    )?;
     Ok(())
 }
 
-pub fn get_credentials() -> Result<Option<(String, String)>> {
-    let db_path = get_db_path().map_err(|e| rusqlite::Error::SqliteFailure(
-        rusqlite::ffi::Error::new(1), Some(e)
+pub fn delete_setting(key: &str) -> Result<()> {
+    let conn = open()?;
+    conn.execute("DELETE FROM app_settings WHERE key = ?1", [key])?;
+    Ok(())
+}
+
+pub fn get_install_dir(app_id: u32) -> Result<Option<String>> {
+    let conn = open()?;
+    conn.query_row(
+        "SELECT path FROM app_install_dirs WHERE app_id = ?1",
+        [app_id],
+        |row| row.get(0),
+    ).optional()
+}
+
+pub fn list_install_dirs() -> Result<Vec<InstallDir>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare("SELECT app_id, path FROM app_install_dirs ORDER BY app_id")?;
+    let dirs = stmt.query_map([], |row| {
+        Ok(InstallDir {
+            app_id: row.get(0)?,
+            path: row.get(1)?,
+        })
+    })?;
+    dirs.collect()
+}
+
+pub fn set_install_dir(app_id: u32, path: &str) -> Result<()> {
+    let conn = open()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_install_dirs (app_id, path) VALUES (?1, ?2)",
+        params![app_id, path],
+    )?;
+    Ok(())
+}
+
+pub fn clear_install_dir(app_id: u32) -> Result<()> {
+    let conn = open()?;
+    conn.execute("DELETE FROM app_install_dirs WHERE app_id = ?1", [app_id])?;
+    Ok(())
+}
+
+pub fn get_app_branch(app_id: u32) -> Result<Option<(String, Option<String>)>> {
+    let conn = open()?;
+    conn.query_row(
+        "SELECT branch, password FROM app_branches WHERE app_id = ?1",
+        [app_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional()
+}
+
+pub fn list_app_branches() -> Result<Vec<AppBranch>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare(
+        "SELECT app_id, branch, password IS NOT NULL FROM app_branches ORDER BY app_id"
+    )?;
+    let branches = stmt.query_map([], |row| {
+        Ok(AppBranch {
+            app_id: row.get(0)?,
+            branch: row.get(1)?,
+            has_password: row.get(2)?,
+        })
+    })?;
+    branches.collect()
+}
+
+pub fn set_app_branch(app_id: u32, branch: &str, password: Option<&str>) -> Result<()> {
+    let conn = open()?;
+    conn.execute(
+        "INSERT OR REPLACE INTO app_branches (app_id, branch, password) VALUES (?1, ?2, ?3)",
+        params![app_id, branch, password],
+    )?;
+    Ok(())
+}
+
+pub fn clear_app_branch(app_id: u32) -> Result<()> {
+    let conn = open()?;
+    conn.execute("DELETE FROM app_branches WHERE app_id = ?1", [app_id])?;
+    Ok(())
+}
+
+/// Returns every stored branch password as `(app id, encrypted value)`.
+pub fn list_branch_passwords() -> Result<Vec<(i64, String)>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare("SELECT app_id, password FROM app_branches WHERE password IS NOT NULL")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+const JOB_COLUMNS: &str =
+    "id, app_id, username, status, position, message, created_at, started_at, finished_at, validate, \
+     platform, bitness";
+
+fn parse_platform(row: &rusqlite::Row, index: usize) -> Result<Option<Platform>> {
+    let platform: Option<String> = row.get(index)?;
+    platform
+        .map(|platform| Platform::parse(&platform).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                index,
+                rusqlite::types::Type::Text,
+                format!("Unknown platform: {}", platform).into(),
+            )
+        }))
+        .transpose()
+}
+
+fn job_from_row(row: &rusqlite::Row) -> Result<UpdateJob> {
+    let status: String = row.get(3)?;
+    Ok(UpdateJob {
+        id: row.get(0)?,
+        app_id: row.get(1)?,
+        username: row.get(2)?,
+        status: JobStatus::parse(&status).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                3,
+                rusqlite::types::Type::Text,
+                format!("Unknown job status: {}", status).into(),
+            )
+        })?,
+        position: row.get(4)?,
+        message: row.get(5)?,
+        created_at: row.get(6)?,
+        started_at: row.get(7)?,
+        finished_at: row.get(8)?,
+        options: UpdateOptions {
+            validate: row.get(9)?,
+            platform: parse_platform(row, 10)?,
+            bitness: row.get(11)?,
+        },
+    })
+}
+
+pub fn insert_job(app_id: u32, username: Option<&str>, options: &UpdateOptions, now: i64) -> Result<UpdateJob> {
+    insert_job_in(&open()?, app_id, username, options, now)
+}
+
+fn insert_job_in(
+    conn: &Connection,
+    app_id: u32,
+    username: Option<&str>,
+    options: &UpdateOptions,
+    now: i64
+) -> Result<UpdateJob> {
+    conn.execute(
+        "INSERT INTO update_jobs (app_id, username, status, position, created_at, validate, platform, bitness)
+         VALUES (?1, ?2, ?3, (SELECT COALESCE(MAX(position), 0) + 1 FROM update_jobs), ?4, ?5, ?6, ?7)",
+        params![
+            app_id,
+            username,
+            JobStatus::Queued.as_str(),
+            now,
+            options.validate,
+            options.platform.map(|platform| platform.as_str()),
+            options.bitness,
+        ],
+    )?;
+
+    let id = conn.last_insert_rowid();
+    conn.query_row(
+        &format!("SELECT {} FROM update_jobs WHERE id = ?1", JOB_COLUMNS),
+        [id],
+        job_from_row,
+    )
+}
+
+pub fn get_job(id: i64) -> Result<Option<UpdateJob>> {
+    let conn = open()?;
+    conn.query_row(
+        &format!("SELECT {} FROM update_jobs WHERE id = ?1", JOB_COLUMNS),
+        [id],
+        job_from_row,
+    ).optional()
+}
+
+pub fn list_jobs() -> Result<Vec<UpdateJob>> {
+    list_jobs_in(&open()?)
+}
+
+fn list_jobs_in(conn: &Connection) -> Result<Vec<UpdateJob>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM update_jobs ORDER BY position",
+        JOB_COLUMNS
     ))?;
+    let jobs = stmt.query_map([], job_from_row)?;
+    jobs.collect()
+}
+
+pub fn list_jobs_with_status(status: JobStatus) -> Result<Vec<UpdateJob>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM update_jobs WHERE status = ?1 ORDER BY position",
+        JOB_COLUMNS
+    ))?;
+    let jobs = stmt.query_map([status.as_str()], job_from_row)?;
+    jobs.collect()
+}
+
+pub fn mark_job_started(id: i64, now: i64) -> Result<()> {
+    mark_job_started_in(&open()?, id, now)
+}
+
+fn mark_job_started_in(conn: &Connection, id: i64, now: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE update_jobs SET status = ?1, started_at = ?2, message = NULL WHERE id = ?3",
+        params![JobStatus::Running.as_str(), now, id],
+    )?;
+    Ok(())
+}
 
-    let conn = Connection::open(db_path)?;
+pub fn mark_job_finished(id: i64, status: JobStatus, message: Option<&str>, now: i64) -> Result<()> {
+    let conn = open()?;
+    conn.execute(
+        "UPDATE update_jobs SET status = ?1, message = ?2, finished_at = ?3 WHERE id = ?4",
+        params![status.as_str(), message, now, id],
+    )?;
+    Ok(())
+}
+
+/// Puts jobs that were running when the app went away back into the queue.
+pub fn requeue_interrupted_jobs() -> Result<usize> {
+    requeue_interrupted_jobs_in(&open()?)
+}
+
+fn requeue_interrupted_jobs_in(conn: &Connection) -> Result<usize> {
+    conn.execute(
+        "UPDATE update_jobs SET status = ?1, started_at = NULL WHERE status = ?2",
+        [JobStatus::Queued.as_str(), JobStatus::Running.as_str()],
+    )
+}
+
+/// Moves a queued job to `index` among the queued jobs and renumbers them.
+pub fn move_queued_job(id: i64, index: usize) -> Result<bool> {
+    move_queued_job_in(&mut open()?, id, index)
+}
+
+fn move_queued_job_in(conn: &mut Connection, id: i64, index: usize) -> Result<bool> {
+    let tx = conn.transaction()?;
+
+    let (mut ids, positions): (Vec<i64>, Vec<i64>) = {
+        let mut stmt = tx.prepare(
+            "SELECT id, position FROM update_jobs WHERE status = ?1 ORDER BY position"
+        )?;
+        let rows = stmt.query_map([JobStatus::Queued.as_str()], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?.into_iter().unzip()
+    };
 
-    let mut stmt = conn.prepare("SELECT username, password FROM steam_credentials LIMIT 1")?;
-    let mut rows = stmt.query([])?;
+    let Some(current) = ids.iter().position(|&job_id| job_id == id) else {
+        return Ok(false);
+    };
 
-    if let Some(row) = rows.next()? {
-        Ok(Some((
-            row.get(0)?,
-            row.get(1)?
-        )))
-    } else {
-        Ok(None)
+    ids.remove(current);
+    ids.insert(index.min(ids.len()), id);
+
+    // Reuse the queued jobs' existing slots so their order relative to
+    // running and finished jobs stays unchanged.
+    for (job_id, position) in ids.iter().zip(&positions) {
+        tx.execute(
+            "UPDATE update_jobs SET position = ?1 WHERE id = ?2",
+            params![position, job_id],
+        )?;
     }
+
+    tx.commit()?;
+    Ok(true)
 }
 
-pub fn clear_credentials() -> Result<()> {
-    let db_path = get_db_path().map_err(|e| rusqlite::Error::SqliteFailure(
-        rusqlite::ffi::Error::new(1), Some(e)
-    ))?;
+const HISTORY_COLUMNS: &str = "id, app_id, username, started_at, finished_at, result, \
+    bytes_downloaded, exit_code, error_code, error_message, platform, bitness, output";
+
+fn history_from_row(row: &rusqlite::Row) -> Result<HistoryEntry> {
+    let result: String = row.get(5)?;
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        app_id: row.get(1)?,
+        username: row.get(2)?,
+        started_at: row.get(3)?,
+        finished_at: row.get(4)?,
+        result: UpdateResult::parse(&result).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                5,
+                rusqlite::types::Type::Text,
+                format!("Unknown update result: {}", result).into(),
+            )
+        })?,
+        bytes_downloaded: row.get::<_, i64>(6)? as u64,
+        exit_code: row.get(7)?,
+        error_code: row.get(8)?,
+        error_message: row.get(9)?,
+        platform: parse_platform(row, 10)?,
+        bitness: row.get(11)?,
+        output: row.get(12)?,
+    })
+}
 
-    let conn = Connection::open(db_path)?;
-    conn.execute("DELETE FROM steam_credentials", [])?;
+pub fn insert_history(
+    app_id: u32,
+    username: Option<&str>,
+    platform: Platform,
+    bitness: Option<u32>,
+    now: i64
+) -> Result<i64> {
+    let conn = open()?;
+    conn.execute(
+        "INSERT INTO update_history (app_id, username, started_at, result, platform, bitness)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![app_id, username, now, UpdateResult::Running.as_str(), platform.as_str(), bitness],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn finish_history(id: i64, now: i64, outcome: &Outcome) -> Result<()> {
+    let conn = open()?;
+    conn.execute(
+        "UPDATE update_history
+         SET finished_at = ?1, result = ?2, bytes_downloaded = ?3, exit_code = ?4,
+             error_code = ?5, error_message = ?6, output = ?7
+         WHERE id = ?8",
+        params![
+            now,
+            outcome.result.as_str(),
+            outcome.bytes_downloaded as i64,
+            outcome.exit_code,
+            outcome.error_code,
+            outcome.error_message,
+            outcome.output,
+            id
+        ],
+    )?;
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Fails attempts that were still running when the app went away.
+pub fn close_interrupted_history(now: i64) -> Result<usize> {
+    let conn = open()?;
+    conn.execute(
+        "UPDATE update_history
+         SET finished_at = ?1, result = ?2, error_code = 'interrupted',
+             error_message = 'The app exited before the update finished'
+         WHERE result = ?3",
+        params![now, UpdateResult::Failed.as_str(), UpdateResult::Running.as_str()],
+    )
+}
+
+/// What the most recent successful update of `app_id` downloaded, if it downloaded anything.
+pub fn last_download(app_id: u32) -> Result<Option<u64>> {
+    last_download_in(&open()?, app_id)
+}
+
+fn last_download_in(conn: &Connection, app_id: u32) -> Result<Option<u64>> {
+    let bytes: Option<i64> = conn.query_row(
+        "SELECT bytes_downloaded FROM update_history
+         WHERE app_id = ?1 AND result = ?2 AND bytes_downloaded > 0
+         ORDER BY started_at DESC, id DESC
+         LIMIT 1",
+        params![app_id, UpdateResult::Success.as_str()],
+        |row| row.get(0),
+    ).optional()?;
+    Ok(bytes.map(|bytes| bytes as u64))
+}
+
+/// Newest first, optionally for a single app.
+pub fn list_history(app_id: Option<u32>, limit: Option<u32>) -> Result<Vec<HistoryEntry>> {
+    let conn = open()?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM update_history
+         WHERE ?1 IS NULL OR app_id = ?1
+         ORDER BY started_at DESC, id DESC
+         LIMIT ?2",
+        HISTORY_COLUMNS
+    ))?;
+    // SQLite treats a negative LIMIT as "no limit"
+    let limit = limit.map(i64::from).unwrap_or(-1);
+    let entries = stmt.query_map(params![app_id, limit], history_from_row)?;
+    entries.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migrated() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run(&mut conn).unwrap();
+        conn
+    }
+
+    fn store_account(conn: &Connection, username: &str, app_ids: &[u32]) {
+        conn.execute("INSERT INTO steam_credentials (username, password) VALUES (?1, 'enc:v1:x')", [username]).unwrap();
+        for app_id in app_ids {
+            conn.execute("INSERT INTO app_accounts (app_id, username) VALUES (?1, ?2)", params![app_id, username]).unwrap();
+        }
+    }
+
+    fn mapped_apps(conn: &Connection) -> Vec<(u32, String)> {
+        let mut stmt = conn.prepare("SELECT app_id, username FROM app_accounts ORDER BY app_id").unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        rows.collect::<Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn stored_credentials_are_the_first_account_saved() {
+        let conn = migrated();
+        store_account(&conn, "zoe", &[]);
+        store_account(&conn, "adam", &[]);
+
+        assert_eq!(get_credentials_in(&conn).unwrap().map(|(username, _)| username).as_deref(), Some("zoe"));
+    }
+
+    #[test]
+    fn clearing_stored_credentials_keeps_other_accounts_and_their_apps() {
+        let mut conn = migrated();
+        store_account(&conn, "zoe", &[740]);
+        store_account(&conn, "adam", &[90, 730]);
+
+        clear_credentials_in(&mut conn).unwrap();
+
+        assert_eq!(get_credentials_in(&conn).unwrap().map(|(username, _)| username).as_deref(), Some("adam"));
+        assert_eq!(mapped_apps(&conn), vec![(90, "adam".to_string()), (730, "adam".to_string())]);
+    }
+
+    fn queue(conn: &Connection, app_ids: &[u32]) -> Vec<i64> {
+        app_ids.iter()
+            .map(|&app_id| insert_job_in(conn, app_id, None, &UpdateOptions::default(), 100).unwrap().id)
+            .collect()
+    }
+
+    fn queued_order(conn: &Connection) -> Vec<i64> {
+        list_jobs_in(conn).unwrap().into_iter()
+            .filter(|job| job.status == JobStatus::Queued)
+            .map(|job| job.id)
+            .collect()
+    }
+
+    #[test]
+    fn moving_a_job_reorders_the_queue() {
+        let mut conn = migrated();
+        let ids = queue(&conn, &[10, 20, 30, 40]);
+
+        assert!(move_queued_job_in(&mut conn, ids[3], 0).unwrap());
+        assert_eq!(queued_order(&conn), vec![ids[3], ids[0], ids[1], ids[2]]);
+
+        assert!(move_queued_job_in(&mut conn, ids[3], 2).unwrap());
+        assert_eq!(queued_order(&conn), vec![ids[0], ids[1], ids[3], ids[2]]);
+    }
+
+    #[test]
+    fn an_index_past_the_end_moves_a_job_last() {
+        let mut conn = migrated();
+        let ids = queue(&conn, &[10, 20, 30]);
+
+        assert!(move_queued_job_in(&mut conn, ids[0], 99).unwrap());
+
+        assert_eq!(queued_order(&conn), vec![ids[1], ids[2], ids[0]]);
+    }
+
+    #[test]
+    fn only_queued_jobs_can_be_moved() {
+        let mut conn = migrated();
+        let ids = queue(&conn, &[10, 20, 30]);
+        mark_job_started_in(&conn, ids[0], 200).unwrap();
+
+        assert!(!move_queued_job_in(&mut conn, ids[0], 1).unwrap());
+        assert!(!move_queued_job_in(&mut conn, 9999, 0).unwrap());
+        assert!(move_queued_job_in(&mut conn, ids[2], 0).unwrap());
+
+        // The running job keeps its slot ahead of the queue
+        let positions: Vec<(i64, i64)> = list_jobs_in(&conn).unwrap().iter().map(|job| (job.id, job.position)).collect();
+        assert_eq!(positions, vec![(ids[0], 1), (ids[2], 2), (ids[1], 3)]);
+    }
+
+    #[test]
+    fn jobs_running_at_shutdown_are_queued_again() {
+        let conn = migrated();
+        let ids = queue(&conn, &[10, 20]);
+        mark_job_started_in(&conn, ids[0], 200).unwrap();
+
+        assert_eq!(requeue_interrupted_jobs_in(&conn).unwrap(), 1);
+
+        let jobs = list_jobs_in(&conn).unwrap();
+        assert!(jobs.iter().all(|job| job.status == JobStatus::Queued && job.started_at.is_none()));
+        assert_eq!(queued_order(&conn), ids);
+    }
+
+    fn record_run(conn: &Connection, app_id: u32, started_at: i64, result: UpdateResult, bytes: u64) {
+        conn.execute(
+            "INSERT INTO update_history (app_id, started_at, finished_at, result, bytes_downloaded)
+             VALUES (?1, ?2, ?2, ?3, ?4)",
+            params![app_id, started_at, result.as_str(), bytes as i64],
+        ).unwrap();
+    }
+
+    #[test]
+    fn last_download_follows_the_latest_patch_not_the_first_install() {
+        let conn = migrated();
+        record_run(&conn, 740, 100, UpdateResult::Success, 30_000_000_000);
+        record_run(&conn, 740, 200, UpdateResult::Success, 150_000_000);
+        record_run(&conn, 740, 300, UpdateResult::Success, 80_000_000);
+        record_run(&conn, 740, 400, UpdateResult::Failed, 900_000_000);
+        record_run(&conn, 740, 500, UpdateResult::Success, 0);
+        record_run(&conn, 896660, 600, UpdateResult::Success, 5_000_000);
+
+        assert_eq!(last_download_in(&conn, 740).unwrap(), Some(80_000_000));
+        assert_eq!(last_download_in(&conn, 4000).unwrap(), None);
+    }
+}
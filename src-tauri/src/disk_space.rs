@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+use crate::db;
+use crate::error::SteamError;
+use crate::history;
+use crate::install_dirs::{self, MIN_FREE_SPACE};
+use crate::queue::JobStatus;
+use crate::steam;
+use crate::vdf;
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[DiskSpace] {}", format!($($arg)*));
+    }
+}
+
+/// Identifies the filesystem a path lives on, so jobs on the same disk share its free space.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Volume(String);
+
+/// Where an update will write and how much it is expected to need there.
+#[derive(Debug, Clone)]
+pub(crate) struct Target {
+    /// The install directory, or the closest ancestor of it that exists yet.
+    path: PathBuf,
+    volume: Volume,
+    /// Zero when neither history nor the manifest tell.
+    required: u64,
+}
+
+/// Space set aside for updates that are running, keyed by app id.
+#[derive(Default)]
+pub struct DiskReservations {
+    reserved: Mutex<HashMap<u32, (Volume, u64)>>,
+}
+
+/// Holds an update's reservation until it is dropped.
+pub struct Reservation<'a> {
+    reservations: &'a DiskReservations,
+    app_id: u32,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut reserved) = self.reservations.reserved.lock() {
+            reserved.remove(&self.app_id);
+        }
+    }
+}
+
+impl DiskReservations {
+    /// Reserves what `app_id` is expected to need, refusing if the volume
+    /// can't hold it next to the updates already running there.
+    pub(crate) fn reserve(&self, app_id: u32, target: &Target) -> Result<Reservation<'_>, SteamError> {
+        // Held across the free space check so two updates can't both claim the same room
+        let mut reserved = self.reserved.lock()
+            .map_err(|e| SteamError::internal("Failed to lock disk reservations", e))?;
+
+        let others = reserved.iter()
+            .filter(|(&other, (volume, _))| other != app_id && *volume == target.volume)
+            .map(|(_, (_, bytes))| bytes)
+            .sum();
+        check_fit(target, available_space(&target.path)?, others)?;
+
+        dev_log!("Reserved {} bytes on {:?} for app {}", target.required, target.path, app_id);
+        reserved.insert(app_id, (target.volume.clone(), target.required));
+        Ok(Reservation { reservations: self, app_id })
+    }
+
+    fn reserved_on(&self, volume: &Volume) -> u64 {
+        self.reserved.lock()
+            .map(|reserved| {
+                reserved.values()
+                    .filter(|(other, _)| other == volume)
+                    .map(|(_, bytes)| bytes)
+                    .sum()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Works out where `app_id` installs to and how much room its update needs.
+pub(crate) fn target(app: &AppHandle, app_id: u32) -> Result<Target, SteamError> {
+    let library = match install_dirs::resolve(app_id)? {
+        Some(path) => path,
+        None => steam::get_steamcmd_dir(app)?,
+    };
+    let path = existing_ancestor(&library);
+
+    Ok(Target {
+        volume: volume_of(&path)?,
+        required: expected_size(app_id, &library),
+        path,
+    })
+}
+
+/// Refuses a job whose update won't fit next to the running and queued ones on its volume.
+pub(crate) fn preflight(app: &AppHandle, app_id: u32) -> Result<(), SteamError> {
+    let wanted = target(app, app_id)?;
+    if wanted.required == 0 {
+        return Ok(());
+    }
+
+    let mut others = app.state::<DiskReservations>().reserved_on(&wanted.volume);
+    for job in db::list_jobs_with_status(JobStatus::Queued)? {
+        // A queued job whose directory is gone fails on its own when it runs
+        let Ok(queued) = target(app, job.app_id) else {
+            continue;
+        };
+        if queued.volume == wanted.volume {
+            others += queued.required;
+        }
+    }
+
+    check_fit(&wanted, available_space(&wanted.path)?, others)
+}
+
+/// Fails with the space left after `others` when `target` doesn't fit into `available`.
+fn check_fit(target: &Target, available: u64, others: u64) -> Result<(), SteamError> {
+    let available = available.saturating_sub(others);
+    let required = target.required + MIN_FREE_SPACE;
+    if available < required {
+        return Err(SteamError::InsufficientDiskSpace {
+            path: target.path.display().to_string(),
+            available,
+            required,
+        });
+    }
+    Ok(())
+}
+
+/// What the manifest still expects, or for an app without one, what its last update downloaded.
+fn expected_size(app_id: u32, library: &Path) -> u64 {
+    let manifest = library.join("steamapps").join(format!("appmanifest_{}.acf", app_id));
+    let manifest = fs::read_to_string(&manifest).ok();
+    size_to_reserve(manifest.as_deref(), || history::last_download(app_id))
+}
+
+/// A manifest knows the size of the next patch. History only stands in
+/// for a first install, and then the latest run, not the all-time largest:
+/// the first full download would otherwise size every later patch.
+fn size_to_reserve(manifest: Option<&str>, last_download: impl FnOnce() -> Option<u64>) -> u64 {
+    match manifest {
+        Some(manifest) => manifest_size(manifest),
+        None => last_download().unwrap_or_default(),
+    }
+}
+
+/// Bytes an `appmanifest_<id>.acf` says are left to download and stage.
+///
+/// A manifest for an app that isn't installed yet needs the whole download.
+fn manifest_size(text: &str) -> u64 {
+    let Ok((_, state)) = vdf::parse(text) else {
+        return 0;
+    };
+    let value = |key: &str| state.u64(key).unwrap_or_default();
+
+    if value("SizeOnDisk") == 0 {
+        value("BytesToDownload").max(value("BytesToStage"))
+    } else {
+        value("BytesToDownload").saturating_sub(value("BytesDownloaded"))
+            + value("BytesToStage").saturating_sub(value("BytesStaged"))
+    }
+}
+
+fn available_space(path: &Path) -> Result<u64, SteamError> {
+    fs2::available_space(path)
+        .map_err(|e| SteamError::io(&format!("Failed to read free space of {}", path.display()), e))
+}
+
+/// Per-app folders are created right before SteamCMD starts, so measure their parent until then.
+fn existing_ancestor(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+#[cfg(unix)]
+fn volume_of(path: &Path) -> Result<Volume, SteamError> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path)
+        .map_err(|e| SteamError::io(&format!("Failed to read {}", path.display()), e))?;
+    Ok(Volume(metadata.dev().to_string()))
+}
+
+#[cfg(windows)]
+fn volume_of(path: &Path) -> Result<Volume, SteamError> {
+    // The drive or UNC share, which is as close as std gets to a volume
+    let root = path.components().next()
+        .map(|component| component.as_os_str().to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+    Ok(Volume(root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn target(required: u64) -> Target {
+        Target { path: PathBuf::from("/srv/games"), volume: Volume("2049".to_string()), required }
+    }
+
+    #[test]
+    fn manifest_of_an_installed_app_needs_what_is_left() {
+        let manifest = r#"
+"AppState"
+{
+	"appid"		"740"
+	"SizeOnDisk"		"1394857217"
+	"BytesToDownload"		"524288000"
+	"BytesDownloaded"		"104857600"
+	"BytesToStage"		"1048576000"
+	"BytesStaged"		"0"
+}
+"#;
+
+        assert_eq!(manifest_size(manifest), 419430400 + 1048576000);
+    }
+
+    #[test]
+    fn manifest_of_a_new_install_needs_the_whole_download() {
+        let manifest = "\"AppState\"\n{\n\t\"SizeOnDisk\"\t\t\"0\"\n\t\"BytesToDownload\"\t\t\"3000\"\n\t\"BytesToStage\"\t\t\"5000\"\n}\n";
+
+        assert_eq!(manifest_size(manifest), 5000);
+    }
+
+    #[test]
+    fn manifest_without_sizes_needs_nothing() {
+        assert_eq!(manifest_size("\"AppState\"\n{\n\t\"appid\"\t\t\"740\"\n}\n"), 0);
+    }
+
+    #[test]
+    fn patches_are_sized_by_the_manifest_not_the_first_install() {
+        // 30 GiB initial install, then a 200 MiB patch is pending
+        let manifest = "\"AppState\"\n{\n\t\"SizeOnDisk\"\t\t\"32212254720\"\n\t\"BytesToDownload\"\t\t\"209715200\"\n}\n";
+        let first_install = || Some(30 * GIB);
+
+        assert_eq!(size_to_reserve(Some(manifest), first_install), 209715200);
+        assert!(check_fit(&target(size_to_reserve(Some(manifest), first_install)), 2 * GIB, 0).is_ok());
+    }
+
+    #[test]
+    fn first_install_without_a_manifest_falls_back_to_history() {
+        assert_eq!(size_to_reserve(None, || Some(5 * GIB)), 5 * GIB);
+        assert_eq!(size_to_reserve(None, || None), 0);
+    }
+
+    #[test]
+    fn update_that_fits_is_accepted() {
+        assert!(check_fit(&target(10 * GIB), 20 * GIB, 0).is_ok());
+    }
+
+    #[test]
+    fn update_that_would_leave_no_headroom_is_refused() {
+        let result = check_fit(&target(10 * GIB), 10 * GIB, 0);
+
+        assert_eq!(result, Err(SteamError::InsufficientDiskSpace {
+            path: "/srv/games".to_string(),
+            available: 10 * GIB,
+            required: 10 * GIB + MIN_FREE_SPACE,
+        }));
+    }
+
+    #[test]
+    fn space_reserved_by_other_jobs_is_not_available() {
+        let result = check_fit(&target(10 * GIB), 30 * GIB, 25 * GIB);
+
+        assert_eq!(result, Err(SteamError::InsufficientDiskSpace {
+            path: "/srv/games".to_string(),
+            available: 5 * GIB,
+            required: 10 * GIB + MIN_FREE_SPACE,
+        }));
+    }
+}
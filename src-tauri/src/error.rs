@@ -0,0 +1,379 @@
+use std::fmt;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json::{json, Value};
+use crate::steam::Platform;
+
+/// Error returned by every Tauri command.
+///
+/// Serialized as `{ code, message, context }`. `code` is stable and meant for
+/// the frontend to branch on and localize, `message` is an English fallback
+/// and `context` carries the variant's fields.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SteamError {
+    InvalidPassword,
+    SteamGuardRequired,
+    InvalidGuardCode,
+    GuardChallengeNotFound { challenge_id: u64 },
+    GuardChallengeExpired { challenge_id: u64 },
+    GuardChallengeCancelled { challenge_id: u64 },
+    NoSubscription { app_id: u32 },
+    InvalidPlatform { app_id: u32, platform: String, bitness: Option<u32> },
+    InvalidBranchPassword { app_id: u32, branch: String },
+    DiskFull { app_id: u32 },
+    RateLimited,
+    NetworkUnavailable,
+    AuthenticationFailed { output: String },
+    UpdateFailed { app_id: u32, output: String },
+    SteamCmdMissing { path: String },
+    SteamCmdInstallFailed { reason: String },
+    ChecksumMismatch { expected: String, actual: String },
+    SteamCmdBusy,
+    SteamCmdTimedOut { seconds: u64 },
+    SessionExited,
+    CommandFailed { program: String, exit_code: Option<i32>, output: String },
+    InstallDirNotFound { path: String },
+    InstallDirNotWritable { path: String, reason: String },
+    InsufficientDiskSpace { path: String, available: u64, required: u64 },
+    UpdateAlreadyRunning { app_id: u32 },
+    UpdateNotRunning { app_id: u32 },
+    UpdateCancelled { app_id: u32 },
+    CredentialsNotFound { username: String },
+    JobNotFound { job_id: i64 },
+    JobNotQueued { job_id: i64 },
+    VaultLocked,
+    VaultNotInitialized,
+    InvalidMasterPassword,
+    Crypto { reason: String },
+    DatabaseTooNew { version: u32, supported: u32 },
+    Database { reason: String },
+    Io { reason: String },
+    InvalidInput { reason: String },
+    Unsupported { reason: String },
+    Internal { reason: String },
+}
+
+impl SteamError {
+    /// Stable identifier of the variant, safe to persist and match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SteamError::InvalidPassword => "INVALID_PASSWORD",
+            SteamError::SteamGuardRequired => "STEAM_GUARD_REQUIRED",
+            SteamError::InvalidGuardCode => "INVALID_GUARD_CODE",
+            SteamError::GuardChallengeNotFound { .. } => "GUARD_CHALLENGE_NOT_FOUND",
+            SteamError::GuardChallengeExpired { .. } => "GUARD_CHALLENGE_EXPIRED",
+            SteamError::GuardChallengeCancelled { .. } => "GUARD_CHALLENGE_CANCELLED",
+            SteamError::NoSubscription { .. } => "NO_SUBSCRIPTION",
+            SteamError::InvalidPlatform { .. } => "INVALID_PLATFORM",
+            SteamError::InvalidBranchPassword { .. } => "INVALID_BRANCH_PASSWORD",
+            SteamError::DiskFull { .. } => "DISK_FULL",
+            SteamError::RateLimited => "RATE_LIMITED",
+            SteamError::NetworkUnavailable => "NETWORK_UNAVAILABLE",
+            SteamError::AuthenticationFailed { .. } => "AUTHENTICATION_FAILED",
+            SteamError::UpdateFailed { .. } => "UPDATE_FAILED",
+            SteamError::SteamCmdMissing { .. } => "STEAMCMD_MISSING",
+            SteamError::SteamCmdInstallFailed { .. } => "STEAMCMD_INSTALL_FAILED",
+            SteamError::ChecksumMismatch { .. } => "CHECKSUM_MISMATCH",
+            SteamError::SteamCmdBusy => "STEAMCMD_BUSY",
+            SteamError::SteamCmdTimedOut { .. } => "STEAMCMD_TIMED_OUT",
+            SteamError::SessionExited => "SESSION_EXITED",
+            SteamError::CommandFailed { .. } => "COMMAND_FAILED",
+            SteamError::InstallDirNotFound { .. } => "INSTALL_DIR_NOT_FOUND",
+            SteamError::InstallDirNotWritable { .. } => "INSTALL_DIR_NOT_WRITABLE",
+            SteamError::InsufficientDiskSpace { .. } => "INSUFFICIENT_DISK_SPACE",
+            SteamError::UpdateAlreadyRunning { .. } => "UPDATE_ALREADY_RUNNING",
+            SteamError::UpdateNotRunning { .. } => "UPDATE_NOT_RUNNING",
+            SteamError::UpdateCancelled { .. } => "UPDATE_CANCELLED",
+            SteamError::CredentialsNotFound { .. } => "CREDENTIALS_NOT_FOUND",
+            SteamError::JobNotFound { .. } => "JOB_NOT_FOUND",
+            SteamError::JobNotQueued { .. } => "JOB_NOT_QUEUED",
+            SteamError::VaultLocked => "VAULT_LOCKED",
+            SteamError::VaultNotInitialized => "VAULT_NOT_INITIALIZED",
+            SteamError::InvalidMasterPassword => "INVALID_MASTER_PASSWORD",
+            SteamError::Crypto { .. } => "CRYPTO",
+            SteamError::DatabaseTooNew { .. } => "DATABASE_TOO_NEW",
+            SteamError::Database { .. } => "DATABASE",
+            SteamError::Io { .. } => "IO",
+            SteamError::InvalidInput { .. } => "INVALID_INPUT",
+            SteamError::Unsupported { .. } => "UNSUPPORTED",
+            SteamError::Internal { .. } => "INTERNAL",
+        }
+    }
+
+    fn context(&self) -> Value {
+        match self {
+            SteamError::NoSubscription { app_id }
+            | SteamError::DiskFull { app_id }
+            | SteamError::UpdateAlreadyRunning { app_id }
+            | SteamError::UpdateNotRunning { app_id }
+            | SteamError::UpdateCancelled { app_id } => json!({ "app_id": app_id }),
+            SteamError::GuardChallengeNotFound { challenge_id }
+            | SteamError::GuardChallengeExpired { challenge_id }
+            | SteamError::GuardChallengeCancelled { challenge_id } => json!({ "challenge_id": challenge_id }),
+            SteamError::UpdateFailed { app_id, output } => json!({ "app_id": app_id, "output": output }),
+            SteamError::InvalidBranchPassword { app_id, branch } => json!({ "app_id": app_id, "branch": branch }),
+            SteamError::InvalidPlatform { app_id, platform, bitness } => {
+                json!({ "app_id": app_id, "platform": platform, "bitness": bitness })
+            }
+            SteamError::AuthenticationFailed { output } => json!({ "output": output }),
+            SteamError::SteamCmdMissing { path } | SteamError::InstallDirNotFound { path } => {
+                json!({ "path": path })
+            }
+            SteamError::ChecksumMismatch { expected, actual } => {
+                json!({ "expected": expected, "actual": actual })
+            }
+            SteamError::CommandFailed { program, exit_code, output } => {
+                json!({ "program": program, "exit_code": exit_code, "output": output })
+            }
+            SteamError::InstallDirNotWritable { path, reason } => {
+                json!({ "path": path, "reason": reason })
+            }
+            SteamError::InsufficientDiskSpace { path, available, required } => {
+                json!({ "path": path, "available": available, "required": required })
+            }
+            SteamError::SteamCmdTimedOut { seconds } => json!({ "seconds": seconds }),
+            SteamError::CredentialsNotFound { username } => json!({ "username": username }),
+            SteamError::JobNotFound { job_id } | SteamError::JobNotQueued { job_id } => {
+                json!({ "job_id": job_id })
+            }
+            SteamError::DatabaseTooNew { version, supported } => {
+                json!({ "version": version, "supported": supported })
+            }
+            SteamError::SteamCmdInstallFailed { reason }
+            | SteamError::Crypto { reason }
+            | SteamError::Database { reason }
+            | SteamError::Io { reason }
+            | SteamError::InvalidInput { reason }
+            | SteamError::Unsupported { reason }
+            | SteamError::Internal { reason } => json!({ "reason": reason }),
+            SteamError::InvalidPassword
+            | SteamError::SteamGuardRequired
+            | SteamError::InvalidGuardCode
+            | SteamError::RateLimited
+            | SteamError::NetworkUnavailable
+            | SteamError::SteamCmdBusy
+            | SteamError::SessionExited
+            | SteamError::VaultLocked
+            | SteamError::VaultNotInitialized
+            | SteamError::InvalidMasterPassword => json!({}),
+        }
+    }
+
+    pub fn io(context: &str, error: impl fmt::Display) -> Self {
+        SteamError::Io { reason: format!("{}: {}", context, error) }
+    }
+
+    pub fn internal(context: &str, error: impl fmt::Display) -> Self {
+        SteamError::Internal { reason: format!("{}: {}", context, error) }
+    }
+
+    /// Recognises the failures SteamCMD reports in its output.
+    pub fn from_output(app_id: Option<u32>, output: &str) -> Option<Self> {
+        let app_id = app_id.unwrap_or_default();
+
+        if output.contains("Invalid Password") {
+            Some(SteamError::InvalidPassword)
+        } else if output.contains("Invalid Steam Guard code")
+            || output.contains("Invalid two-factor code")
+            || output.contains("Two-factor code mismatch")
+            || output.contains("Invalid Login Auth Code")
+        {
+            Some(SteamError::InvalidGuardCode)
+        } else if output.contains("Rate Limit Exceeded") {
+            Some(SteamError::RateLimited)
+        } else if output.contains("No subscription") {
+            Some(SteamError::NoSubscription { app_id })
+        } else if output.contains("Invalid Platform") {
+            // Updates that force a platform replace the host's with theirs
+            Some(SteamError::InvalidPlatform { app_id, platform: Platform::host().as_str().to_string(), bitness: None })
+        } else if output.contains("Not enough disk space")
+            || output.contains("Disk write failure")
+            || output.contains("state is 0x202")
+        {
+            Some(SteamError::DiskFull { app_id })
+        } else if output.contains("No Connection")
+            || output.contains("Connection to Steam servers failed")
+            || output.contains("Timeout")
+        {
+            Some(SteamError::NetworkUnavailable)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for SteamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SteamError::InvalidPassword => write!(f, "Invalid username or password"),
+            SteamError::SteamGuardRequired => write!(f, "Steam Guard code required"),
+            SteamError::InvalidGuardCode => write!(f, "Invalid Steam Guard code"),
+            SteamError::GuardChallengeNotFound { challenge_id } => {
+                write!(f, "Steam Guard challenge {} is no longer pending", challenge_id)
+            }
+            SteamError::GuardChallengeExpired { challenge_id } => {
+                write!(f, "Steam Guard challenge {} expired without a code", challenge_id)
+            }
+            SteamError::GuardChallengeCancelled { challenge_id } => {
+                write!(f, "Steam Guard challenge {} was cancelled", challenge_id)
+            }
+            SteamError::NoSubscription { app_id } => {
+                write!(f, "You don't have access to app {}", app_id)
+            }
+            SteamError::InvalidPlatform { app_id, platform, bitness: Some(bitness) } => {
+                write!(f, "App {} is not available for {} ({}-bit)", app_id, platform, bitness)
+            }
+            SteamError::InvalidPlatform { app_id, platform, bitness: None } => {
+                write!(f, "App {} is not available for {}", app_id, platform)
+            }
+            SteamError::InvalidBranchPassword { app_id, branch } => {
+                write!(f, "Steam rejected the password of branch {} of app {}", branch, app_id)
+            }
+            SteamError::DiskFull { app_id } => {
+                write!(f, "Not enough disk space to update app {}", app_id)
+            }
+            SteamError::RateLimited => write!(f, "Steam is rate limiting logins, try again later"),
+            SteamError::NetworkUnavailable => write!(f, "Could not connect to Steam"),
+            SteamError::AuthenticationFailed { .. } => {
+                write!(f, "Authentication failed. Please check your credentials and try again.")
+            }
+            SteamError::UpdateFailed { app_id, .. } => write!(f, "Update of app {} failed", app_id),
+            SteamError::SteamCmdMissing { path } => write!(f, "SteamCMD not found at {}", path),
+            SteamError::SteamCmdInstallFailed { reason } => {
+                write!(f, "Failed to install SteamCMD: {}", reason)
+            }
+            SteamError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "SteamCMD installer checksum {} does not match the pinned {}",
+                actual, expected
+            ),
+            SteamError::SteamCmdBusy => write!(f, "SteamCMD is busy with an update"),
+            SteamError::SteamCmdTimedOut { seconds } => {
+                write!(f, "SteamCMD did not respond within {} seconds", seconds)
+            }
+            SteamError::SessionExited => write!(f, "SteamCMD session exited unexpectedly"),
+            SteamError::CommandFailed { program, exit_code: Some(code), .. } => {
+                write!(f, "{} exited with code {}", program, code)
+            }
+            SteamError::CommandFailed { program, exit_code: None, .. } => {
+                write!(f, "{} was terminated", program)
+            }
+            SteamError::InstallDirNotFound { path } => {
+                write!(f, "Install directory {} does not exist", path)
+            }
+            SteamError::InstallDirNotWritable { path, reason } => {
+                write!(f, "Install directory {} is not writable: {}", path, reason)
+            }
+            SteamError::InsufficientDiskSpace { path, available, required } => write!(
+                f,
+                "Not enough free space in {}: {} bytes available, {} bytes required",
+                path, available, required
+            ),
+            SteamError::UpdateAlreadyRunning { app_id } => {
+                write!(f, "An update for app {} is already running", app_id)
+            }
+            SteamError::UpdateNotRunning { app_id } => {
+                write!(f, "No update is running for app {}", app_id)
+            }
+            SteamError::UpdateCancelled { app_id } => write!(f, "Update of app {} was cancelled", app_id),
+            SteamError::CredentialsNotFound { username } => {
+                write!(f, "No stored credentials for {}", username)
+            }
+            SteamError::JobNotFound { job_id } => write!(f, "Job {} not found", job_id),
+            SteamError::JobNotQueued { job_id } => write!(f, "Job {} is not waiting in the queue", job_id),
+            SteamError::VaultLocked => write!(f, "Credential vault is locked"),
+            SteamError::VaultNotInitialized => write!(f, "Credential vault is not set up"),
+            SteamError::InvalidMasterPassword => write!(f, "Invalid master password"),
+            SteamError::Crypto { reason } => write!(f, "{}", reason),
+            SteamError::DatabaseTooNew { version, supported } => write!(
+                f,
+                "steam.db has schema version {} but this app only supports up to version {}. \
+                 Please update the app.",
+                version, supported
+            ),
+            SteamError::Database { reason } => write!(f, "Database error: {}", reason),
+            SteamError::Io { reason }
+            | SteamError::InvalidInput { reason }
+            | SteamError::Unsupported { reason }
+            | SteamError::Internal { reason } => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SteamError {}
+
+impl Serialize for SteamError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("SteamError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("context", &self.context())?;
+        state.end()
+    }
+}
+
+impl From<rusqlite::Error> for SteamError {
+    fn from(error: rusqlite::Error) -> Self {
+        SteamError::Database { reason: error.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_code_message_and_context() {
+        let error = SteamError::InsufficientDiskSpace { path: "/srv/games".to_string(), available: 10, required: 20 };
+
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "code": "INSUFFICIENT_DISK_SPACE",
+                "message": error.to_string(),
+                "context": { "path": "/srv/games", "available": 10, "required": 20 },
+            })
+        );
+    }
+
+    #[test]
+    fn unit_variants_serialize_an_empty_context() {
+        assert_eq!(
+            serde_json::to_value(SteamError::SteamGuardRequired).unwrap(),
+            json!({ "code": "STEAM_GUARD_REQUIRED", "message": "Steam Guard code required", "context": {} })
+        );
+    }
+
+    #[test]
+    fn classifies_steamcmd_output() {
+        let cases = [
+            ("Error! App '740' state is 0x202 after update job.", SteamError::DiskFull { app_id: 740 }),
+            ("Error! App '740' Not enough disk space", SteamError::DiskFull { app_id: 740 }),
+            (
+                "Logging in user 'club' [U:1:90123456] to Steam Public...FAILED (Rate Limit Exceeded)",
+                SteamError::RateLimited,
+            ),
+            (
+                "Logging in user 'club' [U:1:90123456] to Steam Public...FAILED (Invalid Password)",
+                SteamError::InvalidPassword,
+            ),
+            ("Invalid Steam Guard code", SteamError::InvalidGuardCode),
+            ("Two-factor code mismatch", SteamError::InvalidGuardCode),
+            ("FAILED (Invalid Login Auth Code)", SteamError::InvalidGuardCode),
+            ("ERROR! Failed to install app '740' (No subscription)", SteamError::NoSubscription { app_id: 740 }),
+        ];
+
+        for (output, expected) in cases {
+            assert_eq!(SteamError::from_output(Some(740), output), Some(expected), "{}", output);
+        }
+    }
+
+    #[test]
+    fn leaves_unknown_output_unclassified() {
+        assert_eq!(SteamError::from_output(Some(740), "Success! App '740' fully installed."), None);
+        assert_eq!(SteamError::from_output(None, ""), None);
+        assert_eq!(
+            SteamError::from_output(None, "ERROR! Failed to install app '740' (No subscription)"),
+            Some(SteamError::NoSubscription { app_id: 0 })
+        );
+    }
+}
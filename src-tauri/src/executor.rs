@@ -0,0 +1,446 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use crate::accounts;
+use crate::error::SteamError;
+use crate::history::{self, RunReport};
+use crate::install_dirs;
+use crate::processes::ProcessRegistry;
+use crate::runner::{self, Limits, ProcessRunner, ProcessSpec, SystemRunner};
+use crate::steam::{self, Platform, UpdateOptions};
+
+/// Longest a custom command may run. Its output means nothing to us, so
+/// unlike SteamCMD it is not expected to keep printing.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(4 * 60 * 60);
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Executor] {}", format!($($arg)*));
+    }
+}
+
+/// The `Settings` record synced from the updateio API, which tells us how a game is updated.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutorSettings {
+    pub id: String,
+    pub app_id: String,
+    pub game_id: String,
+    pub executor_name: String,
+    pub update_command: String,
+}
+
+/// Values available to an executor, and to `{placeholders}` in custom commands.
+#[derive(Debug, Clone, Default)]
+pub struct UpdateContext {
+    pub app_id: String,
+    pub game_id: String,
+    pub install_dir: Option<String>,
+    pub account: Option<String>,
+}
+
+#[async_trait]
+pub trait Executor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn run(&self, app: AppHandle, command: &str, context: &UpdateContext) -> Result<String, SteamError>;
+}
+
+/// Runs SteamCMD; the update command is a Steam app id or a `+app_update <id>` script.
+pub struct SteamCmdExecutor;
+
+#[async_trait]
+impl Executor for SteamCmdExecutor {
+    fn name(&self) -> &'static str {
+        "steam"
+    }
+
+    async fn run(&self, app: AppHandle, command: &str, context: &UpdateContext) -> Result<String, SteamError> {
+        let app_id = parse_steam_app_id(command)?;
+        let options = parse_steam_options(command);
+
+        // Same precedence as the queue: explicit account, mapped account, anonymous
+        let credentials = match &context.account {
+            Some(username) => Some(accounts::load_account(&app, username)?),
+            None => accounts::credentials_for_app(&app, app_id)?,
+        };
+
+        match credentials {
+            Some(credentials) => steam::update_game_authenticated(app, app_id, credentials, options).await,
+            None => steam::update_game(app, app_id, options).await,
+        }
+    }
+}
+
+/// Runs an arbitrary program, for launchers that are not Steam.
+///
+/// The command is split into arguments the way a shell would split quoted
+/// words, but it is never passed to a shell: placeholders are substituted
+/// inside each argument, so a value containing spaces or metacharacters
+/// stays a single argument.
+pub struct CustomCommandExecutor;
+
+#[async_trait]
+impl Executor for CustomCommandExecutor {
+    fn name(&self) -> &'static str {
+        "custom"
+    }
+
+    async fn run(&self, app: AppHandle, command: &str, context: &UpdateContext) -> Result<String, SteamError> {
+        // Only a numeric app id has a mapped account, can be cancelled from
+        // the UI and shows up in history
+        let app_id = context.app_id.parse().ok();
+
+        // Without an explicit account `{account}` is the one mapped to the app, if any
+        let mut context = context.clone();
+        if let (None, Some(app_id)) = (&context.account, app_id) {
+            context.account = accounts::credentials_for_app(&app, app_id)?.map(|credentials| credentials.username);
+        }
+
+        let args = split_command(command)?
+            .iter()
+            .map(|arg| render(arg, &context))
+            .collect::<Result<Vec<_>, SteamError>>()?;
+        let (program, args) = args.split_first()
+            .ok_or_else(|| SteamError::InvalidInput { reason: "Update command is empty".to_string() })?;
+        let spec = ProcessSpec {
+            program: PathBuf::from(program),
+            args: args.to_vec(),
+            current_dir: context.install_dir.as_ref().map(PathBuf::from),
+        };
+
+        let attempt = app_id.map(|app_id| history::start(app_id, context.account.as_deref(), &UpdateOptions::default()));
+        let report = RunReport::default();
+        let result = run_command(&SystemRunner, &app.state::<ProcessRegistry>(), app_id, &spec, &report).await;
+        if let Some(attempt) = attempt {
+            history::finish(attempt, &result, &report);
+        }
+        result
+    }
+}
+
+/// Runs a custom command to the end, registered under `app_id` so it can be
+/// paused and cancelled like SteamCMD.
+async fn run_command(
+    runner: &dyn ProcessRunner,
+    registry: &ProcessRegistry,
+    app_id: Option<u32>,
+    spec: &ProcessSpec,
+    report: &RunReport
+) -> Result<String, SteamError> {
+    let program = spec.program.display().to_string();
+    dev_log!("Running {} with {} argument(s)", program, spec.args.len());
+    let mut spawned = runner.spawn(spec)?;
+
+    let limits = Limits { timeout: Some(COMMAND_TIMEOUT), ..Default::default() };
+    let registration = match (app_id, spawned.child.id()) {
+        (Some(app_id), Some(pid)) => match registry.register(app_id, pid, limits.cancel.clone()) {
+            Ok(registration) => Some((app_id, registration)),
+            Err(e) => {
+                spawned.child.kill().await;
+                return Err(e);
+            }
+        },
+        _ => None,
+    };
+
+    let output = runner::collect(spawned, &limits, |_| {}).await?;
+    report.set_exit_code(output.exit_code);
+
+    if let Some((app_id, registration)) = registration {
+        if registration.finish() {
+            return Err(SteamError::UpdateCancelled { app_id });
+        }
+    }
+
+    if output.success() {
+        Ok(format!("Successfully ran {}", program))
+    } else {
+        Err(SteamError::CommandFailed {
+            program,
+            exit_code: output.exit_code,
+            output: format!("{}{}", output.stdout, output.stderr),
+        })
+    }
+}
+
+/// Picks the executor named by a Settings record.
+pub(crate) fn for_name(name: &str) -> Result<Box<dyn Executor>, SteamError> {
+    match name.trim().to_lowercase().as_str() {
+        "steam" | "steamcmd" => Ok(Box::new(SteamCmdExecutor)),
+        "custom" | "command" => Ok(Box::new(CustomCommandExecutor)),
+        other => Err(SteamError::Unsupported { reason: format!("Unknown executor {:?}", other) }),
+    }
+}
+
+fn parse_steam_app_id(command: &str) -> Result<u32, SteamError> {
+    let command = command.trim();
+    if let Ok(app_id) = command.parse() {
+        return Ok(app_id);
+    }
+
+    let words: Vec<&str> = command.split_whitespace().collect();
+    words.windows(2)
+        .find(|pair| pair[0].trim_start_matches('+') == "app_update")
+        .and_then(|pair| pair[1].parse().ok())
+        .ok_or_else(|| SteamError::InvalidInput {
+            reason: format!("Update command {:?} does not name a Steam app", command),
+        })
+}
+
+/// Reads update options out of a `+app_update` script; a bare app id has none.
+///
+/// `validate` asks for validation, `+@sSteamCmdForcePlatformType <platform>`
+/// and `+@sSteamCmdForcePlatformBitness <bits>` force the target platform.
+fn parse_steam_options(command: &str) -> UpdateOptions {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let setting = |name: &str| {
+        words.windows(2)
+            .find(|pair| pair[0].trim_start_matches('+') == name)
+            .map(|pair| pair[1])
+    };
+
+    UpdateOptions {
+        validate: words.contains(&"validate"),
+        platform: setting("@sSteamCmdForcePlatformType").and_then(Platform::parse),
+        bitness: setting("@sSteamCmdForcePlatformBitness").and_then(|bitness| bitness.parse().ok()),
+    }
+}
+
+/// Splits a command line into arguments, honouring single and double quotes.
+///
+/// Backslashes are kept literally so Windows paths need no escaping.
+fn split_command(command: &str) -> Result<Vec<String>, SteamError> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote = None;
+
+    for c in command.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            None if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            None => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(SteamError::InvalidInput { reason: "Update command has an unclosed quote".to_string() });
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// Substitutes `{app_id}`, `{game_id}`, `{install_dir}` and `{account}` in one argument.
+fn render(arg: &str, context: &UpdateContext) -> Result<String, SteamError> {
+    let mut rendered = String::new();
+    let mut rest = arg;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .ok_or_else(|| SteamError::InvalidInput { reason: format!("Unclosed placeholder in {:?}", arg) })?;
+        let name = &rest[start + 1..start + end];
+
+        let value = match name {
+            "app_id" => Some(context.app_id.as_str()),
+            "game_id" => Some(context.game_id.as_str()),
+            "install_dir" => context.install_dir.as_deref(),
+            "account" => context.account.as_deref(),
+            _ => return Err(SteamError::InvalidInput { reason: format!("Unknown placeholder {{{}}}", name) }),
+        };
+        let value = value.ok_or_else(|| SteamError::InvalidInput {
+            reason: format!("Placeholder {{{}}} has no value", name),
+        })?;
+
+        rendered.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+fn uses_placeholder(command: &str, name: &str) -> bool {
+    command.contains(&format!("{{{}}}", name))
+}
+
+/// Runs the update described by a Settings record with the executor it names.
+pub(crate) async fn run_update(
+    app: AppHandle,
+    settings: ExecutorSettings,
+    username: Option<String>
+) -> Result<String, SteamError> {
+    let executor = for_name(&settings.executor_name)?;
+    // Steam apps get the same directory SteamCMD would install them into, but
+    // only commands that install there need it to exist with room to spare
+    let install_dir = if uses_placeholder(&settings.update_command, "install_dir") {
+        match settings.app_id.parse() {
+            Ok(app_id) => install_dirs::prepare(app_id)?,
+            Err(_) => install_dirs::default_root()?,
+        }
+    } else {
+        None
+    };
+    let context = UpdateContext {
+        app_id: settings.app_id,
+        game_id: settings.game_id,
+        install_dir: install_dir.map(|path| path.display().to_string()),
+        account: username,
+    };
+
+    dev_log!("Updating game {} with the {} executor", context.game_id, executor.name());
+    executor.run(app, &settings.update_command, &context).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::fake::FakeRunner;
+    use super::*;
+
+    fn context() -> UpdateContext {
+        UpdateContext {
+            app_id: "740".to_string(),
+            game_id: "cs2".to_string(),
+            install_dir: Some("/srv/games/Counter Strike".to_string()),
+            account: None,
+        }
+    }
+
+    #[test]
+    fn finds_placeholders_in_a_command() {
+        assert!(uses_placeholder("launcher --dir {install_dir} {app_id}", "install_dir"));
+        assert!(uses_placeholder(r#"launcher "--dir={install_dir}/bin""#, "install_dir"));
+        assert!(!uses_placeholder("launcher update {app_id}", "install_dir"));
+        assert!(!uses_placeholder("740", "install_dir"));
+    }
+
+    #[test]
+    fn splits_on_whitespace_outside_quotes() {
+        assert_eq!(
+            split_command(r#"launcher  update "My Game" 'two words' --flag"#).unwrap(),
+            vec!["launcher", "update", "My Game", "two words", "--flag"],
+        );
+        assert_eq!(split_command(r#"launcher --name="a b""#).unwrap(), vec!["launcher", "--name=a b"]);
+        assert_eq!(split_command(r#"launcher "" x"#).unwrap(), vec!["launcher", "", "x"]);
+        assert!(split_command("   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_backslashes() {
+        assert_eq!(
+            split_command(r#"C:\Games\launcher.exe "C:\Program Files\Game\""#).unwrap(),
+            vec![r"C:\Games\launcher.exe", r"C:\Program Files\Game\"],
+        );
+    }
+
+    #[test]
+    fn rejects_an_unclosed_quote() {
+        assert!(matches!(split_command(r#"launcher "My Game"#), Err(SteamError::InvalidInput { .. })));
+        assert!(matches!(split_command("launcher 'x"), Err(SteamError::InvalidInput { .. })));
+    }
+
+    #[test]
+    fn renders_placeholders_inside_an_argument() {
+        let context = context();
+
+        assert_eq!(render("--app={app_id}/{game_id}", &context).unwrap(), "--app=740/cs2");
+        assert_eq!(render("plain", &context).unwrap(), "plain");
+    }
+
+    #[test]
+    fn a_value_with_spaces_stays_one_argument() {
+        let args = split_command("launcher --dir {install_dir} {app_id}").unwrap()
+            .iter()
+            .map(|arg| render(arg, &context()))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(args, vec!["launcher", "--dir", "/srv/games/Counter Strike", "740"]);
+    }
+
+    #[test]
+    fn rejects_unknown_unclosed_and_missing_placeholders() {
+        let context = context();
+
+        assert!(matches!(render("{password}", &context), Err(SteamError::InvalidInput { .. })));
+        assert!(matches!(render("{app_id", &context), Err(SteamError::InvalidInput { .. })));
+        assert!(matches!(render("--user={account}", &context), Err(SteamError::InvalidInput { .. })));
+    }
+
+    fn launcher() -> ProcessSpec {
+        ProcessSpec { program: PathBuf::from("launcher"), args: vec!["update".to_string()], current_dir: None }
+    }
+
+    #[tokio::test]
+    async fn a_finished_command_is_no_longer_registered() {
+        let runner = FakeRunner::new(&["< Up to date\n= 0\n"]);
+        let registry = ProcessRegistry::default();
+        let report = RunReport::default();
+
+        let result = run_command(&runner, &registry, Some(740), &launcher(), &report).await;
+
+        assert_eq!(result.unwrap(), "Successfully ran launcher");
+        assert!(registry.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_failed_command_keeps_its_output() {
+        let runner = FakeRunner::new(&["< Patch server unreachable\n= 3\n"]);
+        let registry = ProcessRegistry::default();
+
+        let result = run_command(&runner, &registry, Some(740), &launcher(), &RunReport::default()).await;
+
+        match result {
+            Err(SteamError::CommandFailed { program, exit_code, output }) => {
+                assert_eq!(program, "launcher");
+                assert_eq!(exit_code, Some(3));
+                assert_eq!(output, "Patch server unreachable\n");
+            }
+            other => panic!("Expected CommandFailed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_the_app_id_from_a_bare_id_or_a_script() {
+        assert_eq!(parse_steam_app_id(" 740 ").unwrap(), 740);
+        assert_eq!(parse_steam_app_id("+login anonymous +app_update 740 validate +quit").unwrap(), 740);
+        assert_eq!(parse_steam_app_id("app_update 90").unwrap(), 90);
+        assert!(parse_steam_app_id("+app_update cs2").is_err());
+        assert!(parse_steam_app_id("+login anonymous +quit").is_err());
+    }
+
+    #[test]
+    fn reads_update_options_from_a_script() {
+        assert_eq!(parse_steam_options("740"), UpdateOptions::default());
+        assert_eq!(
+            parse_steam_options(
+                "+@sSteamCmdForcePlatformType windows +@sSteamCmdForcePlatformBitness 64 +app_update 740 validate"
+            ),
+            UpdateOptions { validate: true, platform: Some(Platform::Windows), bitness: Some(64) },
+        );
+        assert_eq!(
+            parse_steam_options("+@sSteamCmdForcePlatformType amiga +app_update 740"),
+            UpdateOptions::default(),
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::oneshot;
+use crate::error::SteamError;
+use crate::queue::now;
+
+/// How long a challenge waits for the user before the login is abandoned.
+const CHALLENGE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[SteamGuard] {}", format!($($arg)*));
+    }
+}
+
+/// Where the Steam Guard code comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardKind {
+    /// A code mailed to the account's address.
+    Email,
+    /// A code from the Steam mobile authenticator.
+    Mobile,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GuardChallenge {
+    pub id: u64,
+    pub username: String,
+    pub kind: GuardKind,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+struct Pending {
+    challenge: GuardChallenge,
+    /// `None` means the user cancelled.
+    responder: oneshot::Sender<Option<String>>,
+}
+
+/// Hands Steam Guard prompts to the frontend and routes the answers back
+/// to the login that is waiting for them.
+#[derive(Default)]
+pub struct GuardBroker {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<u64, Pending>>,
+}
+
+impl GuardBroker {
+    /// Registers a challenge for `username`; it is withdrawn again when the
+    /// returned `Challenge` is dropped, answered or not.
+    fn open(&self, username: &str, kind: GuardKind) -> Result<Challenge<'_>, SteamError> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let created_at = now();
+        let challenge = GuardChallenge {
+            id,
+            username: username.to_string(),
+            kind,
+            created_at,
+            expires_at: created_at + CHALLENGE_TIMEOUT.as_secs() as i64,
+        };
+
+        let (responder, response) = oneshot::channel();
+        self.pending.lock()
+            .map_err(|e| SteamError::internal("Failed to lock Steam Guard challenges", e))?
+            .insert(id, Pending { challenge: challenge.clone(), responder });
+        Ok(Challenge { broker: self, challenge, response })
+    }
+
+    fn submit(&self, challenge_id: u64, code: &str) -> Result<(), SteamError> {
+        let code = code.trim().to_uppercase();
+        if code.is_empty() {
+            return Err(SteamError::InvalidInput { reason: "Steam Guard code must not be empty".to_string() });
+        }
+
+        let pending = self.remove(challenge_id)
+            .ok_or(SteamError::GuardChallengeNotFound { challenge_id })?;
+        pending.responder.send(Some(code))
+            .map_err(|_| SteamError::GuardChallengeNotFound { challenge_id })
+    }
+
+    fn cancel(&self, challenge_id: u64) -> Result<(), SteamError> {
+        let pending = self.remove(challenge_id)
+            .ok_or(SteamError::GuardChallengeNotFound { challenge_id })?;
+        let _ = pending.responder.send(None);
+        Ok(())
+    }
+
+    fn remove(&self, id: u64) -> Option<Pending> {
+        self.pending.lock().ok()?.remove(&id)
+    }
+
+    fn list(&self) -> Vec<GuardChallenge> {
+        let mut challenges: Vec<GuardChallenge> = self.pending.lock()
+            .map(|pending| pending.values().map(|pending| pending.challenge.clone()).collect())
+            .unwrap_or_default();
+        challenges.sort_by_key(|challenge| challenge.id);
+        challenges
+    }
+}
+
+/// A challenge waiting for its answer.
+struct Challenge<'a> {
+    broker: &'a GuardBroker,
+    challenge: GuardChallenge,
+    response: oneshot::Receiver<Option<String>>,
+}
+
+impl Challenge<'_> {
+    /// Waits for `submit` or `cancel`, giving up after `CHALLENGE_TIMEOUT`.
+    async fn wait(mut self) -> Result<String, SteamError> {
+        let id = self.challenge.id;
+        match tokio::time::timeout(CHALLENGE_TIMEOUT, &mut self.response).await {
+            Ok(Ok(Some(code))) => Ok(code),
+            Ok(Ok(None)) | Ok(Err(_)) => Err(SteamError::GuardChallengeCancelled { challenge_id: id }),
+            Err(_) => Err(SteamError::GuardChallengeExpired { challenge_id: id }),
+        }
+    }
+}
+
+impl Drop for Challenge<'_> {
+    fn drop(&mut self) {
+        self.broker.remove(self.challenge.id);
+    }
+}
+
+/// Tells the frontend a challenge is gone, however its wait ended.
+struct Resolved<'a> {
+    app: &'a AppHandle,
+    id: u64,
+}
+
+impl Drop for Resolved<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.app.emit("steam-guard-resolved", self.id) {
+            dev_log!("Failed to emit Steam Guard event: {}", e);
+        }
+    }
+}
+
+/// Asks the user for a code and waits for `submit_2fa_code` or `cancel_2fa_challenge`.
+///
+/// Dropping the returned future, e.g. when the update is cancelled, withdraws
+/// the challenge.
+pub(crate) async fn request_code(app: &AppHandle, username: &str, kind: GuardKind) -> Result<String, SteamError> {
+    let broker = app.state::<GuardBroker>();
+    let challenge = broker.open(username, kind)?;
+    let id = challenge.challenge.id;
+
+    dev_log!("Waiting for {:?} code for {} (challenge {})", kind, username, id);
+    if let Err(e) = app.emit("steam-guard-required", challenge.challenge.clone()) {
+        dev_log!("Failed to emit Steam Guard event: {}", e);
+    }
+
+    let _resolved = Resolved { app, id };
+    challenge.wait().await
+}
+
+pub(crate) async fn submit_code(app: AppHandle, challenge_id: u64, code: String) -> Result<(), SteamError> {
+    app.state::<GuardBroker>().submit(challenge_id, &code)
+}
+
+pub(crate) async fn cancel_challenge(app: AppHandle, challenge_id: u64) -> Result<(), SteamError> {
+    app.state::<GuardBroker>().cancel(challenge_id)
+}
+
+pub(crate) async fn list_challenges(app: AppHandle) -> Result<Vec<GuardChallenge>, SteamError> {
+    Ok(app.state::<GuardBroker>().list())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(broker: &GuardBroker) -> Vec<u64> {
+        broker.list().iter().map(|challenge| challenge.id).collect()
+    }
+
+    #[tokio::test]
+    async fn a_submitted_code_answers_the_challenge() {
+        let broker = GuardBroker::default();
+        let challenge = broker.open("club", GuardKind::Email).unwrap();
+        let id = challenge.challenge.id;
+
+        let listed = broker.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].id, listed[0].username.as_str(), listed[0].kind), (id, "club", GuardKind::Email));
+        assert_eq!(listed[0].expires_at - listed[0].created_at, 300);
+
+        broker.submit(id, " f4k3c\n").unwrap();
+
+        assert_eq!(challenge.wait().await.unwrap(), "F4K3C");
+        assert!(broker.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn an_empty_code_keeps_the_challenge_open() {
+        let broker = GuardBroker::default();
+        let challenge = broker.open("club", GuardKind::Mobile).unwrap();
+        let id = challenge.challenge.id;
+
+        assert!(matches!(broker.submit(id, "  "), Err(SteamError::InvalidInput { .. })));
+        assert_eq!(ids(&broker), vec![id]);
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_challenge_fails_the_wait() {
+        let broker = GuardBroker::default();
+        let first = broker.open("club", GuardKind::Email).unwrap();
+        let second = broker.open("other", GuardKind::Mobile).unwrap();
+        let (first_id, second_id) = (first.challenge.id, second.challenge.id);
+        assert_eq!(ids(&broker), vec![first_id, second_id]);
+
+        broker.cancel(first_id).unwrap();
+
+        assert!(matches!(
+            first.wait().await,
+            Err(SteamError::GuardChallengeCancelled { challenge_id }) if challenge_id == first_id
+        ));
+        assert_eq!(ids(&broker), vec![second_id]);
+    }
+
+    #[test]
+    fn unknown_challenges_are_reported() {
+        let broker = GuardBroker::default();
+
+        assert!(matches!(broker.submit(42, "F4K3C"), Err(SteamError::GuardChallengeNotFound { challenge_id: 42 })));
+        assert!(matches!(broker.cancel(42), Err(SteamError::GuardChallengeNotFound { challenge_id: 42 })));
+
+        let challenge = broker.open("club", GuardKind::Email).unwrap();
+        let id = challenge.challenge.id;
+        broker.submit(id, "F4K3C").unwrap();
+        assert!(matches!(broker.submit(id, "F4K3C"), Err(SteamError::GuardChallengeNotFound { .. })));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn an_unanswered_challenge_expires_after_five_minutes() {
+        let broker = GuardBroker::default();
+        let challenge = broker.open("club", GuardKind::Email).unwrap();
+        let id = challenge.challenge.id;
+        let started = tokio::time::Instant::now();
+
+        let result = challenge.wait().await;
+
+        assert!(matches!(result, Err(SteamError::GuardChallengeExpired { challenge_id }) if challenge_id == id));
+        assert_eq!(started.elapsed(), CHALLENGE_TIMEOUT);
+        assert!(broker.list().is_empty());
+    }
+
+    #[test]
+    fn dropping_a_challenge_withdraws_it() {
+        let broker = GuardBroker::default();
+        let challenge = broker.open("club", GuardKind::Email).unwrap();
+        let id = challenge.challenge.id;
+
+        drop(challenge);
+
+        assert!(broker.list().is_empty());
+        assert!(matches!(broker.submit(id, "F4K3C"), Err(SteamError::GuardChallengeNotFound { .. })));
+    }
+}
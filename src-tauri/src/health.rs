@@ -0,0 +1,191 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Serialize;
+use tauri::AppHandle;
+use crate::error::SteamError;
+use crate::installer;
+use crate::processes;
+use crate::runner::{self, Limits, ProcessRunner, ProcessSpec, SystemRunner};
+use crate::steam;
+
+/// A first run may download the whole SteamCMD client before it quits.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(300);
+/// Game content, kept by `repair_steamcmd`.
+const STEAMAPPS_DIR: &str = "steamapps";
+/// Directories SteamCMD keeps its own packages, runtime and caches in.
+///
+/// Anything else, `steamapps` above all, may hold game content far too large
+/// to walk on every health check.
+const PACKAGE_DIRS: &[&str] = &[
+    "appcache",
+    "config",
+    "depotcache",
+    "dumps",
+    "linux32",
+    "linux64",
+    "logs",
+    "package",
+    "public",
+    "siteserverui",
+];
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Health] {}", format!($($arg)*));
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirSize {
+    pub name: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SteamCmdHealth {
+    pub installed: bool,
+    pub healthy: bool,
+    pub version: Option<String>,
+    /// SteamCMD downloaded a newer client during the check.
+    pub self_updated: bool,
+    pub exit_code: Option<i32>,
+    pub package_dirs: Vec<DirSize>,
+    pub output: String,
+}
+
+/// Runs SteamCMD with `+quit` and reports whether it bootstrapped cleanly.
+pub(crate) async fn steamcmd_health(app: AppHandle) -> Result<SteamCmdHealth, SteamError> {
+    processes::ensure_steamcmd_idle(&app).await?;
+
+    let steamcmd_dir = steam::get_steamcmd_dir(&app)?;
+    let steamcmd_path = steam::get_steamcmd_path(&app)?;
+
+    if !steamcmd_path.exists() {
+        return Ok(SteamCmdHealth {
+            installed: false,
+            healthy: false,
+            version: None,
+            self_updated: false,
+            exit_code: None,
+            package_dirs: measure_package_dirs(steamcmd_dir).await,
+            output: String::new(),
+        });
+    }
+
+    let (exit_code, output) = run_quit(&steamcmd_path).await?;
+
+    let version = parse_version(&output);
+    let healthy = exit_code == Some(0) && output.contains("Loading Steam API...OK");
+    dev_log!("SteamCMD healthy: {}, version: {:?}", healthy, version);
+
+    Ok(SteamCmdHealth {
+        installed: true,
+        healthy,
+        version,
+        self_updated: output.contains("Update complete, launching"),
+        exit_code,
+        package_dirs: measure_package_dirs(steamcmd_dir).await,
+        output,
+    })
+}
+
+async fn run_quit(steamcmd_path: &Path) -> Result<(Option<i32>, String), SteamError> {
+    let spawned = SystemRunner.spawn(&ProcessSpec::new(steamcmd_path).arg("+quit"))?;
+    let limits = Limits { timeout: Some(HEALTH_CHECK_TIMEOUT), ..Default::default() };
+    let output = runner::collect(spawned, &limits, |_| {}).await?;
+    Ok((output.exit_code, output.stdout))
+}
+
+/// Reads the build from `Steam Console Client (c) Valve Corporation - version 1700000000`.
+fn parse_version(output: &str) -> Option<String> {
+    output.lines()
+        .filter(|line| line.contains("Steam Console Client"))
+        .find_map(|line| line.rsplit_once("version ").map(|(_, version)| version.trim().to_string()))
+        .filter(|version| !version.is_empty())
+}
+
+/// `package_dirs` walks every file, so it runs on the blocking pool.
+async fn measure_package_dirs(steamcmd_dir: PathBuf) -> Vec<DirSize> {
+    tauri::async_runtime::spawn_blocking(move || package_dirs(&steamcmd_dir))
+        .await
+        .unwrap_or_default()
+}
+
+/// Sizes of the `PACKAGE_DIRS` present in the SteamCMD directory.
+fn package_dirs(steamcmd_dir: &Path) -> Vec<DirSize> {
+    PACKAGE_DIRS.iter()
+        .map(|name| (name, steamcmd_dir.join(name)))
+        .filter(|(_, path)| path.is_dir())
+        .map(|(name, path)| DirSize {
+            name: name.to_string(),
+            bytes: dir_size(&path),
+        })
+        .collect()
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(kind) if kind.is_file() => entry.metadata().map(|meta| meta.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Installs SteamCMD again over a clean directory, then re-runs the health check.
+///
+/// `steamapps` is kept so games installed without an install directory survive.
+pub(crate) async fn repair_steamcmd(app: AppHandle) -> Result<SteamCmdHealth, SteamError> {
+    processes::ensure_steamcmd_idle(&app).await?;
+
+    dev_log!("Reinstalling SteamCMD into {:?}", steam::get_steamcmd_dir(&app)?);
+    installer::reinstall(&app, &[STEAMAPPS_DIR]).await?;
+    steamcmd_health(app).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_version_from_the_banner() {
+        // As printed by fake-steamcmd
+        let output = "Redirecting stderr to '/opt/steamcmd/logs/stderr.txt'\n\
+            [  0%] Checking for available updates...\n\
+            [----] Verifying installation...\n\
+            Steam Console Client (c) Valve Corporation - version 1716584665\n\
+            -- type 'quit' to exit --\n\
+            Loading Steam API...OK\n";
+
+        assert_eq!(parse_version(output), Some("1716584665".to_string()));
+        assert_eq!(parse_version("Loading Steam API...OK"), None);
+        assert_eq!(parse_version("Steam Console Client (c) Valve Corporation - version "), None);
+    }
+
+    #[test]
+    fn only_measures_steamcmd_directories() {
+        let dir = std::env::temp_dir().join(format!("updateio-health-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("package")).unwrap();
+        fs::create_dir_all(dir.join("public/nested")).unwrap();
+        fs::create_dir_all(dir.join("steamapps/common/Game")).unwrap();
+        fs::write(dir.join("package/steam_cmd_linux.installed"), [0u8; 10]).unwrap();
+        fs::write(dir.join("public/nested/steamconsole.so"), [0u8; 32]).unwrap();
+        fs::write(dir.join("steamapps/common/Game/content.pak"), [0u8; 64]).unwrap();
+        fs::write(dir.join("linux32"), "not a directory").unwrap();
+
+        let sizes: Vec<(String, u64)> = package_dirs(&dir).into_iter().map(|dir| (dir.name, dir.bytes)).collect();
+
+        assert_eq!(sizes, vec![("package".to_string(), 10), ("public".to_string(), 32)]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
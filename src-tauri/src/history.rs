@@ -0,0 +1,298 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::error::SteamError;
+use crate::queue::now;
+use crate::steam::{Platform, UpdateOptions};
+
+/// Lines of output kept with a failed attempt.
+const OUTPUT_TAIL_LINES: usize = 50;
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[History] {}", format!($($arg)*));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateResult {
+    Running,
+    Success,
+    Failed,
+    Cancelled,
+}
+
+impl UpdateResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpdateResult::Running => "running",
+            UpdateResult::Success => "success",
+            UpdateResult::Failed => "failed",
+            UpdateResult::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "running" => Some(UpdateResult::Running),
+            "success" => Some(UpdateResult::Success),
+            "failed" => Some(UpdateResult::Failed),
+            "cancelled" => Some(UpdateResult::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub app_id: u32,
+    pub username: Option<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub result: UpdateResult,
+    pub bytes_downloaded: u64,
+    pub exit_code: Option<i32>,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+    /// The platform the depots were downloaded for; unknown for attempts
+    /// recorded before platforms could be forced, which were the host's.
+    pub platform: Option<Platform>,
+    pub bitness: Option<u32>,
+    /// The last lines printed before the attempt failed, when the error has any.
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// What a SteamCMD run reported besides its result, filled in while it runs.
+#[derive(Debug, Default)]
+pub struct RunReport {
+    bytes_downloaded: AtomicU64,
+    exit_code: Mutex<Option<i32>>,
+    corrupted_files: AtomicU64,
+}
+
+impl RunReport {
+    /// Records the byte counter from a progress line; SteamCMD restarts it per phase.
+    pub fn observe_bytes(&self, bytes: u64) {
+        self.bytes_downloaded.fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    /// Counts files a validating run found damaged.
+    pub fn observe_corrupted_files(&self, files: u64) {
+        self.corrupted_files.fetch_add(files, Ordering::Relaxed);
+    }
+
+    pub fn corrupted_files(&self) -> u64 {
+        self.corrupted_files.load(Ordering::Relaxed)
+    }
+
+    pub fn set_exit_code(&self, code: Option<i32>) {
+        if let Ok(mut exit_code) = self.exit_code.lock() {
+            *exit_code = code;
+        }
+    }
+}
+
+/// How an attempt ended, as written to the history table.
+pub struct Outcome<'a> {
+    pub result: UpdateResult,
+    pub bytes_downloaded: u64,
+    pub exit_code: Option<i32>,
+    pub error_code: Option<&'a str>,
+    pub error_message: Option<&'a str>,
+    pub output: Option<&'a str>,
+}
+
+/// An update attempt that has been written to the history table.
+pub struct Attempt {
+    id: Option<i64>,
+}
+
+/// Records the start of an update attempt.
+///
+/// History must never stop an update, so failures are only logged.
+pub(crate) fn start(app_id: u32, username: Option<&str>, options: &UpdateOptions) -> Attempt {
+    match db::insert_history(app_id, username, options.target_platform(), options.bitness, now()) {
+        Ok(id) => Attempt { id: Some(id) },
+        Err(e) => {
+            dev_log!("Failed to record update start for app {}: {}", app_id, e);
+            Attempt { id: None }
+        }
+    }
+}
+
+pub(crate) fn finish(attempt: Attempt, result: &Result<String, SteamError>, report: &RunReport) {
+    let Some(id) = attempt.id else {
+        return;
+    };
+
+    let (status, error_code, error_message) = match result {
+        Ok(_) => (UpdateResult::Success, None, None),
+        Err(e @ SteamError::UpdateCancelled { .. }) => {
+            (UpdateResult::Cancelled, Some(e.code()), Some(e.to_string()))
+        }
+        Err(e) => (UpdateResult::Failed, Some(e.code()), Some(e.to_string())),
+    };
+    let output = result.as_ref().err().and_then(failure_output);
+    let outcome = Outcome {
+        result: status,
+        bytes_downloaded: report.bytes_downloaded.load(Ordering::Relaxed),
+        exit_code: report.exit_code.lock().ok().and_then(|code| *code),
+        error_code,
+        error_message: error_message.as_deref(),
+        output: output.as_deref(),
+    };
+
+    if let Err(e) = db::finish_history(id, now(), &outcome) {
+        dev_log!("Failed to record update result for attempt {}: {}", id, e);
+    }
+}
+
+/// The tail of what SteamCMD or a custom command printed, for errors that carry it.
+fn failure_output(error: &SteamError) -> Option<String> {
+    let output = match error {
+        SteamError::UpdateFailed { output, .. }
+        | SteamError::AuthenticationFailed { output }
+        | SteamError::CommandFailed { output, .. } => output,
+        _ => return None,
+    };
+
+    let lines: Vec<&str> = output.lines().collect();
+    let tail = lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("\n");
+    (!tail.trim().is_empty()).then_some(tail)
+}
+
+/// What the last successful update of `app_id` downloaded, used to size an
+/// install SteamCMD has no manifest for.
+pub(crate) fn last_download(app_id: u32) -> Option<u64> {
+    match db::last_download(app_id) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            dev_log!("Failed to read download sizes for app {}: {}", app_id, e);
+            None
+        }
+    }
+}
+
+/// Marks attempts left running by a crash as failed.
+pub(crate) fn close_interrupted() -> Result<(), SteamError> {
+    db::close_interrupted_history(now())?;
+    Ok(())
+}
+
+pub(crate) async fn list_history(app_id: Option<u32>, limit: Option<u32>) -> Result<Vec<HistoryEntry>, SteamError> {
+    Ok(db::list_history(app_id, limit)?)
+}
+
+pub(crate) async fn export_history(format: ExportFormat, app_id: Option<u32>) -> Result<String, SteamError> {
+    let entries = list_history(app_id, None).await?;
+
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(&entries)
+            .map_err(|e| SteamError::internal("Failed to export update history", e)),
+        ExportFormat::Csv => Ok(to_csv(&entries)),
+    }
+}
+
+fn to_csv(entries: &[HistoryEntry]) -> String {
+    let mut csv = String::from(
+        "id,app_id,username,started_at,finished_at,result,bytes_downloaded,exit_code,error_code,error_message,\
+         platform,bitness,output\n"
+    );
+
+    for entry in entries {
+        let fields = [
+            entry.id.to_string(),
+            entry.app_id.to_string(),
+            entry.username.clone().unwrap_or_default(),
+            entry.started_at.to_string(),
+            entry.finished_at.map(|t| t.to_string()).unwrap_or_default(),
+            entry.result.as_str().to_string(),
+            entry.bytes_downloaded.to_string(),
+            entry.exit_code.map(|c| c.to_string()).unwrap_or_default(),
+            entry.error_code.clone().unwrap_or_default(),
+            entry.error_message.clone().unwrap_or_default(),
+            entry.platform.map(|platform| platform.as_str().to_string()).unwrap_or_default(),
+            entry.bitness.map(|bitness| bitness.to_string()).unwrap_or_default(),
+            entry.output.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_tail_of_a_failed_update() {
+        let output: String = (1..=80).map(|line| format!("line {}\n", line)).collect();
+
+        let tail = failure_output(&SteamError::UpdateFailed { app_id: 740, output }).unwrap();
+
+        assert_eq!(tail.lines().count(), OUTPUT_TAIL_LINES);
+        assert!(tail.starts_with("line 31\n"));
+        assert!(tail.ends_with("line 80"));
+    }
+
+    #[test]
+    fn only_errors_with_output_have_any() {
+        assert_eq!(failure_output(&SteamError::UpdateCancelled { app_id: 740 }), None);
+        assert_eq!(failure_output(&SteamError::UpdateFailed { app_id: 740, output: "\n".to_string() }), None);
+        assert_eq!(
+            failure_output(&SteamError::CommandFailed {
+                program: "launcher".to_string(),
+                exit_code: Some(3),
+                output: "Patch server unreachable\n".to_string(),
+            }).as_deref(),
+            Some("Patch server unreachable"),
+        );
+    }
+
+    #[test]
+    fn exports_the_output_as_one_csv_field() {
+        let entry = HistoryEntry {
+            id: 1,
+            app_id: 740,
+            username: None,
+            started_at: 100,
+            finished_at: Some(160),
+            result: UpdateResult::Failed,
+            bytes_downloaded: 0,
+            exit_code: Some(8),
+            error_code: Some("UPDATE_FAILED".to_string()),
+            error_message: Some("Update of app 740 failed".to_string()),
+            platform: Some(Platform::Linux),
+            bitness: None,
+            output: Some("Error! App '740' state is 0x6 after update job.\nsaid \"no\"".to_string()),
+        };
+
+        let csv = to_csv(&[entry]);
+
+        assert!(csv.lines().next().unwrap().ends_with(",output"));
+        assert!(csv.ends_with(",linux,,\"Error! App '740' state is 0x6 after update job.\nsaid \"\"no\"\"\"\n"));
+    }
+}
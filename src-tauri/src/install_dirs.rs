@@ -0,0 +1,229 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::db;
+use crate::error::SteamError;
+
+const DEFAULT_DIR_SETTING: &str = "default_install_dir";
+/// Free space an install directory needs before SteamCMD is even started.
+pub(crate) const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+const PROBE_FILE_NAME: &str = ".updateio-write-test";
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[InstallDirs] {}", format!($($arg)*));
+    }
+}
+
+/// Directory an app is installed into, passed to SteamCMD as `force_install_dir`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstallDir {
+    pub app_id: u32,
+    pub path: String,
+}
+
+/// Where `app_id` should be installed, or `None` to leave it to SteamCMD.
+///
+/// A per-app directory is used as is. Otherwise the app gets its own
+/// `<app_id>` folder inside the default directory, so two apps never share
+/// an install directory.
+pub(crate) fn resolve(app_id: u32) -> Result<Option<PathBuf>, SteamError> {
+    Ok(choose(app_id, db::get_install_dir(app_id)?, default_root()?))
+}
+
+fn choose(app_id: u32, own_dir: Option<String>, default_root: Option<PathBuf>) -> Option<PathBuf> {
+    match own_dir {
+        Some(path) => Some(PathBuf::from(path)),
+        None => default_root.map(|root| root.join(app_id.to_string())),
+    }
+}
+
+/// The library apps without a directory of their own are installed into.
+pub(crate) fn default_root() -> Result<Option<PathBuf>, SteamError> {
+    Ok(db::get_setting(DEFAULT_DIR_SETTING)?.map(PathBuf::from))
+}
+
+/// Resolves and validates the install directory right before SteamCMD is launched.
+pub(crate) fn prepare(app_id: u32) -> Result<Option<PathBuf>, SteamError> {
+    let own_dir = db::get_install_dir(app_id)?;
+    // The per-app folder under the default directory is ours to create
+    let create = own_dir.is_none();
+    let Some(path) = choose(app_id, own_dir, default_root()?) else {
+        return Ok(None);
+    };
+
+    prepare_path(&path, create)?;
+    dev_log!("Installing app {} into {:?}", app_id, path);
+    Ok(Some(path))
+}
+
+/// Validates `path`, first creating it inside its validated parent if `create` is set.
+fn prepare_path(path: &Path, create: bool) -> Result<(), SteamError> {
+    if create && !path.exists() {
+        if let Some(root) = path.parent() {
+            validate(root)?;
+        }
+        fs::create_dir_all(path)
+            .map_err(|e| SteamError::InstallDirNotWritable { path: display(path), reason: e.to_string() })?;
+    }
+
+    validate(path)
+}
+
+/// Checks that `path` is an existing, writable directory with enough free space.
+pub(crate) fn validate(path: &Path) -> Result<(), SteamError> {
+    if !path.is_absolute() {
+        return Err(SteamError::InvalidInput {
+            reason: format!("Install directory {} must be an absolute path", display(path)),
+        });
+    }
+    if !path.is_dir() {
+        return Err(SteamError::InstallDirNotFound { path: display(path) });
+    }
+
+    let probe = path.join(PROBE_FILE_NAME);
+    fs::write(&probe, b"")
+        .map_err(|e| SteamError::InstallDirNotWritable { path: display(path), reason: e.to_string() })?;
+    let _ = fs::remove_file(&probe);
+
+    let available = fs2::available_space(path)
+        .map_err(|e| SteamError::io(&format!("Failed to read free space of {}", display(path)), e))?;
+    if available < MIN_FREE_SPACE {
+        return Err(SteamError::InsufficientDiskSpace {
+            path: display(path),
+            available,
+            required: MIN_FREE_SPACE,
+        });
+    }
+
+    Ok(())
+}
+
+fn display(path: &Path) -> String {
+    path.display().to_string()
+}
+
+pub(crate) async fn list_install_dirs() -> Result<Vec<InstallDir>, SteamError> {
+    Ok(db::list_install_dirs()?)
+}
+
+pub(crate) async fn set_app_install_dir(app_id: u32, path: String) -> Result<InstallDir, SteamError> {
+    validate(Path::new(&path))?;
+    db::set_install_dir(app_id, &path)?;
+    Ok(InstallDir { app_id, path })
+}
+
+pub(crate) async fn clear_app_install_dir(app_id: u32) -> Result<(), SteamError> {
+    Ok(db::clear_install_dir(app_id)?)
+}
+
+pub(crate) async fn get_default_install_dir() -> Result<Option<String>, SteamError> {
+    Ok(db::get_setting(DEFAULT_DIR_SETTING)?)
+}
+
+/// Sets the library new apps are installed into; `None` goes back to SteamCMD's own directory.
+pub(crate) async fn set_default_install_dir(path: Option<String>) -> Result<Option<String>, SteamError> {
+    match &path {
+        Some(path) => {
+            validate(Path::new(path))?;
+            db::set_setting(DEFAULT_DIR_SETTING, path)?;
+        }
+        None => db::delete_setting(DEFAULT_DIR_SETTING)?,
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("updateio-install-dirs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn accepts_a_writable_directory() {
+        let dir = scratch_dir("writable");
+
+        validate(&dir).unwrap();
+
+        assert!(!dir.join(PROBE_FILE_NAME).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_relative_paths() {
+        assert!(matches!(validate(Path::new("games/cs2")), Err(SteamError::InvalidInput { .. })));
+    }
+
+    #[test]
+    fn rejects_paths_that_are_not_directories() {
+        let dir = scratch_dir("not-a-dir");
+        let file = dir.join("steamapps.txt");
+        fs::write(&file, "").unwrap();
+
+        assert!(matches!(validate(&file), Err(SteamError::InstallDirNotFound { .. })));
+        assert!(matches!(validate(&dir.join("missing")), Err(SteamError::InstallDirNotFound { .. })));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_directories_that_cannot_be_written() {
+        let dir = scratch_dir("read-only");
+        // A directory in the probe's place fails the write even for root,
+        // which permission bits would not
+        fs::create_dir(dir.join(PROBE_FILE_NAME)).unwrap();
+
+        assert!(matches!(validate(&dir), Err(SteamError::InstallDirNotWritable { .. })));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn an_own_directory_wins_over_the_default_root() {
+        assert_eq!(
+            choose(740, Some("/srv/cs2".to_string()), Some(PathBuf::from("/srv/games"))),
+            Some(PathBuf::from("/srv/cs2"))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_a_folder_in_the_default_root() {
+        assert_eq!(choose(740, None, Some(PathBuf::from("/srv/games"))), Some(PathBuf::from("/srv/games/740")));
+        assert_eq!(choose(740, None, None), None);
+    }
+
+    #[test]
+    fn prepare_creates_the_folder_in_the_default_root() {
+        let root = scratch_dir("prepare");
+        let path = root.join("740");
+
+        prepare_path(&path, true).unwrap();
+
+        assert!(path.is_dir());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn prepare_does_not_create_an_own_directory() {
+        let root = scratch_dir("prepare-own");
+        let path = root.join("cs2");
+
+        assert!(matches!(prepare_path(&path, false), Err(SteamError::InstallDirNotFound { .. })));
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn prepare_needs_an_existing_default_root() {
+        let root = scratch_dir("prepare-missing").join("missing");
+        let path = root.join("740");
+
+        assert!(matches!(prepare_path(&path, true), Err(SteamError::InstallDirNotFound { .. })));
+        assert!(!root.exists());
+    }
+}
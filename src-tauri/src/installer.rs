@@ -0,0 +1,440 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_http::reqwest;
+use crate::db;
+use crate::error::SteamError;
+use crate::steam;
+
+const PROGRESS_EVENT: &str = "steamcmd-download-progress";
+/// Setting holding the SHA-256 the installer archive must match.
+const CHECKSUM_SETTING: &str = "steamcmd_sha256";
+/// Overrides the pinned checksum, e.g. after Valve republishes the installer.
+const CHECKSUM_ENV: &str = "UPDATEIO_STEAMCMD_SHA256";
+/// Emit a progress event at most once per this many downloaded bytes.
+const PROGRESS_STEP: u64 = 256 * 1024;
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Installer] {}", format!($($arg)*));
+    }
+}
+
+enum ArchiveKind {
+    #[cfg(not(target_os = "windows"))]
+    TarGz,
+    #[cfg(target_os = "windows")]
+    Zip,
+}
+
+struct Package {
+    url: &'static str,
+    file_name: &'static str,
+    kind: ArchiveKind,
+    /// SHA-256 of the published archive. `None` until one has been taken
+    /// from Valve's CDN, archives are then installed unverified.
+    sha256: Option<&'static str>,
+}
+
+#[cfg(target_os = "windows")]
+const PACKAGE: Package = Package {
+    url: "https://steamcdn-a.akamaihd.net/client/installer/steamcmd.zip",
+    file_name: "steamcmd.zip",
+    kind: ArchiveKind::Zip,
+    sha256: None,
+};
+
+#[cfg(target_os = "linux")]
+const PACKAGE: Package = Package {
+    url: "https://steamcdn-a.akamaihd.net/client/installer/steamcmd_linux.tar.gz",
+    file_name: "steamcmd_linux.tar.gz",
+    kind: ArchiveKind::TarGz,
+    sha256: None,
+};
+
+#[cfg(target_os = "macos")]
+const PACKAGE: Package = Package {
+    url: "https://steamcdn-a.akamaihd.net/client/installer/steamcmd_osx.tar.gz",
+    file_name: "steamcmd_osx.tar.gz",
+    kind: ArchiveKind::TarGz,
+    sha256: None,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub bytes_done: u64,
+    pub bytes_total: Option<u64>,
+}
+
+/// Downloads and unpacks SteamCMD without any external tools.
+///
+/// Everything happens in a staging directory next to the SteamCMD directory,
+/// which is only swapped in once the archive has been verified and fully
+/// extracted, so an interrupted install never leaves a half-written SteamCMD.
+pub(crate) async fn install(app: &AppHandle) -> Result<(), SteamError> {
+    install_staged(app, None).await
+}
+
+/// Replaces SteamCMD with a fresh copy, removing everything in its directory
+/// except the entries named in `keep`.
+///
+/// Nothing is removed until the new copy has been downloaded, verified and
+/// extracted, so a failed download leaves the old SteamCMD as it was.
+pub(crate) async fn reinstall(app: &AppHandle, keep: &[&str]) -> Result<(), SteamError> {
+    install_staged(app, Some(keep)).await
+}
+
+async fn install_staged(app: &AppHandle, keep: Option<&[&str]>) -> Result<(), SteamError> {
+    let target = steam::get_steamcmd_dir(app)?;
+    let parent = target.parent()
+        .ok_or_else(|| SteamError::Internal { reason: "Failed to get parent directory".to_string() })?;
+
+    let staging = parent.join(format!(".steamcmd-install-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)
+        .map_err(|e| SteamError::io("Failed to create staging directory", e))?;
+
+    let result = install_into(app, &staging, &target, keep).await;
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+async fn install_into(
+    app: &AppHandle,
+    staging: &Path,
+    target: &Path,
+    keep: Option<&[&str]>
+) -> Result<(), SteamError> {
+    let archive = staging.join(PACKAGE.file_name);
+
+    dev_log!("Downloading {}", PACKAGE.url);
+    let checksum = download(app, PACKAGE.url, &archive).await?;
+    verify_checksum(&checksum)?;
+
+    let extracted = staging.join("steamcmd");
+    let archive_path = archive.clone();
+    let extract_dir = extracted.clone();
+    tauri::async_runtime::spawn_blocking(move || extract(&archive_path, &extract_dir))
+        .await
+        .map_err(|e| SteamError::internal("Extraction task failed", e))??;
+
+    #[cfg(unix)]
+    make_executable(&extracted.join("steamcmd.sh"))?;
+
+    if let Some(keep) = keep {
+        remove_all_except(target, keep)?;
+    }
+    swap_into_place(&extracted, target)?;
+    dev_log!("Installed SteamCMD into {:?}", target);
+    Ok(())
+}
+
+/// Streams `url` to `path` and returns the SHA-256 of what was written.
+async fn download(app: &AppHandle, url: &str, path: &Path) -> Result<String, SteamError> {
+    let install_error = |e: reqwest::Error| SteamError::SteamCmdInstallFailed {
+        reason: format!("Failed to download SteamCMD: {}", e),
+    };
+
+    let mut response = reqwest::get(url).await
+        .and_then(|response| response.error_for_status())
+        .map_err(install_error)?;
+    let bytes_total = response.content_length();
+
+    let mut file = BufWriter::new(
+        File::create(path).map_err(|e| SteamError::io("Failed to create download file", e))?
+    );
+    let mut hasher = Sha256::new();
+    let mut bytes_done = 0u64;
+    let mut last_emitted = 0u64;
+
+    while let Some(chunk) = response.chunk().await.map_err(install_error)? {
+        file.write_all(&chunk)
+            .map_err(|e| SteamError::io("Failed to write download file", e))?;
+        hasher.update(&chunk);
+        bytes_done += chunk.len() as u64;
+
+        if bytes_done - last_emitted >= PROGRESS_STEP {
+            emit_progress(app, bytes_done, bytes_total);
+            last_emitted = bytes_done;
+        }
+    }
+
+    file.into_inner()
+        .map_err(|e| SteamError::io("Failed to write download file", e.error()))?
+        .sync_all()
+        .map_err(|e| SteamError::io("Failed to write download file", e))?;
+    emit_progress(app, bytes_done, bytes_total);
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn emit_progress(app: &AppHandle, bytes_done: u64, bytes_total: Option<u64>) {
+    if let Err(e) = app.emit(PROGRESS_EVENT, DownloadProgress { bytes_done, bytes_total }) {
+        dev_log!("Failed to emit download progress: {}", e);
+    }
+}
+
+/// Checks the archive against the checksum it is expected to have.
+///
+/// `UPDATEIO_STEAMCMD_SHA256` or the `steamcmd_sha256` setting override the
+/// checksum pinned for the platform. Without any of them the archive is
+/// installed as downloaded, like before checksums were pinned.
+fn verify_checksum(actual: &str) -> Result<(), SteamError> {
+    let configured = match std::env::var(CHECKSUM_ENV) {
+        Ok(value) => Some(value),
+        Err(_) => db::get_setting(CHECKSUM_SETTING)?,
+    };
+    check_checksum(expected_checksum(configured, PACKAGE.sha256).as_deref(), actual)
+}
+
+/// The configured checksum if there is a non-empty one, the pinned one otherwise.
+fn expected_checksum(configured: Option<String>, pinned: Option<&str>) -> Option<String> {
+    configured.as_deref()
+        .into_iter()
+        .chain(pinned)
+        .map(|checksum| checksum.trim().to_lowercase())
+        .find(|checksum| !checksum.is_empty())
+}
+
+fn check_checksum(expected: Option<&str>, actual: &str) -> Result<(), SteamError> {
+    match expected {
+        Some(expected) if expected.eq_ignore_ascii_case(actual) => Ok(()),
+        Some(expected) => Err(SteamError::ChecksumMismatch {
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        }),
+        None => {
+            dev_log!("No checksum pinned for {}, installing archive {} unverified", PACKAGE.file_name, actual);
+            Ok(())
+        }
+    }
+}
+
+/// Sets or, with `None`, clears the checksum the installer archive must match.
+pub(crate) async fn set_steamcmd_checksum(checksum: Option<String>) -> Result<Option<String>, SteamError> {
+    let checksum = checksum
+        .map(|checksum| checksum.trim().to_lowercase())
+        .filter(|checksum| !checksum.is_empty());
+    match &checksum {
+        Some(checksum) => {
+            if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(SteamError::InvalidInput {
+                    reason: format!("{:?} is not a SHA-256 checksum", checksum),
+                });
+            }
+            db::set_setting(CHECKSUM_SETTING, checksum)?;
+        }
+        None => db::delete_setting(CHECKSUM_SETTING)?,
+    }
+    Ok(checksum)
+}
+
+fn extract(archive: &Path, destination: &Path) -> Result<(), SteamError> {
+    let install_error = |e: &dyn std::fmt::Display| SteamError::SteamCmdInstallFailed {
+        reason: format!("Failed to extract SteamCMD: {}", e),
+    };
+
+    fs::create_dir_all(destination)
+        .map_err(|e| SteamError::io("Failed to create SteamCMD directory", e))?;
+    let file = File::open(archive)
+        .map_err(|e| SteamError::io("Failed to open downloaded archive", e))?;
+
+    // Both unpackers refuse entries that would escape `destination`
+    match PACKAGE.kind {
+        #[cfg(not(target_os = "windows"))]
+        ArchiveKind::TarGz => tar::Archive::new(flate2::read::GzDecoder::new(file))
+            .unpack(destination)
+            .map_err(|e| install_error(&e)),
+        #[cfg(target_os = "windows")]
+        ArchiveKind::Zip => zip::ZipArchive::new(file)
+            .and_then(|mut archive| archive.extract(destination))
+            .map_err(|e| install_error(&e)),
+    }
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), SteamError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if path.exists() {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+            .map_err(|e| SteamError::io("Failed to make steamcmd.sh executable", e))?;
+    }
+    Ok(())
+}
+
+/// Empties `dir` except for the top-level entries named in `keep`.
+fn remove_all_except(dir: &Path, keep: &[&str]) -> Result<(), SteamError> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(());
+    };
+
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if keep.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+
+        let path = entry.path();
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        removed.map_err(|e| SteamError::io(&format!("Failed to remove {:?}", path), e))?;
+    }
+    Ok(())
+}
+
+/// Moves the extracted files into `target`.
+///
+/// A fresh install renames the whole directory. An existing directory may
+/// hold games downloaded without an install directory, so there each
+/// top-level entry is swapped individually and everything else is kept.
+fn swap_into_place(source: &Path, target: &Path) -> Result<(), SteamError> {
+    if !target.exists() {
+        return fs::rename(source, target)
+            .map_err(|e| SteamError::io("Failed to move SteamCMD into place", e));
+    }
+
+    let entries = fs::read_dir(source)
+        .map_err(|e| SteamError::io("Failed to read extracted SteamCMD", e))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| SteamError::io("Failed to read extracted SteamCMD", e))?;
+        let destination = target.join(entry.file_name());
+
+        if destination.is_dir() {
+            fs::remove_dir_all(&destination)
+                .map_err(|e| SteamError::io(&format!("Failed to replace {:?}", destination), e))?;
+        } else if destination.exists() {
+            fs::remove_file(&destination)
+                .map_err(|e| SteamError::io(&format!("Failed to replace {:?}", destination), e))?;
+        }
+        fs::rename(entry.path(), &destination)
+            .map_err(|e| SteamError::io(&format!("Failed to move {:?} into place", destination), e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("updateio-installer-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn clearing_keeps_only_what_was_asked_for() {
+        let dir = scratch_dir("clear");
+        fs::create_dir_all(dir.join("steamapps/common/Game")).unwrap();
+        fs::write(dir.join("steamapps/appmanifest_740.acf"), "\"AppState\" {}").unwrap();
+        fs::create_dir_all(dir.join("package")).unwrap();
+        fs::write(dir.join("package/steam_cmd_linux.installed"), "").unwrap();
+        fs::write(dir.join("steamcmd.sh"), "#!/bin/sh").unwrap();
+
+        remove_all_except(&dir, &["steamapps"]).unwrap();
+
+        let mut left: Vec<_> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["steamapps"]);
+        assert!(dir.join("steamapps/appmanifest_740.acf").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A small steamcmd_linux.tar.gz lookalike and its real SHA-256.
+    const FIXTURE: &[u8] = include_bytes!("../testdata/installer/steamcmd_fixture.tar.gz");
+    const ARCHIVE: &str = "cd796466560743eca595513f2a8d267c0a9918624e3a78e81e7e80136f48d9fb";
+    const OTHER: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+    fn fixture_checksum() -> String {
+        format!("{:x}", Sha256::digest(FIXTURE))
+    }
+
+    #[test]
+    fn accepts_an_archive_matching_the_checksum() {
+        assert!(check_checksum(Some(ARCHIVE), &fixture_checksum()).is_ok());
+        let configured = expected_checksum(Some(format!(" {} ", ARCHIVE.to_uppercase())), None);
+        assert!(check_checksum(configured.as_deref(), &fixture_checksum()).is_ok());
+    }
+
+    #[test]
+    fn refuses_an_archive_that_does_not_match() {
+        assert!(matches!(
+            check_checksum(Some(OTHER), &fixture_checksum()),
+            Err(SteamError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn installs_unverified_without_any_checksum() {
+        assert_eq!(expected_checksum(None, None), None);
+        assert_eq!(expected_checksum(Some("  ".to_string()), None), None);
+        assert!(check_checksum(None, &fixture_checksum()).is_ok());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn a_verified_archive_extracts() {
+        let dir = scratch_dir("extract");
+        fs::write(dir.join("steamcmd_linux.tar.gz"), FIXTURE).unwrap();
+
+        extract(&dir.join("steamcmd_linux.tar.gz"), &dir.join("steamcmd")).unwrap();
+
+        assert!(fs::read_to_string(dir.join("steamcmd/steamcmd.sh")).unwrap().contains("fake steamcmd"));
+        assert!(dir.join("steamcmd/linux32/steamcmd").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_configured_checksum_overrides_the_pinned_one() {
+        assert_eq!(expected_checksum(Some(OTHER.to_string()), Some(ARCHIVE)).as_deref(), Some(OTHER));
+        assert_eq!(expected_checksum(None, Some(ARCHIVE)).as_deref(), Some(ARCHIVE));
+        assert_eq!(expected_checksum(Some(String::new()), Some(ARCHIVE)).as_deref(), Some(ARCHIVE));
+    }
+
+    #[test]
+    fn a_fresh_install_moves_the_whole_directory() {
+        let dir = scratch_dir("fresh");
+        fs::create_dir_all(dir.join("extracted/linux32")).unwrap();
+        fs::write(dir.join("extracted/steamcmd.sh"), "new").unwrap();
+
+        swap_into_place(&dir.join("extracted"), &dir.join("steamcmd")).unwrap();
+
+        assert!(!dir.join("extracted").exists());
+        assert_eq!(fs::read_to_string(dir.join("steamcmd/steamcmd.sh")).unwrap(), "new");
+        assert!(dir.join("steamcmd/linux32").is_dir());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn swapping_over_an_install_replaces_its_files_and_keeps_the_rest() {
+        let dir = scratch_dir("swap");
+        fs::create_dir_all(dir.join("extracted/linux32")).unwrap();
+        fs::write(dir.join("extracted/linux32/steamcmd"), "new").unwrap();
+        fs::write(dir.join("extracted/steamcmd.sh"), "new").unwrap();
+        fs::create_dir_all(dir.join("steamcmd/linux32")).unwrap();
+        fs::write(dir.join("steamcmd/linux32/stale.so"), "old").unwrap();
+        fs::write(dir.join("steamcmd/steamcmd.sh"), "old").unwrap();
+        fs::create_dir_all(dir.join("steamcmd/steamapps")).unwrap();
+        fs::write(dir.join("steamcmd/steamapps/appmanifest_740.acf"), "\"AppState\" {}").unwrap();
+
+        swap_into_place(&dir.join("extracted"), &dir.join("steamcmd")).unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("steamcmd/steamcmd.sh")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.join("steamcmd/linux32/steamcmd")).unwrap(), "new");
+        assert!(!dir.join("steamcmd/linux32/stale.so").exists());
+        assert!(dir.join("steamcmd/steamapps/appmanifest_740.acf").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,43 @@
+mod error;
 mod steam;
 mod db;
+mod migrations;
+mod accounts;
+mod queue;
+mod progress;
+mod processes;
+mod vault;
+mod history;
+mod install_dirs;
+mod branches;
+mod executor;
+mod installer;
+mod health;
+mod session;
+mod guard;
+mod totp;
+mod login_script;
+mod runner;
+mod disk_space;
+mod vdf;
+mod library;
+mod updates;
 
-use tauri::AppHandle;
-use crate::steam::SteamCredentials;
+use tauri::{AppHandle, Manager};
+use crate::error::SteamError;
+use crate::steam::{SteamCredentials, UpdateOptions, VerifyReport};
+use crate::queue::UpdateJob;
+use crate::processes::ProcessStatus;
+use crate::vault::VaultStatus;
+use crate::accounts::{AppAccount, SteamAccount};
+use crate::history::{ExportFormat, HistoryEntry};
+use crate::install_dirs::InstallDir;
+use crate::branches::AppBranch;
+use crate::library::InstalledGame;
+use crate::updates::OutdatedApp;
+use crate::executor::ExecutorSettings;
+use crate::health::SteamCmdHealth;
+use crate::guard::GuardChallenge;
 
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -10,59 +45,378 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-async fn ensure_steamcmd(app: AppHandle) -> Result<String, String> {
+async fn ensure_steamcmd(app: AppHandle) -> Result<String, SteamError> {
     steam::ensure_steamcmd(app).await
 }
 
 #[tauri::command]
-async fn update_game(app: AppHandle, app_id: u32) -> Result<String, String> {
-    steam::update_game(app, app_id).await
+async fn steamcmd_health(app: AppHandle) -> Result<SteamCmdHealth, SteamError> {
+    health::steamcmd_health(app).await
+}
+
+#[tauri::command]
+async fn repair_steamcmd(app: AppHandle) -> Result<SteamCmdHealth, SteamError> {
+    health::repair_steamcmd(app).await
+}
+
+#[tauri::command]
+async fn submit_2fa_code(app: AppHandle, challenge_id: u64, code: String) -> Result<(), SteamError> {
+    guard::submit_code(app, challenge_id, code).await
+}
+
+#[tauri::command]
+async fn cancel_2fa_challenge(app: AppHandle, challenge_id: u64) -> Result<(), SteamError> {
+    guard::cancel_challenge(app, challenge_id).await
+}
+
+#[tauri::command]
+async fn list_2fa_challenges(app: AppHandle) -> Result<Vec<GuardChallenge>, SteamError> {
+    guard::list_challenges(app).await
+}
+
+#[tauri::command]
+async fn update_game(app: AppHandle, app_id: u32, options: Option<UpdateOptions>) -> Result<String, SteamError> {
+    steam::update_game(app, app_id, options.unwrap_or_default()).await
 }
 
 #[tauri::command]
 async fn update_game_authenticated(
     app: AppHandle,
     app_id: u32,
-    credentials: SteamCredentials
-) -> Result<String, String> {
-    steam::update_game_authenticated(app, app_id, credentials).await
+    credentials: Option<SteamCredentials>,
+    options: Option<UpdateOptions>
+) -> Result<String, SteamError> {
+    let credentials = match credentials {
+        Some(credentials) => Some(credentials),
+        None => accounts::credentials_for_app(&app, app_id)?,
+    };
+    let options = options.unwrap_or_default();
+
+    match credentials {
+        Some(credentials) => steam::update_game_authenticated(app, app_id, credentials, options).await,
+        // Free and dedicated-server apps have no owning account
+        None => steam::update_game(app, app_id, options).await,
+    }
+}
+
+#[tauri::command]
+async fn verify_game(
+    app: AppHandle,
+    app_id: u32,
+    username: Option<String>,
+    options: Option<UpdateOptions>
+) -> Result<VerifyReport, SteamError> {
+    // Same precedence as the queue: explicit account, mapped account, anonymous
+    let credentials = match &username {
+        Some(username) => Some(accounts::load_account(&app, username)?),
+        None => accounts::credentials_for_app(&app, app_id)?,
+    };
+    steam::verify_game(app, app_id, credentials, options.unwrap_or_default()).await
+}
+
+#[tauri::command]
+async fn run_update(
+    app: AppHandle,
+    settings: ExecutorSettings,
+    username: Option<String>
+) -> Result<String, SteamError> {
+    executor::run_update(app, settings, username).await
 }
 
 #[tauri::command]
 async fn authenticate_steam(
     app: AppHandle,
     credentials: SteamCredentials
-) -> Result<String, String> {
+) -> Result<String, SteamError> {
     steam::authenticate_steam(app, credentials).await
 }
 
 #[tauri::command]
-async fn get_stored_credentials(app: AppHandle) -> Result<Option<SteamCredentials>, String> {
+async fn get_stored_credentials(app: AppHandle) -> Result<Option<SteamCredentials>, SteamError> {
     steam::get_stored_credentials(app).await
 }
 
 #[tauri::command]
-async fn clear_stored_credentials(app: AppHandle) -> Result<(), String> {
+async fn clear_stored_credentials(app: AppHandle) -> Result<(), SteamError> {
     steam::clear_stored_credentials(app).await
 }
 
+#[tauri::command]
+async fn list_update_history(
+    app_id: Option<u32>,
+    limit: Option<u32>
+) -> Result<Vec<HistoryEntry>, SteamError> {
+    history::list_history(app_id, limit).await
+}
+
+#[tauri::command]
+async fn export_update_history(
+    format: ExportFormat,
+    app_id: Option<u32>
+) -> Result<String, SteamError> {
+    history::export_history(format, app_id).await
+}
+
+#[tauri::command]
+async fn list_steam_accounts() -> Result<Vec<SteamAccount>, SteamError> {
+    accounts::list_accounts().await
+}
+
+#[tauri::command]
+async fn save_steam_account(
+    app: AppHandle,
+    username: String,
+    password: String
+) -> Result<SteamAccount, SteamError> {
+    accounts::save_account(app, username, password).await
+}
+
+#[tauri::command]
+async fn set_account_shared_secret(
+    app: AppHandle,
+    username: String,
+    shared_secret: Option<String>
+) -> Result<(), SteamError> {
+    accounts::set_shared_secret(app, username, shared_secret).await
+}
+
+#[tauri::command]
+async fn delete_steam_account(username: String) -> Result<(), SteamError> {
+    accounts::delete_account(username).await
+}
+
+#[tauri::command]
+async fn list_app_accounts() -> Result<Vec<AppAccount>, SteamError> {
+    accounts::list_app_accounts().await
+}
+
+#[tauri::command]
+async fn set_app_account(app_id: u32, username: String) -> Result<AppAccount, SteamError> {
+    accounts::set_app_account(app_id, username).await
+}
+
+#[tauri::command]
+async fn clear_app_account(app_id: u32) -> Result<(), SteamError> {
+    accounts::clear_app_account(app_id).await
+}
+
+#[tauri::command]
+async fn list_install_dirs() -> Result<Vec<InstallDir>, SteamError> {
+    install_dirs::list_install_dirs().await
+}
+
+#[tauri::command]
+async fn set_app_install_dir(app_id: u32, path: String) -> Result<InstallDir, SteamError> {
+    install_dirs::set_app_install_dir(app_id, path).await
+}
+
+#[tauri::command]
+async fn clear_app_install_dir(app_id: u32) -> Result<(), SteamError> {
+    install_dirs::clear_app_install_dir(app_id).await
+}
+
+#[tauri::command]
+async fn list_app_branches() -> Result<Vec<AppBranch>, SteamError> {
+    branches::list_app_branches().await
+}
+
+#[tauri::command]
+async fn set_app_branch(
+    app: AppHandle,
+    app_id: u32,
+    branch: String,
+    password: Option<String>
+) -> Result<AppBranch, SteamError> {
+    branches::set_app_branch(app, app_id, branch, password).await
+}
+
+#[tauri::command]
+async fn clear_app_branch(app_id: u32) -> Result<(), SteamError> {
+    branches::clear_app_branch(app_id).await
+}
+
+#[tauri::command]
+async fn get_default_install_dir() -> Result<Option<String>, SteamError> {
+    install_dirs::get_default_install_dir().await
+}
+
+#[tauri::command]
+async fn set_steamcmd_checksum(checksum: Option<String>) -> Result<Option<String>, SteamError> {
+    installer::set_steamcmd_checksum(checksum).await
+}
+
+#[tauri::command]
+async fn set_default_install_dir(path: Option<String>) -> Result<Option<String>, SteamError> {
+    install_dirs::set_default_install_dir(path).await
+}
+
+#[tauri::command]
+async fn installed_games(app: AppHandle) -> Result<Vec<InstalledGame>, SteamError> {
+    library::installed_games(app).await
+}
+
+#[tauri::command]
+async fn check_updates(app: AppHandle, app_ids: Option<Vec<u32>>) -> Result<Vec<OutdatedApp>, SteamError> {
+    updates::check_updates(app, app_ids).await
+}
+
+#[tauri::command]
+async fn vault_status(app: AppHandle) -> Result<VaultStatus, SteamError> {
+    vault::status(app).await
+}
+
+#[tauri::command]
+async fn vault_unlock(app: AppHandle, master_password: String) -> Result<VaultStatus, SteamError> {
+    vault::unlock(app, master_password).await
+}
+
+#[tauri::command]
+async fn vault_lock(app: AppHandle) -> Result<VaultStatus, SteamError> {
+    vault::lock(app).await
+}
+
+#[tauri::command]
+async fn vault_reset(app: AppHandle) -> Result<VaultStatus, SteamError> {
+    vault::reset(app).await
+}
+
+#[tauri::command]
+async fn vault_set_master_password(
+    app: AppHandle,
+    current_password: Option<String>,
+    master_password: Option<String>
+) -> Result<VaultStatus, SteamError> {
+    vault::set_master_password(app, current_password, master_password).await
+}
+
+#[tauri::command]
+async fn cancel_update(app: AppHandle, app_id: u32) -> Result<ProcessStatus, SteamError> {
+    processes::cancel_update(app, app_id).await
+}
+
+#[tauri::command]
+async fn pause_update(app: AppHandle, app_id: u32) -> Result<ProcessStatus, SteamError> {
+    processes::pause_update(app, app_id).await
+}
+
+#[tauri::command]
+async fn resume_update(app: AppHandle, app_id: u32) -> Result<ProcessStatus, SteamError> {
+    processes::resume_update(app, app_id).await
+}
+
+#[tauri::command]
+async fn list_running_updates(app: AppHandle) -> Result<Vec<ProcessStatus>, SteamError> {
+    processes::list_running_updates(app).await
+}
+
+#[tauri::command]
+async fn enqueue_update(
+    app: AppHandle,
+    app_id: u32,
+    username: Option<String>,
+    options: Option<UpdateOptions>
+) -> Result<UpdateJob, SteamError> {
+    queue::enqueue_update(app, app_id, username, options.unwrap_or_default()).await
+}
+
+#[tauri::command]
+async fn cancel_update_job(app: AppHandle, job_id: i64) -> Result<UpdateJob, SteamError> {
+    queue::cancel_job(app, job_id).await
+}
+
+#[tauri::command]
+async fn reorder_update_job(
+    app: AppHandle,
+    job_id: i64,
+    index: usize
+) -> Result<Vec<UpdateJob>, SteamError> {
+    queue::reorder_job(app, job_id, index).await
+}
+
+#[tauri::command]
+async fn list_update_jobs() -> Result<Vec<UpdateJob>, SteamError> {
+    queue::list_jobs().await
+}
+
+#[tauri::command]
+async fn get_queue_concurrency(app: AppHandle) -> Result<usize, SteamError> {
+    queue::get_concurrency(app).await
+}
+
+#[tauri::command]
+async fn set_queue_concurrency(app: AppHandle, concurrency: usize) -> Result<usize, SteamError> {
+    queue::set_concurrency(app, concurrency).await
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_shell::init())
-        .setup(|_app| {
+        .setup(|app| {
             db::init_db().map_err(|e| format!("Failed to initialize database: {}", e))?;
+            history::close_interrupted()?;
+            app.manage(vault::Vault::default());
+            vault::init(app.handle())?;
+            app.manage(processes::ProcessRegistry::default());
+            app.manage(disk_space::DiskReservations::default());
+            app.manage(guard::GuardBroker::default());
+            app.manage(totp::SteamClock::default());
+            app.manage(session::SessionManager::default());
+            app.manage(queue::UpdateQueue::new(queue::load_concurrency()));
+            queue::start(app.handle().clone())?;
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             greet, 
             ensure_steamcmd,
+            steamcmd_health,
+            repair_steamcmd,
             update_game,
             update_game_authenticated,
+            verify_game,
+            run_update,
             authenticate_steam,
+            submit_2fa_code,
+            cancel_2fa_challenge,
+            list_2fa_challenges,
             get_stored_credentials,
-            clear_stored_credentials
+            clear_stored_credentials,
+            list_update_history,
+            export_update_history,
+            list_steam_accounts,
+            save_steam_account,
+            set_account_shared_secret,
+            delete_steam_account,
+            list_app_accounts,
+            set_app_account,
+            clear_app_account,
+            list_install_dirs,
+            set_app_install_dir,
+            clear_app_install_dir,
+            list_app_branches,
+            set_app_branch,
+            clear_app_branch,
+            get_default_install_dir,
+            set_default_install_dir,
+            set_steamcmd_checksum,
+            installed_games,
+            check_updates,
+            vault_status,
+            vault_unlock,
+            vault_lock,
+            vault_set_master_password,
+            vault_reset,
+            cancel_update,
+            pause_update,
+            resume_update,
+            list_running_updates,
+            enqueue_update,
+            cancel_update_job,
+            reorder_update_job,
+            list_update_jobs,
+            get_queue_concurrency,
+            set_queue_concurrency
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
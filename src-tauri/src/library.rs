@@ -0,0 +1,248 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use tauri::AppHandle;
+use crate::branches;
+use crate::db;
+use crate::error::SteamError;
+use crate::install_dirs;
+use crate::steam;
+use crate::vdf;
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Library] {}", format!($($arg)*));
+    }
+}
+
+/// An app SteamCMD has installed, as its `appmanifest_<id>.acf` describes it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstalledGame {
+    pub app_id: u32,
+    pub name: String,
+    pub build_id: u64,
+    /// The game's files, `<library>/steamapps/common/<installdir>`.
+    pub install_dir: String,
+    pub size_on_disk: u64,
+    /// Steam's `EAppState` bits; 4 is fully installed, anything else is mid-update or broken.
+    pub state_flags: u32,
+    /// Unix time of the last update SteamCMD finished.
+    pub last_updated: i64,
+    /// The library the manifest was found in.
+    pub library: String,
+    /// The branch the installed build came from.
+    pub branch: String,
+    /// The branch the next update will install from.
+    pub configured_branch: String,
+    /// The installed build is not from the configured branch, the next update switches it.
+    pub branch_mismatch: bool,
+}
+
+/// Every directory that may hold a `steamapps` folder with app manifests.
+///
+/// That is SteamCMD's own directory, the libraries its `libraryfolders.vdf`
+/// lists, every per-app install directory and every app folder inside the
+/// default install directory.
+fn libraries(app: &AppHandle) -> Result<Vec<PathBuf>, SteamError> {
+    let steamcmd_dir = steam::get_steamcmd_dir(app)?;
+    let mut libraries = vec![steamcmd_dir.clone()];
+    libraries.extend(library_folders(&steamcmd_dir));
+
+    for dir in db::list_install_dirs()? {
+        libraries.push(PathBuf::from(dir.path));
+    }
+    if let Some(root) = install_dirs::default_root()? {
+        if let Ok(entries) = fs::read_dir(&root) {
+            libraries.extend(entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir()));
+        }
+    }
+
+    // The same library can be reached through several of the above
+    let mut seen = Vec::new();
+    libraries.retain(|library| {
+        let key = fs::canonicalize(library).unwrap_or_else(|_| library.clone());
+        if seen.contains(&key) {
+            false
+        } else {
+            seen.push(key);
+            true
+        }
+    });
+    Ok(libraries)
+}
+
+/// Extra libraries listed in `steamapps/libraryfolders.vdf`.
+fn library_folders(steamcmd_dir: &Path) -> Vec<PathBuf> {
+    let Ok(text) = fs::read_to_string(steamcmd_dir.join("steamapps").join("libraryfolders.vdf")) else {
+        return Vec::new();
+    };
+    match vdf::parse(&text) {
+        Ok((_, folders)) => folders.children().iter()
+            // Libraries are numbered; the other keys are Steam's own bookkeeping
+            .filter(|(key, _)| key.parse::<u32>().is_ok())
+            // Entries are either { "path" "..." } or, in older files, the path itself
+            .filter_map(|(_, folder)| folder.str("path").or_else(|| folder.as_str()))
+            .map(PathBuf::from)
+            .collect(),
+        Err(e) => {
+            dev_log!("Ignoring unreadable libraryfolders.vdf: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Reads every `appmanifest_*.acf` in `library`'s `steamapps` folder.
+///
+/// A manifest that can't be read is skipped, SteamCMD rewrites them while it works.
+pub(crate) fn scan_library(library: &Path) -> Vec<InstalledGame> {
+    let Ok(entries) = fs::read_dir(library.join("steamapps")) else {
+        return Vec::new();
+    };
+
+    entries.filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with("appmanifest_") && name.ends_with(".acf"))
+                .unwrap_or(false)
+        })
+        .filter_map(|path| match read_manifest(library, &path) {
+            Ok(game) => Some(game),
+            Err(e) => {
+                dev_log!("Skipping {:?}: {}", path, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn read_manifest(library: &Path, path: &Path) -> Result<InstalledGame, SteamError> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| SteamError::io(&format!("Failed to read {}", path.display()), e))?;
+    let (_, state) = vdf::parse(&text)?;
+
+    let app_id = state.u64("appid")
+        .and_then(|app_id| u32::try_from(app_id).ok())
+        .ok_or_else(|| SteamError::InvalidInput { reason: format!("{} has no app id", path.display()) })?;
+    let install_dir = state.str("installdir").unwrap_or_default();
+    // MountedConfig is what is on disk, UserConfig what was last asked for
+    let branch = ["MountedConfig", "UserConfig"].iter()
+        .filter_map(|config| state.get(config)?.str("BetaKey"))
+        .find(|branch| !branch.is_empty())
+        .unwrap_or(branches::PUBLIC);
+
+    Ok(InstalledGame {
+        app_id,
+        name: state.str("name").unwrap_or_default().to_string(),
+        build_id: state.u64("buildid").unwrap_or_default(),
+        install_dir: library.join("steamapps").join("common").join(install_dir).display().to_string(),
+        size_on_disk: state.u64("SizeOnDisk").unwrap_or_default(),
+        state_flags: state.u64("StateFlags").unwrap_or_default() as u32,
+        last_updated: state.u64("LastUpdated").unwrap_or_default() as i64,
+        library: library.display().to_string(),
+        branch: branch.to_string(),
+        configured_branch: branches::PUBLIC.to_string(),
+        branch_mismatch: false,
+    })
+}
+
+/// Every installed app across all libraries, by app id.
+pub(crate) async fn installed_games(app: AppHandle) -> Result<Vec<InstalledGame>, SteamError> {
+    let libraries = libraries(&app)?;
+
+    let mut games = tauri::async_runtime::spawn_blocking(move || {
+        libraries.iter().flat_map(|library| scan_library(library)).collect::<Vec<_>>()
+    })
+        .await
+        .map_err(|e| SteamError::internal("Library scan failed", e))?;
+
+    let configured = branches::configured()?;
+    for game in &mut games {
+        if let Some(branch) = configured.get(&game.app_id) {
+            game.configured_branch = branch.clone();
+        }
+        game.branch_mismatch = game.branch != game.configured_branch;
+        if game.branch_mismatch {
+            dev_log!("App {} is on branch {} but configured for {}", game.app_id, game.branch, game.configured_branch);
+        }
+    }
+
+    games.sort_by(|a, b| a.app_id.cmp(&b.app_id).then_with(|| a.library.cmp(&b.library)));
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_library(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("updateio-library-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("steamapps")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn scans_the_manifests_of_a_library() {
+        let library = scratch_library("scan");
+        fs::write(
+            library.join("steamapps/appmanifest_740.acf"),
+            include_str!("../testdata/manifests/appmanifest_740.acf"),
+        ).unwrap();
+        fs::write(library.join("steamapps/appmanifest_90.acf"), "\"AppState\"\n{\n\t\"appid\"\t\t\"90\"\n").unwrap();
+        fs::write(library.join("steamapps/libraryfolders.vdf"), "\"libraryfolders\" {}").unwrap();
+
+        let games = scan_library(&library);
+
+        assert_eq!(games, vec![InstalledGame {
+            app_id: 740,
+            name: "Counter-Strike Global Offensive - Dedicated Server".to_string(),
+            build_id: 14512334,
+            install_dir: library.join("steamapps").join("common")
+                .join("Counter-Strike Global Offensive Beta - Dedicated Server")
+                .display().to_string(),
+            size_on_disk: 35413614183,
+            state_flags: 4,
+            last_updated: 1697035452,
+            library: library.display().to_string(),
+            branch: "public".to_string(),
+            configured_branch: "public".to_string(),
+            branch_mismatch: false,
+        }]);
+        fs::remove_dir_all(library).unwrap();
+    }
+
+    #[test]
+    fn reads_the_branch_the_build_came_from() {
+        let library = scratch_library("branch");
+        let manifest = include_str!("../testdata/manifests/appmanifest_740.acf").replacen(
+            "\"MountedConfig\"\n\t{\n",
+            "\"MountedConfig\"\n\t{\n\t\t\"BetaKey\"\t\t\"tournament\"\n",
+            1,
+        );
+        fs::write(library.join("steamapps/appmanifest_740.acf"), manifest).unwrap();
+
+        let games = scan_library(&library);
+
+        assert_eq!(games[0].branch, "tournament");
+        fs::remove_dir_all(library).unwrap();
+    }
+
+    #[test]
+    fn reads_extra_libraries_from_libraryfolders() {
+        let library = scratch_library("folders");
+        fs::write(
+            library.join("steamapps/libraryfolders.vdf"),
+            "\"libraryfolders\"\n{\n\t\"contentstatsid\"\t\t\"-4390718\"\n\t\"0\"\n\t{\n\t\t\"path\"\t\t\"/srv/steam\"\n\t}\n\t\"1\"\t\t\"/mnt/games\"\n}\n",
+        ).unwrap();
+
+        assert_eq!(library_folders(&library), vec![PathBuf::from("/srv/steam"), PathBuf::from("/mnt/games")]);
+        fs::remove_dir_all(library).unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::error::SteamError;
+use crate::runner::ProcessSpec;
+use crate::session::quote;
+use crate::steam::SteamCredentials;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[LoginScript] {}", format!($($arg)*));
+    }
+}
+
+/// A SteamCMD runscript that logs in and quits.
+///
+/// Passing `+login user password` on the command line exposes the password
+/// to every process listing and crash dump, so the login goes into a file
+/// only the current user can read and SteamCMD gets `+runscript <path>`.
+/// Anonymous runs use one too when a command carries a beta branch password.
+/// The file is removed when the script is dropped.
+pub(crate) struct LoginScript {
+    path: PathBuf,
+}
+
+impl LoginScript {
+    /// Writes the script into the system temp directory.
+    pub(crate) fn create(credentials: &SteamCredentials, two_factor_code: Option<&str>) -> Result<Self, SteamError> {
+        Self::create_in(&std::env::temp_dir(), credentials, two_factor_code)
+    }
+
+    pub(crate) fn create_in(
+        dir: &Path,
+        credentials: &SteamCredentials,
+        two_factor_code: Option<&str>
+    ) -> Result<Self, SteamError> {
+        let mut login = format!("login {} {}", quote(&credentials.username), quote(&credentials.password));
+        if let Some(code) = two_factor_code {
+            login.push(' ');
+            login.push_str(&quote(code));
+        }
+        Self::commands_in(dir, &[login, "quit".to_string()])
+    }
+
+    /// Writes a script of console commands, one per line, into the system temp directory.
+    pub(crate) fn commands(lines: &[String]) -> Result<Self, SteamError> {
+        Self::commands_in(&std::env::temp_dir(), lines)
+    }
+
+    pub(crate) fn commands_in(dir: &Path, lines: &[String]) -> Result<Self, SteamError> {
+        let path = dir.join(format!(
+            "updateio-login-{}-{}.txt",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)
+            .map_err(|e| SteamError::io("Failed to create login script", e))?;
+        // From here on a failed write still removes the file
+        let script = LoginScript { path };
+
+        writeln!(file, "{}", lines.join("\n"))
+            .and_then(|_| file.sync_all())
+            .map_err(|e| SteamError::io("Failed to write login script", e))?;
+
+        dev_log!("Created login script {:?}", script.path);
+        Ok(script)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The SteamCMD invocation that runs this script.
+    pub(crate) fn spec(&self, steamcmd_path: &Path) -> ProcessSpec {
+        ProcessSpec::new(steamcmd_path)
+            .arg("+runscript")
+            .arg(self.path().display().to_string())
+    }
+}
+
+impl Drop for LoginScript {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            dev_log!("Failed to remove login script {:?}: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{self, Limits, ProcessRunner, SystemRunner};
+
+    const PASSWORD: &str = "hunter2 with spaces";
+    const CODE: &str = "X45RP";
+
+    fn credentials() -> SteamCredentials {
+        SteamCredentials {
+            username: "venue_bot".to_string(),
+            password: PASSWORD.to_string(),
+            two_factor_code: None,
+            shared_secret: None,
+        }
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("updateio-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn command_arguments_never_contain_the_secrets() {
+        let dir = scratch_dir("argv");
+        let script = LoginScript::create_in(&dir, &credentials(), Some(CODE)).unwrap();
+
+        let args = script.spec(Path::new("/opt/steamcmd/steamcmd.sh")).args;
+
+        assert_eq!(args, vec!["+runscript".to_string(), script.path().display().to_string()]);
+        assert!(args.iter().all(|arg| !arg.contains("hunter2") && !arg.contains(CODE)));
+        drop(script);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn script_holds_the_login_and_quits() {
+        let dir = scratch_dir("content");
+        let script = LoginScript::create_in(&dir, &credentials(), Some(CODE)).unwrap();
+
+        let content = fs::read_to_string(script.path()).unwrap();
+
+        assert_eq!(content, format!("login venue_bot \"{}\" {}\nquit\n", PASSWORD, CODE));
+        drop(script);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn script_is_readable_only_by_the_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = scratch_dir("mode");
+        let script = LoginScript::create_in(&dir, &credentials(), None).unwrap();
+
+        let mode = fs::metadata(script.path()).unwrap().permissions().mode();
+
+        assert_eq!(mode & 0o777, 0o600);
+        drop(script);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn script_is_removed_on_drop() {
+        let dir = scratch_dir("drop");
+        let script = LoginScript::create_in(&dir, &credentials(), None).unwrap();
+        let path = script.path().to_path_buf();
+
+        drop(script);
+
+        assert!(!path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn spawned_process_sees_no_secret_in_its_argv() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // A stand-in for steamcmd.sh that records the arguments it was started with
+        let dir = scratch_dir("spawn");
+        let fake = dir.join("steamcmd.sh");
+        let recorded = dir.join("argv.txt");
+        fs::write(&fake, format!("#!/bin/sh\nprintf '%s\\n' \"$0\" \"$@\" > '{}'\n", recorded.display())).unwrap();
+        fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+        let script = LoginScript::create_in(&dir, &credentials(), Some(CODE)).unwrap();
+
+        let spawned = SystemRunner.spawn(&script.spec(&fake)).unwrap();
+        let output = runner::collect(spawned, &Limits::default(), |_| {}).await.unwrap();
+
+        assert!(output.success());
+        let argv = fs::read_to_string(&recorded).unwrap();
+        assert!(argv.contains("+runscript"));
+        assert!(!argv.contains("hunter2"));
+        assert!(!argv.contains(CODE));
+        drop(script);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
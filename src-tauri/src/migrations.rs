@@ -0,0 +1,146 @@
+use rusqlite::Connection;
+use crate::error::SteamError;
+
+/// Forward-only schema migrations. The database's `PRAGMA user_version` is the
+/// number of migrations already applied, so entries must never be reordered or
+/// edited once released; add a new file instead.
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/0001_initial.sql"),
+    include_str!("../migrations/0002_update_history.sql"),
+    include_str!("../migrations/0003_install_dirs.sql"),
+    include_str!("../migrations/0004_shared_secrets.sql"),
+    include_str!("../migrations/0005_job_options.sql"),
+    include_str!("../migrations/0006_app_branches.sql"),
+    include_str!("../migrations/0007_target_platforms.sql"),
+    include_str!("../migrations/0008_history_output.sql"),
+];
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Migrations] {}", format!($($arg)*));
+    }
+}
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+pub fn current_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Brings the schema up to date, refusing databases written by a newer app.
+pub fn run(conn: &mut Connection) -> Result<(), SteamError> {
+    let current = current_version(conn)
+        .map_err(|e| SteamError::Database { reason: format!("Failed to read schema version: {}", e) })?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(SteamError::DatabaseTooNew { version: current, supported: latest });
+    }
+
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index as u32 + 1;
+        dev_log!("Applying migration {}", version);
+
+        let tx = conn.transaction()
+            .map_err(|e| SteamError::Database { reason: format!("Failed to start migration {}: {}", version, e) })?;
+        tx.execute_batch(sql)
+            .map_err(|e| SteamError::Database { reason: format!("Migration {} failed: {}", version, e) })?;
+        tx.pragma_update(None, "user_version", version)
+            .map_err(|e| SteamError::Database { reason: format!("Failed to record migration {}: {}", version, e) })?;
+        tx.commit()
+            .map_err(|e| SteamError::Database { reason: format!("Failed to commit migration {}: {}", version, e) })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_exists(conn: &Connection, name: &str) -> bool {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+            |row| row.get::<_, i64>(0),
+        ).unwrap() == 1
+    }
+
+    #[test]
+    fn a_fresh_database_reaches_the_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        run(&mut conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        for table in ["steam_credentials", "update_jobs", "update_history", "app_install_dirs", "app_branches"] {
+            assert!(table_exists(&conn, table), "{} is missing", table);
+        }
+
+        // Running again on an up to date database is a no-op
+        run(&mut conn).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn a_database_from_before_versioning_is_upgraded() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE steam_credentials (
+                id INTEGER PRIMARY KEY,
+                username TEXT NOT NULL UNIQUE,
+                password TEXT NOT NULL
+            );
+            INSERT INTO steam_credentials (username, password) VALUES ('club', 'sealed');"
+        ).unwrap();
+
+        run(&mut conn).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        let (username, password, shared_secret): (String, String, Option<String>) = conn.query_row(
+            "SELECT username, password, shared_secret FROM steam_credentials",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        ).unwrap();
+        assert_eq!((username.as_str(), password.as_str(), shared_secret), ("club", "sealed", None));
+    }
+
+    #[test]
+    fn jobs_queued_before_options_keep_validating() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        for sql in &MIGRATIONS[..4] {
+            conn.execute_batch(sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 4).unwrap();
+        conn.execute_batch(
+            "INSERT INTO update_jobs (app_id, status, position, created_at) VALUES (10, 'queued', 1, 0);
+            INSERT INTO update_jobs (app_id, status, position, created_at) VALUES (20, 'completed', 2, 0);"
+        ).unwrap();
+
+        run(&mut conn).unwrap();
+
+        let validate = |app_id: u32| -> bool {
+            conn.query_row("SELECT validate FROM update_jobs WHERE app_id = ?1", [app_id], |row| row.get(0)).unwrap()
+        };
+        assert!(validate(10));
+        assert!(!validate(20));
+    }
+
+    #[test]
+    fn refuses_a_database_from_a_newer_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+        let result = run(&mut conn);
+
+        assert!(matches!(
+            result,
+            Err(SteamError::DatabaseTooNew { version, supported }) if version == latest_version() + 1 && supported == latest_version()
+        ));
+        assert!(!table_exists(&conn, "update_jobs"));
+    }
+}
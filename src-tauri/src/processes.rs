@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use serde::Serialize;
+use crate::error::SteamError;
+use crate::runner::{self, CancelToken};
+use crate::session::SessionManager;
+
+/// How long `cancel_update` waits for the process to actually go away.
+const EXIT_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Processes] {}", format!($($arg)*));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessState {
+    Running,
+    Paused,
+    Cancelling,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessStatus {
+    pub app_id: u32,
+    pub pid: u32,
+    pub state: ProcessState,
+}
+
+struct RunningProcess {
+    pid: u32,
+    /// Makes the task that owns the process kill it.
+    cancel: CancelToken,
+    state: ProcessState,
+}
+
+/// SteamCMD processes that are currently updating a game, keyed by app id.
+#[derive(Default)]
+pub struct ProcessRegistry {
+    processes: Mutex<HashMap<u32, RunningProcess>>,
+    /// Woken whenever a process is removed.
+    removed: Notify,
+}
+
+/// Keeps a process registered until the owner has waited for it.
+pub struct Registration<'a> {
+    registry: &'a ProcessRegistry,
+    app_id: u32,
+    done: bool,
+}
+
+impl Registration<'_> {
+    /// Removes the process from the registry and tells whether it was cancelled.
+    pub fn finish(mut self) -> bool {
+        self.done = true;
+        self.registry.remove(self.app_id) == Some(ProcessState::Cancelling)
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.registry.remove(self.app_id);
+        }
+    }
+}
+
+impl ProcessRegistry {
+    pub fn register(&self, app_id: u32, pid: u32, cancel: CancelToken) -> Result<Registration<'_>, SteamError> {
+        let mut processes = self.processes.lock()
+            .map_err(|e| SteamError::internal("Failed to lock process registry", e))?;
+
+        if processes.contains_key(&app_id) {
+            return Err(SteamError::UpdateAlreadyRunning { app_id });
+        }
+
+        processes.insert(app_id, RunningProcess { pid, cancel, state: ProcessState::Running });
+        Ok(Registration { registry: self, app_id, done: false })
+    }
+
+    fn remove(&self, app_id: u32) -> Option<ProcessState> {
+        let state = self.processes.lock().ok()?.remove(&app_id).map(|process| process.state);
+        self.removed.notify_waiters();
+        state
+    }
+
+    /// Waits until the process for `app_id` is gone; false if it outlived `deadline`.
+    async fn wait_removed(&self, app_id: u32, deadline: Instant) -> bool {
+        loop {
+            let removed = self.removed.notified();
+            if !self.is_running(app_id) {
+                return true;
+            }
+            tokio::select! {
+                _ = removed => {}
+                _ = runner::sleep_until(Some(deadline)) => return !self.is_running(app_id),
+            }
+        }
+    }
+
+    fn is_running(&self, app_id: u32) -> bool {
+        self.processes.lock()
+            .map(|processes| processes.contains_key(&app_id))
+            .unwrap_or(false)
+    }
+
+    pub fn list(&self) -> Vec<ProcessStatus> {
+        self.processes.lock()
+            .map(|processes| {
+                processes.iter()
+                    .map(|(&app_id, process)| ProcessStatus {
+                        app_id,
+                        pid: process.pid,
+                        state: process.state,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Marks the process as being cancelled; returns its pid, whether it was
+    /// paused and the token that makes its owner kill it.
+    fn start_cancelling(&self, app_id: u32) -> Result<(u32, bool, CancelToken), SteamError> {
+        self.with_process(app_id, |process| {
+            let was_paused = process.state == ProcessState::Paused;
+            process.state = ProcessState::Cancelling;
+            Ok((process.pid, was_paused, process.cancel.clone()))
+        })
+    }
+
+    /// Stops a running process tree; returns its pid.
+    fn pause(&self, app_id: u32) -> Result<u32, SteamError> {
+        self.with_process(app_id, |process| {
+            if process.state != ProcessState::Running {
+                return Err(SteamError::UpdateNotRunning { app_id });
+            }
+            suspend(process.pid)?;
+            process.state = ProcessState::Paused;
+            process.cancel.set_paused(true);
+            Ok(process.pid)
+        })
+    }
+
+    /// Continues a paused process tree; returns its pid.
+    fn resume(&self, app_id: u32) -> Result<u32, SteamError> {
+        self.with_process(app_id, |process| {
+            if process.state != ProcessState::Paused {
+                return Err(SteamError::InvalidInput {
+                    reason: format!("Update for app {} is not paused", app_id),
+                });
+            }
+            resume(process.pid)?;
+            process.state = ProcessState::Running;
+            process.cancel.set_paused(false);
+            Ok(process.pid)
+        })
+    }
+
+    /// Applies `f` to the process registered for `app_id`.
+    fn with_process<T>(
+        &self,
+        app_id: u32,
+        f: impl FnOnce(&mut RunningProcess) -> Result<T, SteamError>
+    ) -> Result<T, SteamError> {
+        let mut processes = self.processes.lock()
+            .map_err(|e| SteamError::internal("Failed to lock process registry", e))?;
+        let process = processes.get_mut(&app_id)
+            .ok_or(SteamError::UpdateNotRunning { app_id })?;
+        f(process)
+    }
+}
+
+/// Makes sure nothing else is running from the SteamCMD directory, stopping
+/// idle sessions on the way.
+///
+/// Two SteamCMD instances in one directory fight over its lock files, so
+/// anything that starts its own fails with `SteamCmdBusy` while an update or
+/// a session command is running.
+pub(crate) async fn ensure_steamcmd_idle(app: &AppHandle) -> Result<(), SteamError> {
+    if !app.state::<ProcessRegistry>().list().is_empty() {
+        return Err(SteamError::SteamCmdBusy);
+    }
+    app.state::<SessionManager>().stop_idle().await
+}
+
+/// Puts the child into its own process group so the whole tree can be signalled.
+pub(crate) fn isolate_process_tree(command: &mut Command) -> &mut Command {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command
+}
+
+#[cfg(unix)]
+fn signal_process_tree(pid: u32, signal: libc::c_int) -> Result<(), SteamError> {
+    // The child leads its own process group, so a negative pid reaches every descendant
+    let result = unsafe { libc::kill(-(pid as libc::pid_t), signal) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(SteamError::io(&format!("Failed to signal process {}", pid), std::io::Error::last_os_error()))
+    }
+}
+
+pub(crate) async fn kill_process_tree(pid: u32) -> Result<(), SteamError> {
+    #[cfg(unix)]
+    {
+        signal_process_tree(pid, libc::SIGKILL)
+    }
+    #[cfg(windows)]
+    {
+        let output = tokio::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .output()
+            .await
+            .map_err(|e| SteamError::io("Failed to run taskkill", e))?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(SteamError::Io {
+                reason: format!(
+                    "Failed to kill process {}: {}",
+                    pid,
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+            })
+        }
+    }
+}
+
+fn emit_state(app: &AppHandle, app_id: u32, pid: u32, state: ProcessState) {
+    if let Err(e) = app.emit("steam-update-state", ProcessStatus { app_id, pid, state }) {
+        dev_log!("Failed to emit process state event: {}", e);
+    }
+}
+
+pub(crate) async fn cancel_update(app: AppHandle, app_id: u32) -> Result<ProcessStatus, SteamError> {
+    let registry = app.state::<ProcessRegistry>();
+
+    let (pid, was_paused, cancel) = registry.start_cancelling(app_id)?;
+    emit_state(&app, app_id, pid, ProcessState::Cancelling);
+
+    #[cfg(unix)]
+    if was_paused {
+        // Continue a stopped tree so it exits like any other once killed
+        let _ = signal_process_tree(pid, libc::SIGCONT);
+    }
+    #[cfg(not(unix))]
+    let _ = was_paused;
+
+    // The task running the update kills the process tree and deregisters it
+    dev_log!("Cancelling SteamCMD process tree {} for app {}", pid, app_id);
+    cancel.cancel();
+
+    if !registry.wait_removed(app_id, Instant::now() + EXIT_TIMEOUT).await {
+        return Err(SteamError::Internal {
+            reason: format!("SteamCMD for app {} did not exit after being killed", app_id),
+        });
+    }
+
+    emit_state(&app, app_id, pid, ProcessState::Cancelled);
+    Ok(ProcessStatus { app_id, pid, state: ProcessState::Cancelled })
+}
+
+pub(crate) async fn pause_update(app: AppHandle, app_id: u32) -> Result<ProcessStatus, SteamError> {
+    let pid = app.state::<ProcessRegistry>().pause(app_id)?;
+
+    emit_state(&app, app_id, pid, ProcessState::Paused);
+    Ok(ProcessStatus { app_id, pid, state: ProcessState::Paused })
+}
+
+pub(crate) async fn resume_update(app: AppHandle, app_id: u32) -> Result<ProcessStatus, SteamError> {
+    let pid = app.state::<ProcessRegistry>().resume(app_id)?;
+
+    emit_state(&app, app_id, pid, ProcessState::Running);
+    Ok(ProcessStatus { app_id, pid, state: ProcessState::Running })
+}
+
+pub(crate) async fn list_running_updates(app: AppHandle) -> Result<Vec<ProcessStatus>, SteamError> {
+    Ok(app.state::<ProcessRegistry>().list())
+}
+
+#[cfg(unix)]
+fn suspend(pid: u32) -> Result<(), SteamError> {
+    signal_process_tree(pid, libc::SIGSTOP)
+}
+
+#[cfg(unix)]
+fn resume(pid: u32) -> Result<(), SteamError> {
+    signal_process_tree(pid, libc::SIGCONT)
+}
+
+/// Suspends and resumes processes the way Task Manager and Process Explorer
+/// do. Windows has no process groups, so unlike on Unix only the process
+/// itself is stopped, not any children it started.
+#[cfg(windows)]
+mod nt {
+    use std::ffi::c_void;
+
+    pub const PROCESS_SUSPEND_RESUME: u32 = 0x0800;
+
+    pub type Handle = *mut c_void;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn OpenProcess(desired_access: u32, inherit_handle: i32, process_id: u32) -> Handle;
+        pub fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        pub fn NtSuspendProcess(process: Handle) -> i32;
+        pub fn NtResumeProcess(process: Handle) -> i32;
+    }
+}
+
+#[cfg(windows)]
+fn suspend(pid: u32) -> Result<(), SteamError> {
+    with_process_handle(pid, "suspend", nt::NtSuspendProcess)
+}
+
+#[cfg(windows)]
+fn resume(pid: u32) -> Result<(), SteamError> {
+    with_process_handle(pid, "resume", nt::NtResumeProcess)
+}
+
+#[cfg(windows)]
+fn with_process_handle(
+    pid: u32,
+    action: &str,
+    f: unsafe extern "system" fn(nt::Handle) -> i32
+) -> Result<(), SteamError> {
+    let handle = unsafe { nt::OpenProcess(nt::PROCESS_SUSPEND_RESUME, 0, pid) };
+    if handle.is_null() {
+        return Err(SteamError::io(&format!("Failed to open process {}", pid), std::io::Error::last_os_error()));
+    }
+
+    // A negative NTSTATUS is an error
+    let status = unsafe { f(handle) };
+    unsafe { nt::CloseHandle(handle) };
+    if status >= 0 {
+        Ok(())
+    } else {
+        Err(SteamError::Io { reason: format!("Failed to {} process {}: NTSTATUS {:#010x}", action, pid, status) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(registry: &ProcessRegistry, app_id: u32) -> Option<ProcessState> {
+        registry.list().into_iter().find(|process| process.app_id == app_id).map(|process| process.state)
+    }
+
+    #[test]
+    fn an_app_can_only_be_registered_once() {
+        let registry = ProcessRegistry::default();
+        let _first = registry.register(740, 100, CancelToken::default()).unwrap();
+
+        let second = registry.register(740, 200, CancelToken::default());
+
+        assert!(matches!(second, Err(SteamError::UpdateAlreadyRunning { app_id: 740 })));
+        let listed = registry.list();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].pid, listed[0].state), (100, ProcessState::Running));
+    }
+
+    #[test]
+    fn finish_reports_a_cancelled_process() {
+        let registry = ProcessRegistry::default();
+        let cancel = CancelToken::default();
+        let cancelled = registry.register(740, 100, cancel.clone()).unwrap();
+        let finished = registry.register(90, 200, CancelToken::default()).unwrap();
+
+        let (pid, was_paused, token) = registry.start_cancelling(740).unwrap();
+        assert_eq!((pid, was_paused), (100, false));
+        assert_eq!(state(&registry, 740), Some(ProcessState::Cancelling));
+        token.cancel();
+        assert!(cancel.is_cancelled());
+
+        assert!(cancelled.finish());
+        assert!(!finished.finish());
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn dropping_a_registration_deregisters_the_process() {
+        let registry = ProcessRegistry::default();
+        let registration = registry.register(740, 100, CancelToken::default()).unwrap();
+
+        drop(registration);
+
+        assert!(registry.list().is_empty());
+        assert!(matches!(registry.start_cancelling(740), Err(SteamError::UpdateNotRunning { app_id: 740 })));
+        registry.register(740, 200, CancelToken::default()).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiting_for_removal_times_out() {
+        let registry = ProcessRegistry::default();
+        let registration = registry.register(740, 100, CancelToken::default()).unwrap();
+        let started = Instant::now();
+
+        assert!(!registry.wait_removed(740, started + EXIT_TIMEOUT).await);
+        assert_eq!(started.elapsed(), EXIT_TIMEOUT);
+
+        drop(registration);
+        assert!(registry.wait_removed(740, Instant::now() + EXIT_TIMEOUT).await);
+    }
+
+    #[test]
+    fn pause_and_resume_need_the_right_state() {
+        let registry = ProcessRegistry::default();
+        let _registration = registry.register(740, 100, CancelToken::default()).unwrap();
+
+        // Neither touches the process when the state is wrong
+        assert!(matches!(registry.resume(740), Err(SteamError::InvalidInput { .. })));
+        registry.start_cancelling(740).unwrap();
+        assert!(matches!(registry.pause(740), Err(SteamError::UpdateNotRunning { app_id: 740 })));
+        assert!(matches!(registry.resume(740), Err(SteamError::InvalidInput { .. })));
+        assert!(matches!(registry.pause(90), Err(SteamError::UpdateNotRunning { app_id: 90 })));
+        assert_eq!(state(&registry, 740), Some(ProcessState::Cancelling));
+    }
+
+    /// Waits for the child's next stop or continue without reaping it.
+    #[cfg(unix)]
+    fn wait_status(pid: u32, options: libc::c_int) -> libc::c_int {
+        let mut status = 0;
+        let waited = unsafe { libc::waitpid(pid as libc::pid_t, &mut status, options) };
+        assert_eq!(waited, pid as libc::pid_t);
+        status
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn signals_the_process_group() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        let mut child = isolate_process_tree(&mut command).spawn().unwrap();
+        let pid = child.id();
+        let registry = ProcessRegistry::default();
+        let cancel = CancelToken::default();
+        let _registration = registry.register(740, pid, cancel.clone()).unwrap();
+
+        assert_eq!(registry.pause(740).unwrap(), pid);
+        assert!(libc::WIFSTOPPED(wait_status(pid, libc::WUNTRACED)));
+        assert_eq!(state(&registry, 740), Some(ProcessState::Paused));
+        assert!(cancel.is_paused());
+        assert!(matches!(registry.pause(740), Err(SteamError::UpdateNotRunning { .. })));
+
+        assert_eq!(registry.resume(740).unwrap(), pid);
+        assert!(libc::WIFCONTINUED(wait_status(pid, libc::WCONTINUED)));
+        assert_eq!(state(&registry, 740), Some(ProcessState::Running));
+        assert!(!cancel.is_paused());
+
+        kill_process_tree(pid).await.unwrap();
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGKILL));
+    }
+}
@@ -0,0 +1,266 @@
+use serde::Serialize;
+
+/// Phase of an `app_update` as reported by SteamCMD's "Update state" lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateState {
+    Reconfiguring,
+    Verifying,
+    Preallocating,
+    Downloading,
+    Committing,
+    Completed,
+    Unknown,
+}
+
+impl UpdateState {
+    fn from_label(label: &str) -> Self {
+        let label = label.trim().to_ascii_lowercase();
+        if label.starts_with("downloading") {
+            UpdateState::Downloading
+        } else if label.starts_with("verifying") || label.starts_with("validating") {
+            UpdateState::Verifying
+        } else if label.starts_with("preallocating") {
+            UpdateState::Preallocating
+        } else if label.starts_with("committing") {
+            UpdateState::Committing
+        } else if label.starts_with("reconfiguring") {
+            UpdateState::Reconfiguring
+        } else {
+            UpdateState::Unknown
+        }
+    }
+}
+
+/// Payload of the `steam-update-progress` event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpdateProgress {
+    pub app_id: u32,
+    pub state: UpdateState,
+    pub state_code: u32,
+    pub percent: f64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+/// A SteamCMD output line that carries information about an update.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputLine {
+    Progress {
+        state: UpdateState,
+        state_code: u32,
+        percent: f64,
+        bytes_done: u64,
+        bytes_total: u64,
+    },
+    /// `Success! App '<id>' fully installed.` or `... already up to date.`
+    AppInstalled { app_id: u32 },
+    /// `Error! App '<id>' state is 0x<code> after update job.`
+    AppFailed { app_id: u32, state_code: Option<u32>, reason: String },
+}
+
+impl OutputLine {
+    /// Turns the line into a progress event for `app_id`, if it describes progress.
+    pub fn to_progress(&self, app_id: u32) -> Option<UpdateProgress> {
+        match *self {
+            OutputLine::Progress { state, state_code, percent, bytes_done, bytes_total } => {
+                Some(UpdateProgress { app_id, state, state_code, percent, bytes_done, bytes_total })
+            }
+            OutputLine::AppInstalled { app_id: installed } => Some(UpdateProgress {
+                app_id: installed,
+                state: UpdateState::Completed,
+                state_code: 0,
+                percent: 100.0,
+                bytes_done: 0,
+                bytes_total: 0,
+            }),
+            OutputLine::AppFailed { .. } => None,
+        }
+    }
+}
+
+/// Parses a single line of SteamCMD output.
+///
+/// Recognised forms:
+/// ` Update state (0x61) downloading, progress: 45.32 (1234567 / 2724048)`
+/// `Success! App '740' fully installed.`
+/// `Error! App '740' state is 0x202 after update job.`
+pub fn parse_line(line: &str) -> Option<OutputLine> {
+    let line = line.trim();
+
+    if let Some(rest) = line.strip_prefix("Update state") {
+        return parse_update_state(rest);
+    }
+
+    if let Some(rest) = line.strip_prefix("Success!") {
+        let app_id = quoted_app_id(rest)?;
+        return Some(OutputLine::AppInstalled { app_id });
+    }
+
+    if let Some(rest) = line.strip_prefix("Error!") {
+        let app_id = quoted_app_id(rest)?;
+        let state_code = rest
+            .split_once("state is ")
+            .and_then(|(_, tail)| tail.split_whitespace().next())
+            .and_then(parse_hex);
+        return Some(OutputLine::AppFailed {
+            app_id,
+            state_code,
+            reason: rest.trim().to_string(),
+        });
+    }
+
+    None
+}
+
+/// Reads how many files failed validation from a SteamCMD or content log line.
+///
+/// Recognised forms:
+/// `3 files failed to validate and will be reacquired.`
+/// `Validation: 12 files failed validation`
+/// `AppID 740 validation found 2 corrupt files`
+pub fn parse_validation_failures(line: &str) -> Option<u64> {
+    let lower = line.to_ascii_lowercase();
+    if !lower.contains("valid") || !(lower.contains("fail") || lower.contains("corrupt")) {
+        return None;
+    }
+
+    // The count is the number right before "files", possibly with "corrupt" in between
+    let words: Vec<&str> = lower.split_whitespace().collect();
+    words.iter()
+        .position(|word| word.starts_with("file"))
+        .and_then(|files| {
+            words[..files].iter().rev()
+                .take(2)
+                .find_map(|word| word.trim_matches(|c: char| !c.is_ascii_digit()).parse().ok())
+        })
+}
+
+fn parse_update_state(rest: &str) -> Option<OutputLine> {
+    // (0x61) downloading, progress: 45.32 (1234567 / 2724048)
+    let rest = rest.trim_start().strip_prefix('(')?;
+    let (code, rest) = rest.split_once(')')?;
+    let state_code = parse_hex(code)?;
+
+    let (label, rest) = rest.split_once(',').unwrap_or((rest, ""));
+    let state = UpdateState::from_label(label);
+
+    let rest = rest.trim_start().strip_prefix("progress:").unwrap_or(rest).trim_start();
+    let (percent, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let percent = percent.parse::<f64>().unwrap_or(0.0);
+
+    let (bytes_done, bytes_total) = rest
+        .trim()
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .and_then(|s| s.split_once('/'))
+        .map(|(done, total)| {
+            (
+                done.trim().parse::<u64>().unwrap_or(0),
+                total.trim().parse::<u64>().unwrap_or(0),
+            )
+        })
+        .unwrap_or((0, 0));
+
+    Some(OutputLine::Progress { state, state_code, percent, bytes_done, bytes_total })
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    let value = value.trim().trim_end_matches(|c: char| !c.is_ascii_hexdigit());
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X"))?;
+    u32::from_str_radix(digits, 16).ok()
+}
+
+fn quoted_app_id(text: &str) -> Option<u32> {
+    let (_, rest) = text.split_once("App '")?;
+    let (id, _) = rest.split_once('\'')?;
+    id.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(line: &str) -> (UpdateState, u32, f64, u64, u64) {
+        match parse_line(line) {
+            Some(OutputLine::Progress { state, state_code, percent, bytes_done, bytes_total }) => {
+                (state, state_code, percent, bytes_done, bytes_total)
+            }
+            other => panic!("{:?} is not progress: {:?}", line, other),
+        }
+    }
+
+    #[test]
+    fn parses_each_update_state() {
+        let lines = [
+            (" Update state (0x3) reconfiguring, progress: 0.00 (0 / 0)", UpdateState::Reconfiguring, 0x3),
+            (" Update state (0x5) verifying install, progress: 12.50 (125 / 1000)", UpdateState::Verifying, 0x5),
+            (" Update state (0x11) preallocating, progress: 3.00 (30 / 1000)", UpdateState::Preallocating, 0x11),
+            (" Update state (0x61) downloading, progress: 45.32 (1234567 / 2724048)", UpdateState::Downloading, 0x61),
+            (" Update state (0x81) verifying update, progress: 99.00 (990 / 1000)", UpdateState::Verifying, 0x81),
+            (" Update state (0x101) committing, progress: 100.00 (1000 / 1000)", UpdateState::Committing, 0x101),
+            (" Update state (0x4021) stalled, progress: 1.00 (10 / 1000)", UpdateState::Unknown, 0x4021),
+        ];
+
+        for (line, state, code) in lines {
+            let (parsed_state, parsed_code, ..) = progress(line);
+            assert_eq!((parsed_state, parsed_code), (state, code), "{}", line);
+        }
+    }
+
+    #[test]
+    fn parses_percent_and_bytes() {
+        assert_eq!(
+            progress(" Update state (0x61) downloading, progress: 45.32 (1234567 / 2724048)"),
+            (UpdateState::Downloading, 0x61, 45.32, 1234567, 2724048)
+        );
+        assert_eq!(
+            progress("Update state (0x61) downloading, progress: 0.00 (0 / 0)"),
+            (UpdateState::Downloading, 0x61, 0.0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn parses_success_and_error_lines() {
+        assert_eq!(parse_line("Success! App '740' fully installed."), Some(OutputLine::AppInstalled { app_id: 740 }));
+        assert_eq!(parse_line("Success! App '740' already up to date."), Some(OutputLine::AppInstalled { app_id: 740 }));
+        assert_eq!(
+            parse_line("Error! App '740' state is 0x202 after update job."),
+            Some(OutputLine::AppFailed {
+                app_id: 740,
+                state_code: Some(0x202),
+                reason: "App '740' state is 0x202 after update job.".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_line("Error! App '740' No subscription"),
+            Some(OutputLine::AppFailed { app_id: 740, state_code: None, reason: "App '740' No subscription".to_string() })
+        );
+    }
+
+    #[test]
+    fn ignores_malformed_update_state_lines() {
+        assert_eq!(parse_line("Update state"), None);
+        assert_eq!(parse_line("Update state downloading, progress: 45.32 (1 / 2)"), None);
+        assert_eq!(parse_line("Update state (0x61 downloading, progress: 45.32 (1 / 2)"), None);
+        assert_eq!(parse_line("Update state (61) downloading, progress: 45.32 (1 / 2)"), None);
+        assert_eq!(parse_line("Update state (0xzz) downloading"), None);
+        assert_eq!(parse_line("Loading Steam API...OK"), None);
+        assert_eq!(parse_line("Success! Nothing to see here"), None);
+
+        // A readable state with garbled numbers still reports the phase
+        assert_eq!(
+            progress("Update state (0x61) downloading, progress: n/a (lots / more)"),
+            (UpdateState::Downloading, 0x61, 0.0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn reads_validation_failure_counts() {
+        assert_eq!(parse_validation_failures("3 files failed to validate and will be reacquired."), Some(3));
+        assert_eq!(parse_validation_failures("Validation: 12 files failed validation"), Some(12));
+        assert_eq!(parse_validation_failures("AppID 740 validation found 2 corrupt files"), Some(2));
+        assert_eq!(parse_validation_failures("Validation finished, no files failed"), None);
+        assert_eq!(parse_validation_failures(" Update state (0x61) downloading, progress: 1.00 (1 / 100)"), None);
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+use serde::{Deserialize, Serialize};
+use crate::accounts;
+use crate::db;
+use crate::disk_space;
+use crate::error::SteamError;
+use crate::processes;
+use crate::steam::{self, UpdateOptions};
+
+const CONCURRENCY_SETTING: &str = "queue_concurrency";
+const DEFAULT_CONCURRENCY: usize = 1;
+const MAX_CONCURRENCY: usize = 8;
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[UpdateQueue] {}", format!($($arg)*));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Completed => "completed",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "queued" => Some(JobStatus::Queued),
+            "running" => Some(JobStatus::Running),
+            "completed" => Some(JobStatus::Completed),
+            "failed" => Some(JobStatus::Failed),
+            "cancelled" => Some(JobStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UpdateJob {
+    pub id: i64,
+    pub app_id: u32,
+    pub username: Option<String>,
+    pub status: JobStatus,
+    pub position: i64,
+    pub message: Option<String>,
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub options: UpdateOptions,
+}
+
+/// Scheduler state shared between the Tauri commands and the dispatch loop.
+pub struct UpdateQueue {
+    wakeup: Notify,
+    concurrency: AtomicUsize,
+    running: Mutex<HashSet<i64>>,
+    cancelled: Mutex<HashSet<i64>>,
+}
+
+impl UpdateQueue {
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            wakeup: Notify::new(),
+            concurrency: AtomicUsize::new(concurrency.clamp(1, MAX_CONCURRENCY)),
+            running: Mutex::new(HashSet::new()),
+            cancelled: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn wake(&self) {
+        self.wakeup.notify_one();
+    }
+
+    /// Marks a running job as cancelled while `cancel` stops it.
+    ///
+    /// The mark is dropped again if stopping fails, otherwise a job that keeps
+    /// running would be recorded as cancelled when it later fails.
+    async fn cancel_running<T>(
+        &self,
+        job_id: i64,
+        cancel: impl Future<Output = Result<T, SteamError>>
+    ) -> Result<T, SteamError> {
+        if let Ok(mut cancelled) = self.cancelled.lock() {
+            cancelled.insert(job_id);
+        }
+        let result = cancel.await;
+        if result.is_err() {
+            self.take_cancelled(job_id);
+        }
+        result
+    }
+
+    /// Whether the job was cancelled by the user, forgetting the mark.
+    fn take_cancelled(&self, job_id: i64) -> bool {
+        self.cancelled.lock()
+            .map(|mut cancelled| cancelled.remove(&job_id))
+            .unwrap_or(false)
+    }
+}
+
+pub(crate) fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+pub(crate) fn load_concurrency() -> usize {
+    db::get_setting(CONCURRENCY_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_CONCURRENCY)
+}
+
+/// Requeues jobs interrupted by the previous shutdown and starts the dispatch loop.
+pub(crate) fn start(app: AppHandle) -> Result<(), SteamError> {
+    let requeued = db::requeue_interrupted_jobs()?;
+    if requeued > 0 {
+        dev_log!("Requeued {} job(s) interrupted by the previous shutdown", requeued);
+    }
+
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = dispatch(&app) {
+                dev_log!("Failed to dispatch jobs: {}", e);
+            }
+            app.state::<UpdateQueue>().wakeup.notified().await;
+        }
+    });
+
+    Ok(())
+}
+
+fn dispatch(app: &AppHandle) -> Result<(), SteamError> {
+    let queue = app.state::<UpdateQueue>();
+    let mut running = queue.running.lock()
+        .map_err(|e| SteamError::internal("Failed to lock running jobs", e))?;
+
+    let limit = queue.concurrency.load(Ordering::SeqCst);
+    if running.len() >= limit {
+        return Ok(());
+    }
+
+    let queued = db::list_jobs_with_status(JobStatus::Queued)?;
+    let running_jobs = db::list_jobs_with_status(JobStatus::Running)?;
+
+    // Never run two jobs for the same app at once, they would share an install directory
+    let mut busy_apps: HashSet<u32> = running_jobs.iter().map(|job| job.app_id).collect();
+
+    for job in queued {
+        if running.len() >= limit {
+            break;
+        }
+        if !busy_apps.insert(job.app_id) {
+            continue;
+        }
+
+        db::mark_job_started(job.id, now())?;
+        running.insert(job.id);
+        emit_job(app, job.id);
+
+        dev_log!("Starting job {} for app {}", job.id, job.app_id);
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            run_job(app, job).await;
+        });
+    }
+
+    Ok(())
+}
+
+async fn run_job(app: AppHandle, job: UpdateJob) {
+    // An explicit account wins, otherwise the app's mapped account or anonymous login
+    let credentials = match &job.username {
+        Some(username) => accounts::load_account(&app, username).map(Some),
+        None => accounts::credentials_for_app(&app, job.app_id),
+    };
+    let result = match credentials {
+        Ok(Some(credentials)) => {
+            steam::update_game_authenticated(app.clone(), job.app_id, credentials, job.options.clone()).await
+        }
+        Ok(None) => steam::update_game(app.clone(), job.app_id, job.options.clone()).await,
+        Err(e) => Err(e),
+    };
+
+    let queue = app.state::<UpdateQueue>();
+    let cancelled = queue.take_cancelled(job.id);
+
+    let (status, message) = match result {
+        Ok(message) => (JobStatus::Completed, message),
+        Err(_) if cancelled => (JobStatus::Cancelled, "Cancelled by user".to_string()),
+        Err(e) => (JobStatus::Failed, e.to_string()),
+    };
+    dev_log!("Job {} finished as {}: {}", job.id, status.as_str(), message);
+
+    if let Err(e) = db::mark_job_finished(job.id, status, Some(&message), now()) {
+        dev_log!("Failed to record result of job {}: {}", job.id, e);
+    }
+
+    if let Ok(mut running) = queue.running.lock() {
+        running.remove(&job.id);
+    }
+    emit_job(&app, job.id);
+    queue.wake();
+}
+
+fn emit_job(app: &AppHandle, id: i64) {
+    if let Ok(Some(job)) = db::get_job(id) {
+        if let Err(e) = app.emit("update-job-changed", job) {
+            dev_log!("Failed to emit job event: {}", e);
+        }
+    }
+}
+
+pub(crate) async fn enqueue_update(
+    app: AppHandle,
+    app_id: u32,
+    username: Option<String>,
+    options: UpdateOptions
+) -> Result<UpdateJob, SteamError> {
+    options.check()?;
+    disk_space::preflight(&app, app_id)?;
+    let job = db::insert_job(app_id, username.as_deref(), &options, now())?;
+
+    dev_log!("Enqueued job {} for app {}", job.id, app_id);
+    let _ = app.emit("update-job-changed", job.clone());
+    app.state::<UpdateQueue>().wake();
+    Ok(job)
+}
+
+pub(crate) async fn cancel_job(app: AppHandle, job_id: i64) -> Result<UpdateJob, SteamError> {
+    let job = db::get_job(job_id)?
+        .ok_or(SteamError::JobNotFound { job_id })?;
+
+    match job.status {
+        JobStatus::Queued => {
+            db::mark_job_finished(job_id, JobStatus::Cancelled, Some("Cancelled by user"), now())?;
+        }
+        JobStatus::Running => {
+            app.state::<UpdateQueue>()
+                .cancel_running(job_id, processes::cancel_update(app.clone(), job.app_id))
+                .await?;
+        }
+        _ => return Err(SteamError::JobNotQueued { job_id }),
+    }
+
+    emit_job(&app, job_id);
+    db::get_job(job_id)?
+        .ok_or(SteamError::JobNotFound { job_id })
+}
+
+pub(crate) async fn reorder_job(app: AppHandle, job_id: i64, index: usize) -> Result<Vec<UpdateJob>, SteamError> {
+    if !db::move_queued_job(job_id, index)? {
+        return Err(SteamError::JobNotQueued { job_id });
+    }
+
+    app.state::<UpdateQueue>().wake();
+    list_jobs().await
+}
+
+pub(crate) async fn list_jobs() -> Result<Vec<UpdateJob>, SteamError> {
+    Ok(db::list_jobs()?)
+}
+
+pub(crate) async fn set_concurrency(app: AppHandle, concurrency: usize) -> Result<usize, SteamError> {
+    let concurrency = concurrency.clamp(1, MAX_CONCURRENCY);
+    db::set_setting(CONCURRENCY_SETTING, &concurrency.to_string())?;
+
+    let queue = app.state::<UpdateQueue>();
+    queue.concurrency.store(concurrency, Ordering::SeqCst);
+    queue.wake();
+    Ok(concurrency)
+}
+
+pub(crate) async fn get_concurrency(app: AppHandle) -> Result<usize, SteamError> {
+    Ok(app.state::<UpdateQueue>().concurrency.load(Ordering::SeqCst))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn a_successful_cancel_marks_the_job() {
+        let queue = UpdateQueue::new(1);
+
+        queue.cancel_running(7, async { Ok(()) }).await.unwrap();
+
+        assert!(queue.take_cancelled(7));
+        assert!(!queue.take_cancelled(7));
+    }
+
+    #[tokio::test]
+    async fn a_failed_cancel_leaves_the_job_unmarked() {
+        let queue = UpdateQueue::new(1);
+
+        let result = queue.cancel_running(7, async { Err::<(), _>(SteamError::UpdateNotRunning { app_id: 440 }) }).await;
+
+        assert!(matches!(result, Err(SteamError::UpdateNotRunning { app_id: 440 })));
+        assert!(!queue.take_cancelled(7));
+    }
+
+    #[test]
+    fn concurrency_is_clamped() {
+        assert_eq!(UpdateQueue::new(0).concurrency.load(Ordering::SeqCst), 1);
+        assert_eq!(UpdateQueue::new(usize::MAX).concurrency.load(Ordering::SeqCst), MAX_CONCURRENCY);
+    }
+
+    #[test]
+    fn statuses_round_trip() {
+        for status in [JobStatus::Queued, JobStatus::Running, JobStatus::Completed, JobStatus::Failed, JobStatus::Cancelled] {
+            assert_eq!(JobStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(JobStatus::parse("paused"), None);
+    }
+}
@@ -0,0 +1,635 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use crate::error::SteamError;
+use crate::processes;
+
+/// A process to start: SteamCMD, or whatever stands in for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessSpec {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    pub current_dir: Option<PathBuf>,
+}
+
+impl ProcessSpec {
+    /// Runs `program` from its own directory, as SteamCMD expects.
+    pub fn new(program: &Path) -> Self {
+        ProcessSpec {
+            program: program.to_path_buf(),
+            args: Vec::new(),
+            current_dir: program.parent().map(Path::to_path_buf),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+/// A started process with all three standard streams piped.
+pub struct SpawnedProcess {
+    pub stdin: Box<dyn AsyncWrite + Send + Unpin>,
+    pub stdout: Box<dyn AsyncRead + Send + Unpin>,
+    pub stderr: Box<dyn AsyncRead + Send + Unpin>,
+    pub child: Box<dyn ChildProcess>,
+}
+
+#[async_trait]
+pub trait ChildProcess: Send {
+    fn id(&self) -> Option<u32>;
+
+    fn has_exited(&mut self) -> bool;
+
+    /// Waits for the exit code; `None` if the process was killed by a signal.
+    async fn wait(&mut self) -> Result<Option<i32>, SteamError>;
+
+    async fn kill(&mut self);
+}
+
+/// Starts processes. Everything that talks to SteamCMD goes through this,
+/// so tests can replay recorded output instead of running the real binary.
+pub trait ProcessRunner: Send + Sync {
+    fn spawn(&self, spec: &ProcessSpec) -> Result<SpawnedProcess, SteamError>;
+}
+
+/// Runs real processes on the Tokio runtime.
+pub struct SystemRunner;
+
+impl ProcessRunner for SystemRunner {
+    fn spawn(&self, spec: &ProcessSpec) -> Result<SpawnedProcess, SteamError> {
+        let mut command = Command::new(&spec.program);
+        command
+            .args(&spec.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(current_dir) = &spec.current_dir {
+            command.current_dir(current_dir);
+        }
+        processes::isolate_process_tree(command.as_std_mut());
+
+        let mut child = command.spawn()
+            .map_err(|e| SteamError::io(&format!("Failed to start {}", spec.program.display()), e))?;
+        let missing = |stream: &str| SteamError::Internal { reason: format!("Failed to capture {}", stream) };
+
+        Ok(SpawnedProcess {
+            stdin: Box::new(child.stdin.take().ok_or_else(|| missing("stdin"))?),
+            stdout: Box::new(child.stdout.take().ok_or_else(|| missing("stdout"))?),
+            stderr: Box::new(child.stderr.take().ok_or_else(|| missing("stderr"))?),
+            child: Box::new(SystemChild(child)),
+        })
+    }
+}
+
+struct SystemChild(Child);
+
+#[async_trait]
+impl ChildProcess for SystemChild {
+    fn id(&self) -> Option<u32> {
+        self.0.id()
+    }
+
+    fn has_exited(&mut self) -> bool {
+        !matches!(self.0.try_wait(), Ok(None))
+    }
+
+    async fn wait(&mut self) -> Result<Option<i32>, SteamError> {
+        self.0.wait().await
+            .map(|status| status.code())
+            .map_err(|e| SteamError::io("Failed to wait for process", e))
+    }
+
+    async fn kill(&mut self) {
+        // steamcmd.sh leaves the real client behind as its child, so take the whole tree
+        if let Some(pid) = self.0.id() {
+            let _ = processes::kill_process_tree(pid).await;
+        }
+        let _ = self.0.kill().await;
+    }
+}
+
+/// Asks a running process to stop, from any task, and tells the task
+/// running it when the process has been paused.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<CancelState>);
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    paused: AtomicBool,
+    /// Woken on cancel and on every pause or resume.
+    notify: Notify,
+}
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Completes once `cancel` has been called.
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before the check, so a cancel in between still wakes us
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Marks the process as stopped or continued. A stopped process prints
+    /// nothing, so idle timeouts are held while it is paused.
+    pub fn set_paused(&self, paused: bool) {
+        self.0.paused.store(paused, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.paused.load(Ordering::SeqCst)
+    }
+
+    /// Completes once the process is no longer in the `paused` state.
+    pub async fn pause_changed(&self, paused: bool) {
+        loop {
+            let notified = self.0.notify.notified();
+            if self.is_paused() != paused {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// When a process that went quiet just now should be given up on: never
+    /// while it is paused, `idle_timeout` from now otherwise.
+    pub fn idle_deadline(&self, idle_timeout: Option<Duration>) -> Option<Instant> {
+        if self.is_paused() {
+            return None;
+        }
+        idle_timeout.map(|timeout| Instant::now() + timeout)
+    }
+}
+
+/// How long `collect` lets a process run. The default waits however long it takes.
+#[derive(Clone, Default)]
+pub struct Limits {
+    pub cancel: CancelToken,
+    /// Longest the whole run may take.
+    pub timeout: Option<Duration>,
+    /// Longest the process may go without printing a line, not counting
+    /// the time it spends paused.
+    pub idle_timeout: Option<Duration>,
+}
+
+/// What a process printed before it exited.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOutput {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl ProcessOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Runs a spawned process to the end with its stdin closed, handing every
+/// stdout line to `on_line` as it arrives.
+///
+/// Closing stdin means a prompt SteamCMD cannot answer ends the run
+/// instead of hanging it. A cancelled process is killed and whatever it
+/// printed is returned; one that overruns `limits` is killed as well and
+/// fails with `SteamCmdTimedOut`.
+pub async fn collect(
+    spawned: SpawnedProcess,
+    limits: &Limits,
+    mut on_line: impl FnMut(&str)
+) -> Result<ProcessOutput, SteamError> {
+    let SpawnedProcess { stdin, stdout, mut stderr, mut child } = spawned;
+    drop(stdin);
+
+    let deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+    let read_stdout = async {
+        let mut output = String::new();
+        let mut lines = BufReader::new(stdout).lines();
+        let mut timed_out = None;
+        loop {
+            // Recomputed on every line and on pause or resume, which restarts the wait
+            let paused = limits.cancel.is_paused();
+            let idle_deadline = limits.cancel.idle_deadline(limits.idle_timeout);
+            tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        on_line(&line);
+                        output.push_str(&line);
+                        output.push('\n');
+                    }
+                    _ => break,
+                },
+                _ = limits.cancel.cancelled() => {
+                    child.kill().await;
+                    break;
+                }
+                _ = sleep_until(deadline) => {
+                    timed_out = limits.timeout;
+                    child.kill().await;
+                    break;
+                }
+                _ = sleep_until(idle_deadline) => {
+                    timed_out = limits.idle_timeout;
+                    child.kill().await;
+                    break;
+                }
+                _ = limits.cancel.pause_changed(paused) => {}
+            }
+        }
+        (output, timed_out)
+    };
+    // Drained alongside stdout so a chatty child can't block on a full pipe
+    let read_stderr = async {
+        let mut output = String::new();
+        let _ = stderr.read_to_string(&mut output).await;
+        output
+    };
+
+    let ((stdout, timed_out), stderr) = tokio::join!(read_stdout, read_stderr);
+    let exit_code = child.wait().await?;
+    if let Some(timeout) = timed_out {
+        return Err(SteamError::SteamCmdTimedOut { seconds: timeout.as_secs() });
+    }
+    Ok(ProcessOutput { exit_code, stdout, stderr })
+}
+
+/// Sleeps until `deadline`, or forever without one.
+pub(crate) async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Stand-ins for SteamCMD in tests.
+///
+/// `FakeRunner` replays recorded transcripts instead of starting processes,
+/// `FakeSteamCmd` runs the fake-steamcmd binary. Transcripts live in
+/// `testdata/transcripts`, one step per line:
+///
+/// - `< text` prints `text` and a newline, a bare `<` prints an empty line
+/// - `<| text` prints `text` without a newline, the way SteamCMD shows prompts
+/// - `> text` waits for a line on stdin; what actually arrives is recorded
+/// - `~ ms` goes quiet for that many milliseconds
+/// - `= code` ends the process with that exit code, the default is 0
+/// - `#` starts a comment
+#[cfg(test)]
+pub(crate) mod fake {
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::sync::{Arc, Mutex, OnceLock};
+    use async_trait::async_trait;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+    use tokio::task::JoinHandle;
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Step {
+        Print(String),
+        Expect(String),
+        Sleep(Duration),
+        Exit(i32),
+    }
+
+    #[derive(Debug, Clone)]
+    pub(crate) struct Transcript {
+        steps: Vec<Step>,
+    }
+
+    impl Transcript {
+        pub(crate) fn parse(text: &str) -> Self {
+            let steps = text.lines()
+                .filter_map(|line| {
+                    if line == "<" {
+                        Some(Step::Print("\n".to_string()))
+                    } else if let Some(prompt) = line.strip_prefix("<| ") {
+                        Some(Step::Print(prompt.to_string()))
+                    } else if let Some(text) = line.strip_prefix("< ") {
+                        Some(Step::Print(format!("{}\n", text)))
+                    } else if let Some(input) = line.strip_prefix("> ") {
+                        Some(Step::Expect(input.to_string()))
+                    } else if let Some(ms) = line.strip_prefix("~ ") {
+                        Some(Step::Sleep(Duration::from_millis(ms.trim().parse().expect("milliseconds"))))
+                    } else if let Some(code) = line.strip_prefix("= ") {
+                        Some(Step::Exit(code.trim().parse().expect("exit code")))
+                    } else if line.trim().is_empty() || line.starts_with('#') {
+                        None
+                    } else {
+                        panic!("Unrecognised transcript line {:?}", line)
+                    }
+                })
+                .collect();
+            Transcript { steps }
+        }
+
+        /// The stdin lines the transcript was recorded with.
+        pub(crate) fn expected_inputs(&self) -> Vec<String> {
+            self.steps.iter()
+                .filter_map(|step| match step {
+                    Step::Expect(input) => Some(input.clone()),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    /// Hands out one transcript per spawned process, in order.
+    #[derive(Default)]
+    pub(crate) struct FakeRunner {
+        transcripts: Mutex<VecDeque<Transcript>>,
+        spawned: Mutex<Vec<ProcessSpec>>,
+        inputs: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FakeRunner {
+        pub(crate) fn new(transcripts: &[&str]) -> Self {
+            FakeRunner {
+                transcripts: Mutex::new(transcripts.iter().map(|text| Transcript::parse(text)).collect()),
+                ..Default::default()
+            }
+        }
+
+        /// Every process started so far.
+        pub(crate) fn spawned(&self) -> Vec<ProcessSpec> {
+            self.spawned.lock().unwrap().clone()
+        }
+
+        /// Every line written to the fake processes' stdin.
+        pub(crate) fn inputs(&self) -> Vec<String> {
+            self.inputs.lock().unwrap().clone()
+        }
+    }
+
+    impl ProcessRunner for FakeRunner {
+        fn spawn(&self, spec: &ProcessSpec) -> Result<SpawnedProcess, SteamError> {
+            self.spawned.lock().unwrap().push(spec.clone());
+            let transcript = self.transcripts.lock().unwrap().pop_front()
+                .ok_or_else(|| SteamError::Internal { reason: "No transcript left to replay".to_string() })?;
+
+            let (stdin, process_stdin) = tokio::io::duplex(64 * 1024);
+            let (process_stdout, stdout) = tokio::io::duplex(64 * 1024);
+            let task = tokio::spawn(play(transcript, process_stdin, process_stdout, self.inputs.clone()));
+
+            Ok(SpawnedProcess {
+                stdin: Box::new(stdin),
+                stdout: Box::new(stdout),
+                stderr: Box::new(tokio::io::empty()),
+                child: Box::new(FakeChild { task: Some(task), exit_code: None }),
+            })
+        }
+    }
+
+    async fn play(
+        transcript: Transcript,
+        stdin: DuplexStream,
+        mut stdout: DuplexStream,
+        inputs: Arc<Mutex<Vec<String>>>
+    ) -> i32 {
+        let mut stdin = BufReader::new(stdin).lines();
+        for step in transcript.steps {
+            match step {
+                Step::Print(text) => {
+                    if stdout.write_all(text.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+                Step::Expect(_) => match stdin.next_line().await {
+                    Ok(Some(line)) => inputs.lock().unwrap().push(line),
+                    // Like SteamCMD, give up when nobody can answer
+                    _ => return 1,
+                },
+                Step::Sleep(duration) => tokio::time::sleep(duration).await,
+                Step::Exit(code) => return code,
+            }
+        }
+        0
+    }
+
+    /// fake-steamcmd installed as SteamCMD in a directory of its own.
+    ///
+    /// Unlike `FakeRunner` this starts a real process, so the whole path from
+    /// `SystemRunner` down to the pipes is exercised. The directory is removed
+    /// on drop.
+    pub(crate) struct FakeSteamCmd {
+        dir: PathBuf,
+    }
+
+    impl FakeSteamCmd {
+        /// Installs the fake to play one of the scenarios in `fake-steamcmd/scenarios`.
+        pub(crate) fn install(name: &str, scenario: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("updateio-steamcmd-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            let scenario = Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("fake-steamcmd")
+                .join("scenarios")
+                .join(format!("{}.txt", scenario));
+            fs::copy(scenario, dir.join("scenario.txt")).unwrap();
+
+            let fake = FakeSteamCmd { dir };
+            // A link can't be held open for writing by a concurrently forked test,
+            // which would make exec fail with "text file busy"
+            if fs::hard_link(binary(), fake.entry_point()).is_err() {
+                fs::copy(binary(), fake.entry_point()).unwrap();
+            }
+            fake
+        }
+
+        /// Where `steam::get_steamcmd_path` would look in this directory.
+        pub(crate) fn entry_point(&self) -> PathBuf {
+            if cfg!(windows) {
+                self.dir.join("steamcmd.exe")
+            } else {
+                self.dir.join("steamcmd.sh")
+            }
+        }
+    }
+
+    impl Drop for FakeSteamCmd {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Builds the fake-steamcmd workspace member once per test run.
+    fn binary() -> &'static Path {
+        static BINARY: OnceLock<PathBuf> = OnceLock::new();
+        BINARY.get_or_init(|| {
+            // Test executables live in <target>/<profile>/deps
+            let target_dir = std::env::current_exe().unwrap()
+                .ancestors()
+                .nth(3)
+                .expect("target directory")
+                .to_path_buf();
+            let status = std::process::Command::new(env!("CARGO"))
+                .args(["build", "--quiet", "--package", "fake-steamcmd", "--target-dir"])
+                .arg(&target_dir)
+                .current_dir(env!("CARGO_MANIFEST_DIR"))
+                .status()
+                .expect("Failed to run cargo");
+            assert!(status.success(), "Failed to build fake-steamcmd");
+            target_dir.join("debug").join(format!("fake-steamcmd{}", std::env::consts::EXE_SUFFIX))
+        })
+    }
+
+    struct FakeChild {
+        task: Option<JoinHandle<i32>>,
+        exit_code: Option<Option<i32>>,
+    }
+
+    #[async_trait]
+    impl ChildProcess for FakeChild {
+        fn id(&self) -> Option<u32> {
+            None
+        }
+
+        fn has_exited(&mut self) -> bool {
+            self.exit_code.is_some() || self.task.as_ref().map(|task| task.is_finished()).unwrap_or(true)
+        }
+
+        async fn wait(&mut self) -> Result<Option<i32>, SteamError> {
+            if let Some(task) = self.task.take() {
+                self.exit_code = Some(task.await.ok());
+            }
+            Ok(self.exit_code.flatten())
+        }
+
+        async fn kill(&mut self) {
+            if let Some(task) = self.task.take() {
+                task.abort();
+                self.exit_code = Some(None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fake::FakeRunner;
+    use super::*;
+
+    const STALLS: &str = "< Update state (0x61) downloading, progress: 12.50\n~ 60000\n< Success! App '740' fully installed.\n";
+
+    fn spawn_stalling() -> SpawnedProcess {
+        FakeRunner::new(&[STALLS]).spawn(&ProcessSpec::new(Path::new("steamcmd.sh"))).unwrap()
+    }
+
+    #[tokio::test]
+    async fn finishes_with_everything_printed() {
+        let transcript = "< Loading Steam API...OK\n< Success! App '740' fully installed.\n= 0\n";
+        let spawned = FakeRunner::new(&[transcript]).spawn(&ProcessSpec::new(Path::new("steamcmd.sh"))).unwrap();
+        let mut lines = Vec::new();
+
+        let output = collect(spawned, &Limits::default(), |line| lines.push(line.to_string())).await.unwrap();
+
+        assert!(output.success());
+        assert_eq!(lines, vec!["Loading Steam API...OK", "Success! App '740' fully installed."]);
+    }
+
+    #[tokio::test]
+    async fn cancelling_kills_the_process_and_keeps_its_output() {
+        let limits = Limits::default();
+        let cancel = limits.cancel.clone();
+        let cancelled = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            cancel.cancel();
+        });
+
+        let output = collect(spawn_stalling(), &limits, |_| {}).await.unwrap();
+
+        cancelled.await.unwrap();
+        assert_eq!(output.exit_code, None);
+        assert!(output.stdout.contains("progress: 12.50"));
+        assert!(!output.stdout.contains("Success!"));
+    }
+
+    #[tokio::test]
+    async fn overrunning_the_timeout_fails() {
+        let limits = Limits { timeout: Some(Duration::from_millis(50)), ..Default::default() };
+
+        let result = collect(spawn_stalling(), &limits, |_| {}).await;
+
+        assert!(matches!(result, Err(SteamError::SteamCmdTimedOut { .. })));
+    }
+
+    #[tokio::test]
+    async fn going_quiet_for_too_long_fails() {
+        let limits = Limits { idle_timeout: Some(Duration::from_millis(50)), ..Default::default() };
+
+        let result = collect(spawn_stalling(), &limits, |_| {}).await;
+
+        assert!(matches!(result, Err(SteamError::SteamCmdTimedOut { .. })));
+    }
+
+    #[tokio::test]
+    async fn steady_output_keeps_the_idle_timeout_at_bay() {
+        let transcript = "< 1\n~ 30\n< 2\n~ 30\n< 3\n~ 30\n< 4\n= 0\n";
+        let spawned = FakeRunner::new(&[transcript]).spawn(&ProcessSpec::new(Path::new("steamcmd.sh"))).unwrap();
+        let limits = Limits { idle_timeout: Some(Duration::from_millis(80)), ..Default::default() };
+
+        let output = collect(spawned, &limits, |_| {}).await.unwrap();
+
+        assert_eq!(output.stdout, "1\n2\n3\n4\n");
+    }
+
+    #[tokio::test]
+    async fn a_paused_process_is_not_idle() {
+        // Quiet for three times the idle limit, but paused for most of it
+        let transcript = "< 1\n~ 300\n< 2\n= 0\n";
+        let spawned = FakeRunner::new(&[transcript]).spawn(&ProcessSpec::new(Path::new("steamcmd.sh"))).unwrap();
+        let limits = Limits { idle_timeout: Some(Duration::from_millis(100)), ..Default::default() };
+        let cancel = limits.cancel.clone();
+        let pauses = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            cancel.set_paused(true);
+            tokio::time::sleep(Duration::from_millis(230)).await;
+            cancel.set_paused(false);
+        });
+
+        let output = collect(spawned, &limits, |_| {}).await.unwrap();
+
+        pauses.await.unwrap();
+        assert_eq!(output.stdout, "1\n2\n");
+    }
+
+    #[tokio::test]
+    async fn resuming_restarts_the_idle_timeout() {
+        let limits = Limits { idle_timeout: Some(Duration::from_millis(100)), ..Default::default() };
+        let cancel = limits.cancel.clone();
+        cancel.set_paused(true);
+        let resumes = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            cancel.set_paused(false);
+        });
+
+        let result = collect(spawn_stalling(), &limits, |_| {}).await;
+
+        resumes.await.unwrap();
+        assert!(matches!(result, Err(SteamError::SteamCmdTimedOut { .. })));
+    }
+}
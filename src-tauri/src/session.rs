@@ -0,0 +1,876 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use crate::error::SteamError;
+use crate::guard::{self, GuardKind};
+use crate::totp;
+use crate::processes::{ProcessRegistry, Registration};
+use crate::runner::{self, CancelToken, ChildProcess, ProcessRunner, ProcessSpec, SystemRunner};
+use crate::steam::{self, SteamCredentials};
+
+/// A session with nothing to do is shut down after this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How long SteamCMD may take to bootstrap and show its first prompt.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(300);
+/// A command that prints nothing for this long has hung SteamCMD.
+const COMMAND_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+const PROMPT: &str = "Steam>";
+/// Codes tried for one Steam Guard prompt before the login gives up.
+const GUARD_ATTEMPTS: usize = 3;
+const REQUEST_BUFFER: usize = 16;
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Session] {}", format!($($arg)*));
+    }
+}
+
+/// Long-lived, logged-in SteamCMD processes, one per account.
+#[derive(Default)]
+pub struct SessionManager {
+    sessions: Mutex<HashMap<String, SessionEntry>>,
+}
+
+struct SessionEntry {
+    /// The password the session logs in with; a new one needs a new session.
+    password: String,
+    sender: mpsc::Sender<Request>,
+    /// Requests sent but not yet answered.
+    pending: Arc<AtomicUsize>,
+    /// Closed once the session's SteamCMD has been stopped.
+    stopped: oneshot::Receiver<()>,
+}
+
+/// A command for the session, answered with everything SteamCMD printed
+/// until it was ready for the next one.
+struct Request {
+    /// Registers the session process under this app while the command runs,
+    /// and the login before it, so it can be cancelled and paused like any
+    /// other update.
+    app_id: Option<u32>,
+    install_dir: Option<PathBuf>,
+    /// Console settings such as `@sSteamCmdForcePlatformType windows`, sent before the command.
+    settings: Vec<String>,
+    command: String,
+    lines: mpsc::UnboundedSender<String>,
+    reply: oneshot::Sender<Result<String, SteamError>>,
+}
+
+/// What the reader task saw on SteamCMD's stdout.
+enum Output {
+    Line(String),
+    /// SteamCMD is waiting at `Steam>`.
+    Ready,
+    /// SteamCMD is waiting for a Steam Guard code.
+    GuardCode(GuardKind),
+    Exited,
+}
+
+
+/// A queued command, as seen by the caller.
+pub struct Execution {
+    /// Output lines as they arrive, for progress reporting.
+    pub lines: mpsc::UnboundedReceiver<String>,
+    pub reply: oneshot::Receiver<Result<String, SteamError>>,
+}
+
+impl Execution {
+    /// Waits for the command, handing every line to `on_line` as it arrives.
+    pub async fn finish(mut self, mut on_line: impl FnMut(&str)) -> Result<String, SteamError> {
+        let result = loop {
+            tokio::select! {
+                Some(line) = self.lines.recv() => on_line(&line),
+                result = &mut self.reply => break result,
+            }
+        };
+        while let Ok(line) = self.lines.try_recv() {
+            on_line(&line);
+        }
+        result.map_err(|_| SteamError::SessionExited)?
+    }
+}
+
+impl SessionManager {
+    /// Queues `command` on the session for `credentials`, starting one if needed.
+    ///
+    /// Commands for the same account run strictly one after another.
+    pub async fn execute(
+        &self,
+        app: &AppHandle,
+        credentials: &SteamCredentials,
+        app_id: Option<u32>,
+        install_dir: Option<PathBuf>,
+        settings: Vec<String>,
+        command: String
+    ) -> Result<Execution, SteamError> {
+        let (lines_tx, lines) = mpsc::unbounded_channel();
+        let (reply_tx, reply) = oneshot::channel();
+        let mut request = Request { app_id, install_dir, settings, command, lines: lines_tx, reply: reply_tx };
+
+        loop {
+            let mut sessions = self.sessions.lock().await;
+            let existing = sessions.get(&credentials.username)
+                .filter(|entry| entry.password == credentials.password && !entry.sender.is_closed());
+            if let Some(entry) = existing {
+                entry.pending.fetch_add(1, Ordering::SeqCst);
+                let (sender, pending) = (entry.sender.clone(), entry.pending.clone());
+                // A busy session must not hold up other accounts while its queue is full
+                drop(sessions);
+                match sender.send(request).await {
+                    Ok(()) => return Ok(Execution { lines, reply }),
+                    // The actor is gone, start a new one on the next pass
+                    Err(mpsc::error::SendError(returned)) => {
+                        pending.fetch_sub(1, Ordering::SeqCst);
+                        request = returned;
+                        continue;
+                    }
+                }
+            }
+
+            let (sender, receiver) = mpsc::channel(REQUEST_BUFFER);
+            let (stopped_tx, stopped) = oneshot::channel();
+            let pending = Arc::new(AtomicUsize::new(1));
+            let session = Session {
+                app: app.clone(),
+                steamcmd_path: steam::get_steamcmd_path(app)?,
+                credentials: credentials.clone(),
+                process: None,
+                install_dir: None,
+                settings: Vec::new(),
+                pending: pending.clone(),
+                _stopped: stopped_tx,
+            };
+            tauri::async_runtime::spawn(session.run(receiver));
+
+            // The channel is new and empty, so this cannot wait
+            sender.try_send(request)
+                .map_err(|_| SteamError::SessionExited)?;
+            // Replacing an outdated entry drops its sender, which ends that actor
+            sessions.insert(credentials.username.clone(), SessionEntry {
+                password: credentials.password.clone(),
+                sender,
+                pending,
+                stopped,
+            });
+            return Ok(Execution { lines, reply });
+        }
+    }
+
+    /// Stops every session and waits for its SteamCMD to exit, so another
+    /// SteamCMD can use the directory. Fails with `SteamCmdBusy`, stopping
+    /// nothing, while any session still has a command to run.
+    pub async fn stop_idle(&self) -> Result<(), SteamError> {
+        let mut sessions = self.sessions.lock().await;
+        if sessions.values().any(|entry| entry.pending.load(Ordering::SeqCst) > 0) {
+            return Err(SteamError::SteamCmdBusy);
+        }
+
+        // Dropping the sender ends the actor, which stops SteamCMD on its way out
+        for (username, SessionEntry { stopped, .. }) in sessions.drain() {
+            dev_log!("Stopping idle session for {}", username);
+            let _ = stopped.await;
+        }
+        Ok(())
+    }
+}
+
+/// A running SteamCMD at its interactive prompt.
+pub(crate) struct Process {
+    child: Box<dyn ChildProcess>,
+    stdin: Box<dyn AsyncWrite + Send + Unpin>,
+    output: mpsc::UnboundedReceiver<Output>,
+}
+
+/// Answers Steam Guard prompts.
+#[async_trait]
+pub(crate) trait GuardCodes: Send {
+    async fn code(&mut self, kind: GuardKind) -> Result<String, SteamError>;
+}
+
+/// The app's way of answering prompts: a code passed with the credentials,
+/// then the shared secret for mobile prompts, then the user.
+struct AppGuardCodes<'a> {
+    app: &'a AppHandle,
+    credentials: &'a SteamCredentials,
+    provided: Option<String>,
+    generated: usize,
+}
+
+impl<'a> AppGuardCodes<'a> {
+    fn new(app: &'a AppHandle, credentials: &'a SteamCredentials, provided: Option<String>) -> Self {
+        AppGuardCodes { app, credentials, provided, generated: 0 }
+    }
+}
+
+#[async_trait]
+impl GuardCodes for AppGuardCodes<'_> {
+    /// Mobile prompts are answered from the shared secret twice, re-syncing
+    /// with Steam's clock before the second code; anything else goes to the
+    /// user through the guard broker.
+    async fn code(&mut self, kind: GuardKind) -> Result<String, SteamError> {
+        if let Some(code) = self.provided.take() {
+            return Ok(code);
+        }
+
+        let shared_secret = self.credentials.shared_secret.as_deref()
+            .filter(|_| kind == GuardKind::Mobile && self.generated < 2);
+        match shared_secret {
+            Some(shared_secret) => {
+                if self.generated > 0 {
+                    totp::sync_clock(self.app).await;
+                }
+                self.generated += 1;
+                totp::current_code(self.app, shared_secret).await
+            }
+            None => guard::request_code(self.app, &self.credentials.username, kind).await,
+        }
+    }
+}
+
+struct Session {
+    app: AppHandle,
+    steamcmd_path: PathBuf,
+    credentials: SteamCredentials,
+    process: Option<Process>,
+    /// The `force_install_dir` currently in effect.
+    install_dir: Option<PathBuf>,
+    /// The console settings currently in effect.
+    settings: Vec<String>,
+    pending: Arc<AtomicUsize>,
+    /// Dropped when the actor ends, after SteamCMD has been stopped.
+    _stopped: oneshot::Sender<()>,
+}
+
+impl Session {
+    async fn run(mut self, mut requests: mpsc::Receiver<Request>) {
+        loop {
+            let request = match tokio::time::timeout(IDLE_TIMEOUT, requests.recv()).await {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(_) => {
+                    // Idle: free the process, the next request starts a new one
+                    self.stop().await;
+                    continue;
+                }
+            };
+
+            let result = self.handle(&request).await;
+            if matches!(
+                result,
+                Err(SteamError::SessionExited) | Err(SteamError::UpdateCancelled { .. }) | Err(SteamError::SteamCmdTimedOut { .. })
+            ) {
+                self.process = None;
+            }
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            let _ = request.reply.send(result);
+        }
+
+        self.stop().await;
+    }
+
+    async fn handle(&mut self, request: &Request) -> Result<String, SteamError> {
+        // SteamCMD cannot go back to its own default directory, only a fresh process can
+        if request.install_dir.is_none() && self.install_dir.is_some() {
+            self.stop().await;
+        }
+        // Nor can a forced platform be unset
+        if request.settings != self.settings && !self.settings.is_empty() {
+            self.stop().await;
+        }
+
+        let cancel = CancelToken::default();
+        let app = self.app.clone();
+        let registry = app.state::<ProcessRegistry>();
+        let mut registration = None;
+
+        let result = self.prepare_and_run(request, &cancel, &registry, &mut registration).await;
+
+        if registration.map(|registration| registration.finish()).unwrap_or(false) {
+            return Err(SteamError::UpdateCancelled { app_id: request.app_id.unwrap_or_default() });
+        }
+        result
+    }
+
+    /// Brings the process into the state `request` needs and runs its command,
+    /// registering the process under the request's app as soon as it exists.
+    async fn prepare_and_run<'r>(
+        &mut self,
+        request: &Request,
+        cancel: &CancelToken,
+        registry: &'r ProcessRegistry,
+        registration: &mut Option<Registration<'r>>
+    ) -> Result<String, SteamError> {
+        // A session that died since the last command is restarted transparently
+        if self.process.as_mut().map(|process| process.has_exited()).unwrap_or(true) {
+            self.process = None;
+            self.install_dir = None;
+            self.settings = Vec::new();
+            // Registered before the login, which can wait on the user for a Steam Guard code
+            self.start(cancel, |process| {
+                *registration = register(registry, request.app_id, process, cancel)?;
+                Ok(())
+            }).await?;
+        }
+        let process = self.process.as_mut()
+            .ok_or_else(|| SteamError::Internal { reason: "SteamCMD session is not running".to_string() })?;
+        if registration.is_none() {
+            *registration = register(registry, request.app_id, process, cancel)?;
+        }
+
+        if let Some(install_dir) = &request.install_dir {
+            if self.install_dir.as_ref() != Some(install_dir) {
+                // SteamCMD warns that this belongs before the login, but honours it
+                process.send(&format!("force_install_dir {}", quote(&install_dir.display().to_string()))).await?;
+                process.wait_ready().await?;
+                self.install_dir = Some(install_dir.clone());
+            }
+        }
+        if request.settings != self.settings {
+            for setting in &request.settings {
+                process.send(setting).await?;
+                process.wait_ready().await?;
+            }
+            self.settings = request.settings.clone();
+        }
+
+        // Only the command name, the rest may hold a beta branch password
+        dev_log!("{}: {}", self.credentials.username, request.command.split_whitespace().next().unwrap_or_default());
+        let mut codes = AppGuardCodes::new(&self.app, &self.credentials, None);
+        process.run(&request.command, &mut codes, cancel, |line| {
+            let _ = request.lines.send(line);
+        }).await
+    }
+
+    /// Starts SteamCMD and logs in, leaving it at the prompt.
+    ///
+    /// `spawned` sees the process before it is waited on; cancelling kills it.
+    async fn start(
+        &mut self,
+        cancel: &CancelToken,
+        spawned: impl FnOnce(&Process) -> Result<(), SteamError>
+    ) -> Result<(), SteamError> {
+        dev_log!("Starting session for {}", self.credentials.username);
+        let mut process = Process::spawn(&SystemRunner, &ProcessSpec::new(&self.steamcmd_path))?;
+        if let Err(e) = spawned(&process) {
+            process.kill().await;
+            return Err(e);
+        }
+
+        let ready = tokio::select! {
+            ready = tokio::time::timeout(STARTUP_TIMEOUT, process.wait_ready()) => ready,
+            _ = cancel.cancelled() => Ok(Err(SteamError::SessionExited)),
+        };
+        match ready {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                process.kill().await;
+                return Err(e);
+            }
+            Err(_) => {
+                process.kill().await;
+                return Err(SteamError::SteamCmdTimedOut { seconds: STARTUP_TIMEOUT.as_secs() });
+            }
+        }
+
+        let provided = self.credentials.two_factor_code.clone();
+        let mut codes = AppGuardCodes::new(&self.app, &self.credentials, provided);
+        let login = process.login(&self.credentials, &mut codes, cancel).await;
+        if let Err(e) = login {
+            process.kill().await;
+            return Err(e);
+        }
+
+        dev_log!("Session for {} is logged in", self.credentials.username);
+        self.process = Some(process);
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            dev_log!("Stopping session for {}", self.credentials.username);
+            if process.send("quit").await.is_err()
+                || tokio::time::timeout(Duration::from_secs(10), process.child.wait()).await.is_err()
+            {
+                process.kill().await;
+            }
+        }
+    }
+}
+
+impl Process {
+    /// Starts a process and splits its output into lines and prompts.
+    pub(crate) fn spawn(runner: &dyn ProcessRunner, spec: &ProcessSpec) -> Result<Self, SteamError> {
+        let spawned = runner.spawn(spec)?;
+
+        let (sender, output) = mpsc::unbounded_channel();
+        tauri::async_runtime::spawn(read_output(spawned.stdout, sender));
+        // Nobody reads stderr, but a full pipe would block SteamCMD
+        let mut stderr = spawned.stderr;
+        tauri::async_runtime::spawn(async move {
+            let _ = tokio::io::copy(&mut stderr, &mut tokio::io::sink()).await;
+        });
+
+        Ok(Process { child: spawned.child, stdin: spawned.stdin, output })
+    }
+
+    /// Logs in from the prompt, answering Steam Guard prompts with `codes`.
+    pub(crate) async fn login(
+        &mut self,
+        credentials: &SteamCredentials,
+        codes: &mut dyn GuardCodes,
+        cancel: &CancelToken
+    ) -> Result<String, SteamError> {
+        // The password goes through stdin so it never shows up in the process list
+        self.send(&format!("login {} {}", quote(&credentials.username), quote(&credentials.password))).await?;
+        let output = self.answer_prompts(codes, cancel, |_| {}).await?;
+
+        if output.contains("Logged in OK") || output.contains("Waiting for user info...OK") {
+            Ok(output)
+        } else {
+            Err(SteamError::from_output(None, &output)
+                .unwrap_or(SteamError::AuthenticationFailed { output }))
+        }
+    }
+
+    /// Runs a command and returns what it printed, handing each line to `on_line` as well.
+    ///
+    /// Cancelling kills the process, since SteamCMD doesn't read its prompt
+    /// while a command runs.
+    pub(crate) async fn run(
+        &mut self,
+        command: &str,
+        codes: &mut dyn GuardCodes,
+        cancel: &CancelToken,
+        on_line: impl FnMut(String)
+    ) -> Result<String, SteamError> {
+        self.send(command).await?;
+        // Steam asks for a code again when the login expired mid-session
+        self.answer_prompts(codes, cancel, on_line).await
+    }
+
+    /// Collects output until `Steam>`, answering Steam Guard prompts on the way.
+    ///
+    /// Steam prompts again after a wrong code, so this gives up after a few.
+    async fn answer_prompts(
+        &mut self,
+        codes: &mut dyn GuardCodes,
+        cancel: &CancelToken,
+        mut on_line: impl FnMut(String)
+    ) -> Result<String, SteamError> {
+        let mut output = String::new();
+        let mut attempts = 0;
+        loop {
+            // A paused session prints nothing, that is not a hang
+            let paused = cancel.is_paused();
+            let idle_deadline = cancel.idle_deadline(Some(COMMAND_IDLE_TIMEOUT));
+            let next = tokio::select! {
+                next = self.output.recv() => next,
+                _ = cancel.cancelled() => {
+                    self.kill().await;
+                    return Err(SteamError::SessionExited);
+                }
+                _ = runner::sleep_until(idle_deadline) => {
+                    self.kill().await;
+                    return Err(SteamError::SteamCmdTimedOut { seconds: COMMAND_IDLE_TIMEOUT.as_secs() });
+                }
+                _ = cancel.pause_changed(paused) => continue,
+            };
+            match next {
+                Some(Output::Line(line)) => {
+                    output.push_str(&line);
+                    output.push('\n');
+                    on_line(line);
+                }
+                Some(Output::Ready) => return Ok(output),
+                Some(Output::GuardCode(kind)) => {
+                    if attempts == GUARD_ATTEMPTS {
+                        return Err(SteamError::InvalidGuardCode);
+                    }
+                    attempts += 1;
+                    // The code may have to come from the user, who can take a while
+                    let code = tokio::select! {
+                        code = codes.code(kind) => code?,
+                        _ = cancel.cancelled() => {
+                            self.kill().await;
+                            return Err(SteamError::SessionExited);
+                        }
+                    };
+                    self.send(&code).await?;
+                }
+                Some(Output::Exited) | None => return Err(SteamError::SessionExited),
+            }
+        }
+    }
+
+    async fn send(&mut self, command: &str) -> Result<(), SteamError> {
+        let line = format!("{}\n", command);
+        self.stdin.write_all(line.as_bytes()).await
+            .map_err(|_| SteamError::SessionExited)?;
+        self.stdin.flush().await
+            .map_err(|_| SteamError::SessionExited)
+    }
+
+    /// Collects output until `Steam>`, failing if SteamCMD wants a code instead.
+    pub(crate) async fn wait_ready(&mut self) -> Result<String, SteamError> {
+        let mut output = String::new();
+        loop {
+            match self.output.recv().await {
+                Some(Output::Line(line)) => {
+                    output.push_str(&line);
+                    output.push('\n');
+                }
+                Some(Output::Ready) => return Ok(output),
+                Some(Output::GuardCode(_)) => return Err(SteamError::SteamGuardRequired),
+                Some(Output::Exited) | None => return Err(SteamError::SessionExited),
+            }
+        }
+    }
+
+    fn has_exited(&mut self) -> bool {
+        self.child.has_exited()
+    }
+
+    pub(crate) async fn kill(&mut self) {
+        self.child.kill().await;
+    }
+}
+
+/// Registers `process` under `app_id`, if the request is for an app.
+fn register<'r>(
+    registry: &'r ProcessRegistry,
+    app_id: Option<u32>,
+    process: &Process,
+    cancel: &CancelToken
+) -> Result<Option<Registration<'r>>, SteamError> {
+    match (app_id, process.child.id()) {
+        (Some(app_id), Some(pid)) => registry.register(app_id, pid, cancel.clone()).map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Splits stdout into lines and recognises the prompts, which SteamCMD
+/// prints without a trailing newline.
+async fn read_output(mut stdout: impl AsyncRead + Unpin, sender: mpsc::UnboundedSender<Output>) {
+    let mut pending = String::new();
+    let mut buffer = [0u8; 4096];
+
+    loop {
+        let read = match stdout.read(&mut buffer).await {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        pending.push_str(&String::from_utf8_lossy(&buffer[..read]));
+
+        while let Some(end) = pending.find('\n') {
+            let line: String = pending.drain(..=end).collect();
+            let line = strip_ansi(line.trim_end());
+            if !line.is_empty() {
+                let _ = sender.send(Output::Line(line));
+            }
+        }
+
+        let partial = strip_ansi(&pending);
+        let partial = partial.trim();
+        if partial.ends_with(PROMPT) {
+            pending.clear();
+            let _ = sender.send(Output::Ready);
+        } else if let Some(kind) = guard_prompt(partial) {
+            let _ = sender.send(Output::Line(partial.to_string()));
+            pending.clear();
+            let _ = sender.send(Output::GuardCode(kind));
+        }
+    }
+
+    let _ = sender.send(Output::Exited);
+}
+
+/// Recognises the code prompts: e-mailed codes are "Steam Guard code",
+/// mobile authenticator codes are "Two-factor code".
+fn guard_prompt(partial: &str) -> Option<GuardKind> {
+    if partial.contains("Two-factor code") || partial.contains("Two factor code") {
+        Some(GuardKind::Mobile)
+    } else if partial.contains("Steam Guard code") {
+        Some(GuardKind::Email)
+    } else {
+        None
+    }
+}
+
+/// Quotes a value for SteamCMD's command parser when it contains whitespace.
+///
+/// The parser has no escape for `"` itself, so values that may hold one,
+/// like passwords, are rejected before they get here.
+pub(crate) fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("\"{}\"", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Drops the colour escape codes some SteamCMD builds wrap around the prompt.
+fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::path::Path;
+    use super::*;
+    use crate::runner::fake::{FakeRunner, FakeSteamCmd, Transcript};
+    use crate::runner::SystemRunner;
+
+    const LOGIN: &str = include_str!("../testdata/transcripts/session_login.txt");
+    const EMAIL_GUARD: &str = include_str!("../testdata/transcripts/session_email_guard.txt");
+    const MOBILE_GUARD: &str = include_str!("../testdata/transcripts/session_mobile_guard.txt");
+    const INVALID_PASSWORD: &str = include_str!("../testdata/transcripts/session_invalid_password.txt");
+    const INVALID_GUARD_CODE: &str = include_str!("../testdata/transcripts/session_invalid_guard_code.txt");
+    const GUARD_RETRY: &str = include_str!("../testdata/transcripts/session_guard_retry.txt");
+    const UPDATE_SUCCESS: &str = include_str!("../testdata/transcripts/session_update_success.txt");
+    const NO_SUBSCRIPTION: &str = include_str!("../testdata/transcripts/session_no_subscription.txt");
+    const UPDATE_STALLS: &str = include_str!("../testdata/transcripts/session_update_stalls.txt");
+
+    /// Hands out canned codes and remembers which prompts asked for them.
+    #[derive(Default)]
+    struct CannedCodes {
+        codes: VecDeque<String>,
+        asked: Vec<GuardKind>,
+    }
+
+    impl CannedCodes {
+        fn new(codes: &[&str]) -> Self {
+            CannedCodes { codes: codes.iter().map(|code| code.to_string()).collect(), asked: Vec::new() }
+        }
+    }
+
+    #[async_trait]
+    impl GuardCodes for CannedCodes {
+        async fn code(&mut self, kind: GuardKind) -> Result<String, SteamError> {
+            self.asked.push(kind);
+            self.codes.pop_front().ok_or(SteamError::SteamGuardRequired)
+        }
+    }
+
+    fn credentials() -> SteamCredentials {
+        SteamCredentials {
+            username: "venue_bot".to_string(),
+            password: "hunter2 with spaces".to_string(),
+            two_factor_code: None,
+            shared_secret: None,
+        }
+    }
+
+    async fn logged_in(runner: &FakeRunner, codes: &mut CannedCodes) -> Result<Process, SteamError> {
+        logged_in_with(runner, Path::new("/opt/steamcmd/steamcmd.sh"), codes).await
+    }
+
+    async fn logged_in_with(
+        runner: &dyn ProcessRunner,
+        steamcmd_path: &Path,
+        codes: &mut CannedCodes
+    ) -> Result<Process, SteamError> {
+        let mut process = Process::spawn(runner, &ProcessSpec::new(steamcmd_path))?;
+        process.wait_ready().await?;
+        process.login(&credentials(), codes, &CancelToken::default()).await?;
+        Ok(process)
+    }
+
+    /// Never answers, like a user who walked away from the prompt, and
+    /// cancels the update while it waits.
+    struct AbandonedCodes(CancelToken);
+
+    #[async_trait]
+    impl GuardCodes for AbandonedCodes {
+        async fn code(&mut self, _kind: GuardKind) -> Result<String, SteamError> {
+            self.0.cancel();
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn login_sends_the_password_through_stdin() {
+        let runner = FakeRunner::new(&[LOGIN]);
+        let mut codes = CannedCodes::default();
+
+        logged_in(&runner, &mut codes).await.unwrap();
+
+        assert!(runner.spawned()[0].args.is_empty());
+        assert_eq!(runner.inputs(), vec!["login venue_bot \"hunter2 with spaces\"".to_string()]);
+        assert!(codes.asked.is_empty());
+    }
+
+    #[tokio::test]
+    async fn email_prompt_is_answered_with_a_code() {
+        let runner = FakeRunner::new(&[EMAIL_GUARD]);
+        let mut codes = CannedCodes::new(&["F7K2M"]);
+
+        logged_in(&runner, &mut codes).await.unwrap();
+
+        assert_eq!(codes.asked, vec![GuardKind::Email]);
+        assert_eq!(runner.inputs(), Transcript::parse(EMAIL_GUARD).expected_inputs());
+    }
+
+    #[tokio::test]
+    async fn cancelling_stops_a_login_waiting_for_a_code() {
+        let runner = FakeRunner::new(&[EMAIL_GUARD]);
+        let cancel = CancelToken::default();
+        let mut process = Process::spawn(&runner, &ProcessSpec::new(Path::new("/opt/steamcmd/steamcmd.sh"))).unwrap();
+        process.wait_ready().await.unwrap();
+
+        let result = process.login(&credentials(), &mut AbandonedCodes(cancel.clone()), &cancel).await;
+
+        assert!(matches!(result, Err(SteamError::SessionExited)));
+        assert!(process.has_exited());
+    }
+
+    #[tokio::test]
+    async fn mobile_prompt_is_answered_with_a_code() {
+        let runner = FakeRunner::new(&[MOBILE_GUARD]);
+        let mut codes = CannedCodes::new(&["2BXQ7"]);
+
+        logged_in(&runner, &mut codes).await.unwrap();
+
+        assert_eq!(codes.asked, vec![GuardKind::Mobile]);
+        assert_eq!(runner.inputs(), Transcript::parse(MOBILE_GUARD).expected_inputs());
+    }
+
+    #[tokio::test]
+    async fn unanswered_prompt_fails_the_login() {
+        let runner = FakeRunner::new(&[MOBILE_GUARD]);
+
+        let result = logged_in(&runner, &mut CannedCodes::default()).await;
+
+        assert!(matches!(result, Err(SteamError::SteamGuardRequired)));
+    }
+
+    #[tokio::test]
+    async fn invalid_password_is_reported() {
+        let runner = FakeRunner::new(&[INVALID_PASSWORD]);
+
+        let result = logged_in(&runner, &mut CannedCodes::default()).await;
+
+        assert!(matches!(result, Err(SteamError::InvalidPassword)));
+    }
+
+    #[tokio::test]
+    async fn rejected_guard_code_is_reported() {
+        let runner = FakeRunner::new(&[INVALID_GUARD_CODE]);
+
+        let result = logged_in(&runner, &mut CannedCodes::new(&["2BXQ7"])).await;
+
+        assert!(matches!(result, Err(SteamError::InvalidGuardCode)));
+    }
+
+    #[tokio::test]
+    async fn repeated_prompts_give_up_after_three_codes() {
+        let runner = FakeRunner::new(&[GUARD_RETRY]);
+        let mut codes = CannedCodes::new(&["2BXQ7", "3CYR8", "4DZT9", "5FBV2"]);
+
+        let result = logged_in(&runner, &mut codes).await;
+
+        assert!(matches!(result, Err(SteamError::InvalidGuardCode)));
+        assert_eq!(codes.asked.len(), GUARD_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn update_streams_its_progress() {
+        let runner = FakeRunner::new(&[UPDATE_SUCCESS]);
+        let mut codes = CannedCodes::default();
+        let mut process = logged_in(&runner, &mut codes).await.unwrap();
+        let mut lines = Vec::new();
+
+        let output = process.run("app_update 730 validate", &mut codes, &CancelToken::default(), |line| lines.push(line)).await.unwrap();
+
+        assert_eq!(runner.inputs()[1], "app_update 730 validate");
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2].contains("downloading, progress: 48.02"));
+        assert!(steam::authenticated_update_result(730, output).is_ok());
+    }
+
+    #[tokio::test]
+    async fn update_without_a_subscription_is_reported() {
+        let runner = FakeRunner::new(&[NO_SUBSCRIPTION]);
+        let mut codes = CannedCodes::default();
+        let mut process = logged_in(&runner, &mut codes).await.unwrap();
+
+        let output = process.run("app_update 570940 validate", &mut codes, &CancelToken::default(), |_| {}).await.unwrap();
+
+        assert!(matches!(
+            steam::authenticated_update_result(570940, output),
+            Err(SteamError::NoSubscription { app_id: 570940 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn cancelling_a_command_kills_the_session() {
+        let runner = FakeRunner::new(&[UPDATE_STALLS]);
+        let mut codes = CannedCodes::default();
+        let mut process = logged_in(&runner, &mut codes).await.unwrap();
+        let cancel = CancelToken::default();
+        let mut lines = Vec::new();
+
+        let result = process.run("app_update 730 validate", &mut codes, &cancel, |line| {
+            lines.push(line);
+            cancel.cancel();
+        }).await;
+
+        assert!(matches!(result, Err(SteamError::SessionExited)));
+        assert_eq!(lines.len(), 1);
+        assert!(process.has_exited());
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_session_updates_after_an_email_code() {
+        let steamcmd = FakeSteamCmd::install("session-email", "steam_guard_email");
+        let mut codes = CannedCodes::new(&["F7K2M"]);
+        let mut process = logged_in_with(&SystemRunner, &steamcmd.entry_point(), &mut codes).await.unwrap();
+
+        let output = process.run("app_update 740 validate", &mut codes, &CancelToken::default(), |_| {}).await.unwrap();
+
+        assert_eq!(codes.asked, vec![GuardKind::Email]);
+        assert!(steam::authenticated_update_result(740, output).is_ok());
+        process.kill().await;
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_session_rejects_a_wrong_code() {
+        let steamcmd = FakeSteamCmd::install("session-wrong-code", "steam_guard_mobile");
+
+        let result = logged_in_with(&SystemRunner, &steamcmd.entry_point(), &mut CannedCodes::new(&["AAAAA"])).await;
+
+        assert!(matches!(result, Err(SteamError::InvalidGuardCode)));
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_session_reports_a_dropped_connection() {
+        let steamcmd = FakeSteamCmd::install("session-network-drop", "network_drop");
+        let mut codes = CannedCodes::default();
+        let mut process = logged_in_with(&SystemRunner, &steamcmd.entry_point(), &mut codes).await.unwrap();
+
+        let result = process.run("app_update 740", &mut codes, &CancelToken::default(), |_| {}).await;
+
+        assert!(matches!(result, Err(SteamError::SessionExited)));
+    }
+}
@@ -1,19 +1,163 @@
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::fs;
-use tauri::{AppHandle, Manager, Listener, Emitter};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, Emitter};
 use serde::{Deserialize, Serialize};
+use crate::accounts;
+use crate::branches::{self, BetaBranch};
 use crate::db;
-use std::io::{BufRead, BufReader, Write};
-use std::sync::{Arc, Mutex};
-
-#[derive(Debug, Deserialize, Serialize)]
+use crate::disk_space::{self, DiskReservations};
+use crate::error::SteamError;
+use crate::progress;
+use crate::processes::ProcessRegistry;
+use crate::vault::Vault;
+use crate::history::{self, RunReport};
+use crate::install_dirs;
+use crate::installer;
+use crate::login_script::LoginScript;
+use crate::runner::{self, Limits, ProcessOutput, ProcessRunner, ProcessSpec, SystemRunner};
+use crate::session::{quote, SessionManager};
+use crate::totp;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SteamCredentials {
     pub username: String,
     pub password: String,
     pub two_factor_code: Option<String>,
+    /// Base64 shared secret of the account's mobile authenticator, used to
+    /// answer "Two-factor code" prompts without the user. Never sent back.
+    #[serde(default, skip_serializing)]
+    pub shared_secret: Option<String>,
+}
+
+/// An operating system SteamCMD can download an app's depots for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Windows,
+    Linux,
+    Macos,
+}
+
+impl Platform {
+    /// The name `@sSteamCmdForcePlatformType` takes.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::Windows => "windows",
+            Platform::Linux => "linux",
+            Platform::Macos => "macos",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "windows" => Some(Platform::Windows),
+            "linux" => Some(Platform::Linux),
+            "macos" => Some(Platform::Macos),
+            _ => None,
+        }
+    }
+
+    /// The platform SteamCMD downloads for unless told otherwise.
+    pub fn host() -> Self {
+        if cfg!(target_os = "windows") {
+            Platform::Windows
+        } else if cfg!(target_os = "macos") {
+            Platform::Macos
+        } else {
+            Platform::Linux
+        }
+    }
+}
+
+/// How an update is run.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UpdateOptions {
+    /// Re-hash every installed file and reacquire the ones that don't match.
+    /// On large installs that is far more disk work than the patch itself.
+    pub validate: bool,
+    /// Download the depots of another platform, e.g. Windows installs on a
+    /// Linux server. `None` is the host's own.
+    pub platform: Option<Platform>,
+    /// 32 or 64 bit depots; `None` leaves the choice to SteamCMD.
+    pub bitness: Option<u32>,
+}
+
+impl UpdateOptions {
+    /// Rejects options SteamCMD would quietly ignore.
+    pub(crate) fn check(&self) -> Result<(), SteamError> {
+        match self.bitness {
+            None | Some(32) | Some(64) => Ok(()),
+            Some(bitness) => Err(SteamError::InvalidInput {
+                reason: format!("Bitness must be 32 or 64, not {}", bitness),
+            }),
+        }
+    }
+
+    /// The platform the depots are downloaded for.
+    pub(crate) fn target_platform(&self) -> Platform {
+        self.platform.unwrap_or_else(Platform::host)
+    }
+
+    /// Console settings that force the target platform, as `(name, value)`.
+    /// They have to be in effect before `app_update` runs.
+    fn platform_settings(&self) -> Vec<(&'static str, String)> {
+        let mut settings = Vec::new();
+        if let Some(platform) = self.platform {
+            settings.push(("@sSteamCmdForcePlatformType", platform.as_str().to_string()));
+        }
+        if let Some(bitness) = self.bitness {
+            settings.push(("@sSteamCmdForcePlatformBitness", bitness.to_string()));
+        }
+        settings
+    }
+
+    /// Arguments of `app_update` for `app_id`, on `branch` when it isn't public.
+    fn app_update_args(&self, app_id: u32, branch: Option<&BetaBranch>) -> Vec<String> {
+        let mut args = vec![app_id.to_string()];
+        if let Some(branch) = branch {
+            args.push("-beta".to_string());
+            args.push(branch.name.clone());
+            if let Some(password) = &branch.password {
+                args.push("-betapassword".to_string());
+                args.push(password.clone());
+            }
+        }
+        if self.validate {
+            args.push("validate".to_string());
+        }
+        args
+    }
+
+    /// The `app_update` command, as typed at the `Steam>` prompt.
+    fn app_update(&self, app_id: u32, branch: Option<&BetaBranch>) -> String {
+        let mut command = "app_update".to_string();
+        for arg in self.app_update_args(app_id, branch) {
+            command.push(' ');
+            command.push_str(&quote(&arg));
+        }
+        command
+    }
+}
+
+/// What `verify_game` found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VerifyReport {
+    pub app_id: u32,
+    /// Files that failed validation.
+    pub files_corrupted: u64,
+    /// Files SteamCMD reacquired; all of them once the run succeeded.
+    pub files_fixed: u64,
+    pub message: String,
 }
 
+/// Overrides where SteamCMD lives, e.g. with a directory holding fake-steamcmd.
+const STEAMCMD_DIR_VAR: &str = "UPDATEIO_STEAMCMD_DIR";
+/// SteamCMD reports progress every few seconds while it works, so this long
+/// without a line means it hung.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
 // Helper macro for development logging
 macro_rules! dev_log {
     ($($arg:tt)*) => {
@@ -23,169 +167,92 @@ macro_rules! dev_log {
 }
 
 // Helper function to get SteamCMD directory in the app's directory
-pub(crate) fn get_steamcmd_dir(_app: &AppHandle) -> Result<PathBuf, String> {
+pub(crate) fn get_steamcmd_dir(_app: &AppHandle) -> Result<PathBuf, SteamError> {
+    // Lets end-to-end tests and local runs point at fake-steamcmd instead
+    if let Some(dir) = std::env::var_os(STEAMCMD_DIR_VAR).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+
     let exe_path = std::env::current_exe()
-        .map_err(|e| format!("Failed to get executable path: {}", e))?;
+        .map_err(|e| SteamError::io("Failed to get executable path", e))?;
     
     let app_dir = exe_path
         .parent()
-        .ok_or_else(|| "Failed to get parent directory".to_string())?;
+        .ok_or_else(|| SteamError::Internal { reason: "Failed to get parent directory".to_string() })?;
     
     Ok(app_dir.join("steamcmd"))
 }
 
-pub(crate) fn is_steamcmd_installed(app: &AppHandle) -> Result<bool, String> {
+/// Path of the SteamCMD entry point inside `get_steamcmd_dir`.
+pub(crate) fn get_steamcmd_path(app: &AppHandle) -> Result<PathBuf, SteamError> {
     let steamcmd_dir = get_steamcmd_dir(app)?;
     #[cfg(target_os = "windows")]
     {
-        let path = steamcmd_dir.join("steamcmd.exe");
-        let exists = path.exists();
-        dev_log!("Checking Windows path: {:?} (exists: {})", path, exists);
-        Ok(exists)
+        Ok(steamcmd_dir.join("steamcmd.exe"))
     }
     #[cfg(not(target_os = "windows"))]
     {
-        let path = steamcmd_dir.join("steamcmd.sh");
-        let exists = path.exists();
-        dev_log!("Checking Unix path: {:?} (exists: {})", path, exists);
-        Ok(exists)
+        Ok(steamcmd_dir.join("steamcmd.sh"))
     }
 }
 
-pub(crate) fn install_steamcmd(app: &AppHandle) -> Result<(), String> {
-    let steamcmd_dir = get_steamcmd_dir(app)?;
-    fs::create_dir_all(&steamcmd_dir)
-        .map_err(|e| format!("Failed to create SteamCMD directory: {}", e))?;
-
-    #[cfg(target_os = "windows")]
+/// Sets the executable bits, which extracting SteamCMD doesn't always keep.
+async fn make_executable(path: &Path) -> Result<(), SteamError> {
+    #[cfg(unix)]
     {
-        dev_log!("Starting Windows installation process");
-        let zip_path = steamcmd_dir.join("steamcmd.zip");
-
-        dev_log!("Downloading steamcmd.zip...");
-        let output = Command::new("powershell")
-            .args(&[
-                "-Command",
-                &format!(
-                    "Invoke-WebRequest -Uri 'https://steamcdn-a.akamaihd.net/client/installer/steamcmd.zip' -OutFile '{}'",
-                    zip_path.to_string_lossy()
-                )
-            ])
-            .output()
-            .map_err(|e| format!("Failed to download SteamCMD: {}", e))?;
-
-        if !output.status.success() {
-            dev_log!("Download failed with stderr: {}", String::from_utf8_lossy(&output.stderr));
-            return Err("Failed to download SteamCMD".to_string());
-        }
-        dev_log!("Download completed successfully");
-
-        dev_log!("Extracting steamcmd.zip...");
-        let output = Command::new("powershell")
-            .args(&[
-                "-Command",
-                &format!(
-                    "Expand-Archive -Path '{}' -DestinationPath '{}' -Force",
-                    zip_path.to_string_lossy(),
-                    steamcmd_dir.to_string_lossy()
-                )
-            ])
-            .output()
-            .map_err(|e| format!("Failed to extract SteamCMD: {}", e))?;
-
-        if !output.status.success() {
-            dev_log!("Extraction failed with stderr: {}", String::from_utf8_lossy(&output.stderr));
-            return Err("Failed to extract SteamCMD".to_string());
-        }
-        dev_log!("Extraction completed successfully");
-
-        // Clean up zip file
-        let _ = fs::remove_file(zip_path);
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = tokio::fs::metadata(path).await
+            .map_err(|e| SteamError::io("Failed to read steamcmd permissions", e))?
+            .permissions();
+        permissions.set_mode(permissions.mode() | 0o111);
+        tokio::fs::set_permissions(path, permissions).await
+            .map_err(|e| SteamError::io("Failed to make steamcmd executable", e))?;
     }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
 
-    #[cfg(target_os = "linux")]
-    {
-        dev_log!("Starting Linux installation process");
-        let tar_path = steamcmd_dir.join("steamcmd_linux.tar.gz");
-
-        dev_log!("Downloading steamcmd_linux.tar.gz...");
-        let output = Command::new("curl")
-            .args(&[
-                "-o",
-                tar_path.to_str().unwrap(),
-                "https://steamcdn-a.akamaihd.net/client/installer/steamcmd_linux.tar.gz",
-            ])
-            .output()
-            .map_err(|e| format!("Failed to download SteamCMD: {}", e))?;
-
-        if !output.status.success() {
-            return Err("Failed to download SteamCMD".to_string());
-        }
-
-        dev_log!("Extracting steamcmd_linux.tar.gz...");
-        let output = Command::new("tar")
-            .current_dir(&steamcmd_dir)
-            .args(&["-xzf", tar_path.to_str().unwrap()])
-            .output()
-            .map_err(|e| format!("Failed to extract SteamCMD: {}", e))?;
+/// Parses a progress line, records downloaded bytes and returns the event to emit.
+fn track_progress(app_id: u32, line: &str, report: &RunReport) -> Option<progress::UpdateProgress> {
+    let progress = progress::parse_line(line)?.to_progress(app_id)?;
+    if progress.state == progress::UpdateState::Downloading {
+        report.observe_bytes(progress.bytes_done);
+    }
+    Some(progress)
+}
 
-        if !output.status.success() {
-            return Err("Failed to extract SteamCMD".to_string());
+fn emit_progress(app: &AppHandle, app_id: u32, line: &str, report: &RunReport) {
+    if let Some(files) = progress::parse_validation_failures(line) {
+        report.observe_corrupted_files(files);
+    }
+    if let Some(progress) = track_progress(app_id, line, report) {
+        if let Err(e) = app.emit("steam-update-progress", progress) {
+            dev_log!("Failed to emit progress event: {}", e);
         }
-
-        // Clean up tar.gz file
-        let _ = fs::remove_file(tar_path);
     }
+}
 
-    #[cfg(target_os = "macos")]
+pub(crate) fn is_steamcmd_installed(app: &AppHandle) -> Result<bool, SteamError> {
+    let steamcmd_dir = get_steamcmd_dir(app)?;
+    #[cfg(target_os = "windows")]
     {
-        dev_log!("Starting macOS installation process");
-        let tar_path = steamcmd_dir.join("steamcmd_osx.tar.gz");
-
-        dev_log!("Downloading steamcmd_osx.tar.gz...");
-        let output = Command::new("curl")
-            .args(&[
-                "-o",
-                tar_path.to_str().unwrap(),
-                "https://steamcdn-a.akamaihd.net/client/installer/steamcmd_osx.tar.gz",
-            ])
-            .output()
-            .map_err(|e| format!("Failed to download SteamCMD: {}", e))?;
-
-        if !output.status.success() {
-            return Err("Failed to download SteamCMD".to_string());
-        }
-
-        dev_log!("Extracting steamcmd_osx.tar.gz...");
-        let output = Command::new("tar")
-            .current_dir(&steamcmd_dir)
-            .args(&["-xzf", tar_path.to_str().unwrap()])
-            .output()
-            .map_err(|e| format!("Failed to extract SteamCMD: {}", e))?;
-
-        if !output.status.success() {
-            return Err("Failed to extract SteamCMD".to_string());
-        }
-
-        // Make steamcmd.sh executable
-        let output = Command::new("chmod")
-            .args(&["+x", steamcmd_dir.join("steamcmd.sh").to_str().unwrap()])
-            .output()
-            .map_err(|e| format!("Failed to make steamcmd.sh executable: {}", e))?;
-
-        if !output.status.success() {
-            return Err("Failed to make steamcmd.sh executable".to_string());
-        }
-
-        // Clean up tar.gz file
-        let _ = fs::remove_file(tar_path);
+        let path = steamcmd_dir.join("steamcmd.exe");
+        let exists = path.exists();
+        dev_log!("Checking Windows path: {:?} (exists: {})", path, exists);
+        Ok(exists)
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let path = steamcmd_dir.join("steamcmd.sh");
+        let exists = path.exists();
+        dev_log!("Checking Unix path: {:?} (exists: {})", path, exists);
+        Ok(exists)
     }
-
-    dev_log!("Installation process completed successfully");
-    Ok(())
 }
 
-pub(crate) async fn ensure_steamcmd(app: AppHandle) -> Result<String, String> {
+pub(crate) async fn ensure_steamcmd(app: AppHandle) -> Result<String, SteamError> {
     dev_log!("Checking if SteamCMD is installed...");
     if is_steamcmd_installed(&app)? {
         dev_log!("SteamCMD is already installed");
@@ -193,84 +260,159 @@ pub(crate) async fn ensure_steamcmd(app: AppHandle) -> Result<String, String> {
     }
 
     dev_log!("SteamCMD not found, starting installation...");
-    install_steamcmd(&app).map_err(|e| e.to_string())?;
+    installer::install(&app).await?;
     Ok("SteamCMD has been installed successfully".to_string())
 }
 
-pub(crate) async fn update_game(app: AppHandle, app_id: u32) -> Result<String, String> {
-    let steamcmd_dir = get_steamcmd_dir(&app)?;
-    
-    let steamcmd_path = {
-        #[cfg(target_os = "windows")]
-        {
-            steamcmd_dir.join("steamcmd.exe")
-        }
-        #[cfg(not(target_os = "windows"))]
-        {
-            let path = steamcmd_dir.join("steamcmd.sh");
-            
-            // Ensure the file is executable
-            let output = Command::new("chmod")
-                .args(&["+x", path.to_str().unwrap()])
-                .output()
-                .map_err(|e| format!("Failed to make steamcmd executable: {}", e))?;
-
-            if !output.status.success() {
-                return Err("Failed to make steamcmd executable".to_string());
-            }
-            
-            path
-        }
-    };
+pub(crate) async fn update_game(app: AppHandle, app_id: u32, options: UpdateOptions) -> Result<String, SteamError> {
+    let attempt = history::start(app_id, None, &options);
+    let report = RunReport::default();
+    let result = run_anonymous_update(&app, app_id, &options, &report).await;
+    history::finish(attempt, &result, &report);
+    result
+}
 
+async fn run_anonymous_update(
+    app: &AppHandle,
+    app_id: u32,
+    options: &UpdateOptions,
+    report: &RunReport
+) -> Result<String, SteamError> {
+    options.check()?;
+    let steamcmd_path = get_steamcmd_path(app)?;
     if !steamcmd_path.exists() {
-        return Err(format!("SteamCMD not found at {:?}", steamcmd_path));
+        return Err(SteamError::SteamCmdMissing { path: steamcmd_path.display().to_string() });
     }
+    make_executable(&steamcmd_path).await?;
+
+    let install_dir = install_dirs::prepare(app_id)?;
+    let branch = branches::for_app(app, app_id)?;
+    // Held until SteamCMD is done, so updates queued behind this one see the space as taken
+    let reservations = app.state::<DiskReservations>();
+    let _reservation = reservations.reserve(app_id, &disk_space::target(app, app_id)?)?;
 
     dev_log!("Starting update for app_id: {}", app_id);
     dev_log!("Using SteamCMD at: {:?}", steamcmd_path);
-    
-    let output = Command::new(&steamcmd_path)
-        .current_dir(&steamcmd_dir)
-        .args(&[
-            "+login", "anonymous",
-            "+app_update", &app_id.to_string(),
-            "validate",
-            "+quit"
-        ])
-        .output()
-        .map_err(|e| format!("Failed to execute SteamCMD: {}", e))?;
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
+    // A branch password must not show up in process listings, like a login
+    let script = match branch.as_ref().filter(|branch| branch.password.is_some()) {
+        Some(branch) => Some(LoginScript::commands(&anonymous_update_script(
+            app_id,
+            install_dir.as_deref(),
+            options,
+            Some(branch),
+        ))?),
+        None => None,
+    };
+    let spec = match &script {
+        Some(script) => script.spec(&steamcmd_path),
+        None => anonymous_update_spec(&steamcmd_path, app_id, install_dir.as_deref(), options, branch.as_ref()),
+    };
+    let mut spawned = SystemRunner.spawn(&spec)?;
+
+    let limits = Limits { idle_timeout: Some(IDLE_TIMEOUT), ..Default::default() };
+    let registry = app.state::<ProcessRegistry>();
+    let registration = spawned.child.id()
+        .map(|pid| registry.register(app_id, pid, limits.cancel.clone()))
+        .transpose();
+    let registration = match registration {
+        Ok(registration) => registration,
+        Err(e) => {
+            spawned.child.kill().await;
+            return Err(e);
+        }
+    };
+
+    let output = runner::collect(spawned, &limits, |line| emit_progress(app, app_id, line, report)).await?;
+    report.set_exit_code(output.exit_code);
 
-    dev_log!("SteamCMD stdout:\n{}", stdout);
-    if !stderr.is_empty() {
-        dev_log!("SteamCMD stderr:\n{}", stderr);
+    if registration.map(|registration| registration.finish()).unwrap_or(false) {
+        return Err(SteamError::UpdateCancelled { app_id });
     }
 
-    if !output.status.success() {
-        if stdout.contains("Invalid Platform") {
-            return Err("This game is not available for your platform".to_string());
-        }
-        if stdout.contains("No subscription") {
-            return Err("You don't have access to this game".to_string());
+    dev_log!("SteamCMD stdout:\n{}", output.stdout);
+    if !output.stderr.is_empty() {
+        dev_log!("SteamCMD stderr:\n{}", output.stderr);
+    }
+
+    anonymous_update_result(app_id, output).map_err(|e| update_error(e, app_id, options, branch.as_ref()))
+}
+
+fn anonymous_update_spec(
+    steamcmd_path: &Path,
+    app_id: u32,
+    install_dir: Option<&Path>,
+    options: &UpdateOptions,
+    branch: Option<&BetaBranch>
+) -> ProcessSpec {
+    // force_install_dir only takes effect when it comes before the login
+    let mut spec = ProcessSpec::new(steamcmd_path);
+    if let Some(install_dir) = install_dir {
+        spec = spec.arg("+force_install_dir").arg(install_dir.display().to_string());
+    }
+    for (name, value) in options.platform_settings() {
+        spec = spec.arg(format!("+{}", name)).arg(value);
+    }
+    spec = spec.arg("+login").arg("anonymous").arg("+app_update");
+    for arg in options.app_update_args(app_id, branch) {
+        spec = spec.arg(arg);
+    }
+    spec.arg("+quit")
+}
+
+/// The run of `anonymous_update_spec` as runscript lines.
+fn anonymous_update_script(
+    app_id: u32,
+    install_dir: Option<&Path>,
+    options: &UpdateOptions,
+    branch: Option<&BetaBranch>
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(install_dir) = install_dir {
+        lines.push(format!("force_install_dir {}", quote(&install_dir.display().to_string())));
+    }
+    for (name, value) in options.platform_settings() {
+        lines.push(format!("{} {}", name, value));
+    }
+    lines.push("login anonymous".to_string());
+    lines.push(options.app_update(app_id, branch));
+    lines.push("quit".to_string());
+    lines
+}
+
+/// Fills in what SteamCMD's output alone can't tell about a failed `app_update`.
+///
+/// Once logged in, an "Invalid Password" can only be about the branch
+/// password, and "Invalid Platform" is about the platform that was forced.
+fn update_error(error: SteamError, app_id: u32, options: &UpdateOptions, branch: Option<&BetaBranch>) -> SteamError {
+    match (error, branch) {
+        (SteamError::InvalidPassword, Some(BetaBranch { name, password: Some(_) })) => {
+            SteamError::InvalidBranchPassword { app_id, branch: name.clone() }
         }
-        if stdout.contains("FAILED") {
-            return Err(format!(
-                "Update failed. Please check the game ID and try again.\nError: {}",
-                if stderr.is_empty() { &stdout } else { &stderr }
-            ));
+        (SteamError::InvalidPlatform { app_id, .. }, _) => SteamError::InvalidPlatform {
+            app_id,
+            platform: options.target_platform().as_str().to_string(),
+            bitness: options.bitness,
+        },
+        (error, _) => error,
+    }
+}
+
+/// Turns the output of a one-shot `app_update` run into the command's result.
+fn anonymous_update_result(app_id: u32, output: ProcessOutput) -> Result<String, SteamError> {
+    if !output.success() {
+        if let Some(error) = SteamError::from_output(Some(app_id), &output.stdout) {
+            return Err(error);
         }
 
-        return Err(format!(
-            "SteamCMD failed with unknown error.\nOutput: {}\nError: {}", 
-            stdout, 
-            stderr
-        ));
+        let ProcessOutput { stdout, stderr, .. } = output;
+        return Err(SteamError::UpdateFailed {
+            app_id,
+            output: if stderr.is_empty() { stdout } else { format!("{}\n{}", stdout, stderr) },
+        });
     }
 
-    if stdout.contains("Success!") || stdout.contains("fully installed") {
+    if output.stdout.contains("Success!") || output.stdout.contains("fully installed") {
         dev_log!("Update completed successfully");
         Ok(format!("Successfully updated app {}", app_id))
     } else {
@@ -282,296 +424,608 @@ pub(crate) async fn update_game(app: AppHandle, app_id: u32) -> Result<String, S
 pub(crate) async fn update_game_authenticated(
     app: AppHandle,
     app_id: u32,
-    credentials: SteamCredentials
-) -> Result<String, String> {
-    let steamcmd_dir = get_steamcmd_dir(&app)?;
-    
-    let steamcmd_path = {
-        #[cfg(target_os = "windows")]
-        {
-            steamcmd_dir.join("steamcmd.exe")
-        }
-        #[cfg(not(target_os = "windows"))]
-        {
-            let path = steamcmd_dir.join("steamcmd.sh");
-            
-            // Ensure the file is executable
-            let output = Command::new("chmod")
-                .args(&["+x", path.to_str().unwrap()])
-                .output()
-                .map_err(|e| format!("Failed to make steamcmd executable: {}", e))?;
-
-            if !output.status.success() {
-                return Err("Failed to make steamcmd executable".to_string());
-            }
-            
-            path
-        }
+    credentials: SteamCredentials,
+    options: UpdateOptions
+) -> Result<String, SteamError> {
+    let attempt = history::start(app_id, Some(&credentials.username), &options);
+    let report = RunReport::default();
+    let result = run_authenticated_update(&app, app_id, &credentials, &options, &report).await;
+    history::finish(attempt, &result, &report);
+    result
+}
+
+/// Validates an installed app without asking for anything else, and counts
+/// the files SteamCMD had to reacquire.
+///
+/// The count comes from SteamCMD's output and, since it doesn't always print
+/// it, from what it appended to `logs/content_log.txt` during the run.
+pub(crate) async fn verify_game(
+    app: AppHandle,
+    app_id: u32,
+    credentials: Option<SteamCredentials>,
+    options: UpdateOptions
+) -> Result<VerifyReport, SteamError> {
+    // The platform has to match the install, or verifying swaps its files for another platform's
+    let options = UpdateOptions { validate: true, ..options };
+    let content_log = get_steamcmd_dir(&app)?.join("logs").join("content_log.txt");
+    let log_start = tokio::fs::metadata(&content_log).await.map(|metadata| metadata.len()).unwrap_or(0);
+
+    let username = credentials.as_ref().map(|credentials| credentials.username.as_str());
+    let attempt = history::start(app_id, username, &options);
+    let report = RunReport::default();
+    let result = match &credentials {
+        Some(credentials) => run_authenticated_update(&app, app_id, credentials, &options, &report).await,
+        None => run_anonymous_update(&app, app_id, &options, &report).await,
     };
+    history::finish(attempt, &result, &report);
+    let message = result?;
 
+    let logged = tokio::fs::read(&content_log).await
+        .map(|log| corrupted_files_in_log(&log[(log_start as usize).min(log.len())..], app_id))
+        .unwrap_or(0);
+    let files_corrupted = report.corrupted_files().max(logged);
+    dev_log!("Verified app {}: {} corrupted file(s)", app_id, files_corrupted);
+
+    Ok(VerifyReport { app_id, files_corrupted, files_fixed: files_corrupted, message })
+}
+
+/// Adds up validation failures in a stretch of the content log, skipping
+/// lines about other apps that were updating at the same time.
+fn corrupted_files_in_log(log: &[u8], app_id: u32) -> u64 {
+    let own_app = format!("AppID {} ", app_id);
+    String::from_utf8_lossy(log)
+        .lines()
+        .filter(|line| !line.contains("AppID ") || line.contains(&own_app))
+        .filter_map(progress::parse_validation_failures)
+        .sum()
+}
+
+/// Runs `app_update` on the account's long-lived SteamCMD session.
+///
+/// Updates for the same account reuse one login, so Steam Guard is only
+/// asked for when the session starts.
+async fn run_authenticated_update(
+    app: &AppHandle,
+    app_id: u32,
+    credentials: &SteamCredentials,
+    options: &UpdateOptions,
+    report: &RunReport
+) -> Result<String, SteamError> {
+    options.check()?;
+    let steamcmd_path = get_steamcmd_path(app)?;
     if !steamcmd_path.exists() {
-        return Err(format!("SteamCMD not found at {:?}", steamcmd_path));
+        return Err(SteamError::SteamCmdMissing { path: steamcmd_path.display().to_string() });
     }
 
+    let install_dir = install_dirs::prepare(app_id)?;
+    let branch = branches::for_app(app, app_id)?;
+    // Held until SteamCMD is done, so updates queued behind this one see the space as taken
+    let reservations = app.state::<DiskReservations>();
+    let _reservation = reservations.reserve(app_id, &disk_space::target(app, app_id)?)?;
+
     dev_log!("Starting authenticated update for app_id: {}", app_id);
-    
-    let mut child = Command::new(&steamcmd_path)
-        .current_dir(&steamcmd_dir)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to start SteamCMD: {}", e))?;
-
-    let stdout = child.stdout.take()
-        .ok_or_else(|| "Failed to capture stdout".to_string())?;
-    let stdin = child.stdin.take()
-        .ok_or_else(|| "Failed to capture stdin".to_string())?;
-    
-    // Wrap stdin in Arc<Mutex>
-    let stdin = Arc::new(Mutex::new(stdin));
-    let stdin_clone = stdin.clone();
-    
-    let reader = BufReader::new(stdout);
-    let app_handle = app.clone();
-
-    // Create a channel for 2FA code communication
-    let (tx, rx) = std::sync::mpsc::channel::<String>();
-
-    // Get the main window
-    let window = app_handle.get_webview_window("main")
-        .ok_or_else(|| "Failed to get main window".to_string())?;
-
-    // Set up event listener
-    let tx_clone = tx.clone();
-    window.listen("submit-2fa-code", move |event| {
-        let payload = event.payload();
-        let _ = tx_clone.send(payload.to_string());
-    });
-
-    // Spawn a thread to read stdout
-    std::thread::spawn(move || {
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                dev_log!("SteamCMD: {}", line);
-                let line_clone = line.clone(); // Clone the line for later use
-                
-                // Emit progress updates
-                if line.contains("Update state") || line.contains("Progress:") {
-                    if let Err(e) = window.emit("steam-update-progress", line) {
-                        dev_log!("Failed to emit progress event: {}", e);
-                    }
-                }
-                
-                // Check for 2FA prompt
-                if line_clone.contains("Two factor code:") {
-                    if let Err(e) = window.emit("steam-2fa-required", ()) {
-                        dev_log!("Failed to emit 2FA event: {}", e);
-                    }
-                    
-                    // Wait for 2FA code from frontend
-                    if let Ok(code) = rx.recv() {
-                        if let Ok(mut stdin) = stdin_clone.lock() {
-                            if let Err(e) = writeln!(&mut stdin, "{}", code) {
-                                dev_log!("Failed to send 2FA code: {}", e);
-                            }
-                        }
-                    }
-                }
-                
-                // Check for success/failure conditions
-                if line_clone.contains("Success!") || line_clone.contains("fully installed") {
-                    if let Err(e) = window.emit("steam-update-success", app_id.to_string()) {
-                        dev_log!("Failed to emit success event: {}", e);
-                    }
-                }
-                
-                if line_clone.contains("FAILED") || line_clone.contains("ERROR") {
-                    if let Err(e) = window.emit("steam-update-error", line_clone) {
-                        dev_log!("Failed to emit error event: {}", e);
-                    }
-                }
+
+    let settings = options.platform_settings().into_iter()
+        .map(|(name, value)| format!("{} {}", name, value))
+        .collect();
+    let command = options.app_update(app_id, branch.as_ref());
+    let execution = app.state::<SessionManager>()
+        .execute(app, credentials, Some(app_id), install_dir, settings, command)
+        .await?;
+    let output = execution.finish(|line| {
+        dev_log!("SteamCMD: {}", line);
+        emit_progress(app, app_id, line, report);
+
+        if line.contains("FAILED") || line.contains("ERROR") {
+            if let Err(e) = app.emit("steam-update-error", line) {
+                dev_log!("Failed to emit error event: {}", e);
             }
         }
-    });
-
-    // Send initial login command (using stored credentials)
-    if let Ok(mut stdin) = stdin.lock() {
-        writeln!(&mut stdin, "login {}", credentials.username)
-            .map_err(|e| format!("Failed to send login command: {}", e))?;
+    }).await?;
 
-        // Send update command
-        writeln!(&mut stdin, "app_update {} validate", app_id)
-            .map_err(|e| format!("Failed to send update command: {}", e))?;
-
-        // Send quit command
-        writeln!(&mut stdin, "quit")
-            .map_err(|e| format!("Failed to send quit command: {}", e))?;
+    let result = authenticated_update_result(app_id, output)
+        .map_err(|e| update_error(e, app_id, options, branch.as_ref()));
+    if result.is_ok() {
+        if let Err(e) = app.emit("steam-update-success", app_id.to_string()) {
+            dev_log!("Failed to emit success event: {}", e);
+        }
     }
+    result
+}
 
-    // Wait for the process to complete
-    let status = child.wait()
-        .map_err(|e| format!("Failed to wait for SteamCMD: {}", e))?;
-
-    if status.success() {
+/// Turns what `app_update` printed on a session into the command's result.
+pub(crate) fn authenticated_update_result(app_id: u32, output: String) -> Result<String, SteamError> {
+    if output.contains("Success! App") || output.contains("fully installed") {
         Ok(format!("Successfully updated app {}", app_id))
     } else {
-        Err("Update process failed".to_string())
+        Err(SteamError::from_output(Some(app_id), &output)
+            .unwrap_or(SteamError::UpdateFailed { app_id, output }))
     }
 }
 
-// Add a new command handler for 2FA code submission
-#[tauri::command]
-pub async fn submit_2fa_code(
-    code: String,
-    stdin: tauri::State<'_, std::sync::Mutex<std::process::ChildStdin>>
-) -> Result<(), String> {
-    let mut stdin = stdin.lock()
-        .map_err(|e| format!("Failed to lock stdin: {}", e))?;
-    
-    writeln!(stdin, "{}", code)
-        .map_err(|e| format!("Failed to send 2FA code: {}", e))?;
-    
-    Ok(())
-}
-
 pub(crate) async fn authenticate_steam(
     app: AppHandle,
     credentials: SteamCredentials
-) -> Result<String, String> {
-    let steamcmd_dir = get_steamcmd_dir(&app)?;
-    
-    let steamcmd_path = {
-        #[cfg(target_os = "windows")]
-        {
-            steamcmd_dir.join("steamcmd.exe")
-        }
-        #[cfg(not(target_os = "windows"))]
-        {
-            let path = steamcmd_dir.join("steamcmd.sh");
-            
-            // Ensure the file is executable
-            let output = Command::new("chmod")
-                .args(&["+x", path.to_str().unwrap()])
-                .output()
-                .map_err(|e| format!("Failed to make steamcmd executable: {}", e))?;
-
-            if !output.status.success() {
-                return Err("Failed to make steamcmd executable".to_string());
-            }
-            
-            path
-        }
-    };
-
+) -> Result<String, SteamError> {
+    let steamcmd_path = get_steamcmd_path(&app)?;
     if !steamcmd_path.exists() {
-        return Err(format!("SteamCMD not found at {:?}", steamcmd_path));
-    }
-
-    dev_log!("Starting Steam authentication...");
-    
-    // First attempt - this will always trigger 2FA
-    let mut args = vec![
-        "+login".to_string(),
-        credentials.username.clone(),
-        credentials.password.clone(),
-    ];
-    args.push("+quit".to_string());
-
-    let output = Command::new(&steamcmd_path)
-        .current_dir(&steamcmd_dir)
-        .args(&args)
-        .output()
-        .map_err(|e| format!("Failed to execute SteamCMD: {}", e))?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
-
-    dev_log!("Initial auth attempt stdout:\n{}", stdout);
-    if !stderr.is_empty() {
-        dev_log!("Initial auth attempt stderr:\n{}", stderr);
+        return Err(SteamError::SteamCmdMissing { path: steamcmd_path.display().to_string() });
     }
+    make_executable(&steamcmd_path).await?;
+    accounts::validate(&credentials.username, &credentials.password)?;
 
-    // Check for invalid credentials first
-    if stdout.contains("Invalid Password") {
-        return Err("Invalid username or password".to_string());
+    // A secret passed in is stored on success; otherwise use the one on file
+    let new_shared_secret = credentials.shared_secret.clone();
+    if let Some(shared_secret) = &new_shared_secret {
+        totp::decode_secret(shared_secret)?;
     }
+    let shared_secret = match new_shared_secret.clone() {
+        Some(shared_secret) => Some(shared_secret),
+        None => accounts::load_shared_secret(&app, &credentials.username).unwrap_or(None),
+    };
 
-    // If we don't have a 2FA code yet, request it
-    if credentials.two_factor_code.is_none() {
-        if stdout.contains("Steam Guard code") || stdout.contains("Two-factor code:") {
-            return Err("Steam Guard code required".to_string());
+    dev_log!("Starting Steam authentication...");
+    let (app_ref, shared_secret) = (&app, shared_secret.as_deref());
+    login(&SystemRunner, &steamcmd_path, &credentials, move || async move {
+        match shared_secret {
+            Some(shared_secret) => {
+                dev_log!("Generating Steam Guard code from the shared secret");
+                totp::current_code(app_ref, shared_secret).await.map(Some)
+            }
+            None => Ok(None),
         }
+    }).await?;
+
+    dev_log!("Authentication successful, saving credentials");
+    let saved = app.state::<Vault>()
+        .encrypt(&credentials.password)
+        .and_then(|password| {
+            Ok(db::save_credentials(&credentials.username, &password)?)
+        })
+        .and_then(|_| match &new_shared_secret {
+            Some(shared_secret) => accounts::store_shared_secret(&app, &credentials.username, shared_secret),
+            None => Ok(()),
+        });
+    if let Err(e) = saved {
+        dev_log!("Failed to save credentials: {}", e);
     }
+    Ok(format!("Successfully authenticated as {}", credentials.username))
+}
 
-    // If we have a 2FA code, try the full authentication
-    if let Some(code) = credentials.two_factor_code {
-        dev_log!("Attempting authentication with 2FA code...");
-        let mut args = vec![
-            "+login".to_string(),
-            credentials.username.clone(),
-            credentials.password.clone(),
-            code,
-        ];
-        args.push("+quit".to_string());
-
-        let output = Command::new(&steamcmd_path)
-            .current_dir(&steamcmd_dir)
-            .args(&args)
-            .output()
-            .map_err(|e| format!("Failed to execute SteamCMD: {}", e))?;
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-
-        dev_log!("2FA auth attempt stdout:\n{}", stdout);
-        if !stderr.is_empty() {
-            dev_log!("2FA auth attempt stderr:\n{}", stderr);
+/// Checks credentials with one-shot SteamCMD runs.
+///
+/// Without a code the first run finds out whether Steam Guard is on. A
+/// mobile authenticator prompt can then be answered with `generate_code`,
+/// an e-mail prompt needs the user to call again with the code.
+async fn login<F, Fut>(
+    runner: &dyn ProcessRunner,
+    steamcmd_path: &Path,
+    credentials: &SteamCredentials,
+    generate_code: F
+) -> Result<(), SteamError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Option<String>, SteamError>>,
+{
+    let code = match &credentials.two_factor_code {
+        Some(code) => code.clone(),
+        None => {
+            let output = run_login_script(runner, steamcmd_path, credentials, None).await?;
+
+            // Check for invalid credentials first
+            if output.contains("Invalid Password") {
+                return Err(SteamError::InvalidPassword);
+            }
+            let mobile_prompt = output.contains("Two-factor code") || output.contains("Two factor code");
+            if !mobile_prompt && !output.contains("Steam Guard code") {
+                // Steam Guard is off for this account
+                return login_result(output);
+            }
+            if !mobile_prompt {
+                return Err(SteamError::SteamGuardRequired);
+            }
+            generate_code().await?.ok_or(SteamError::SteamGuardRequired)?
         }
+    };
 
-        if stdout.contains("Invalid Steam Guard code") || stdout.contains("Invalid two-factor code") {
-            return Err("Invalid Steam Guard code".to_string());
-        }
+    dev_log!("Attempting authentication with 2FA code...");
+    let output = run_login_script(runner, steamcmd_path, credentials, Some(&code)).await?;
+    login_result(output)
+}
 
-        if stdout.contains("Waiting for user info...OK") {
-            dev_log!("Authentication successful, saving credentials");
-            if let Err(e) = db::save_credentials(
-                &credentials.username,
-                &credentials.password.clone()
-            ) {
-                dev_log!("Failed to save credentials: {}", e);
-            }
-            Ok(format!("Successfully authenticated as {}", credentials.username))
-        } else if stdout.contains("Invalid Password") {
-            Err("Invalid username or password".to_string())
-        } else if stdout.contains("Invalid Steam Guard code") || stdout.contains("Invalid two-factor code") {
-            Err("Invalid Steam Guard code".to_string())
-        } else if !stdout.contains("Steam Guard code provided") {
-            Err("Steam Guard code required".to_string())
-        } else {
-            dev_log!("Authentication completed but success message not found");
-            dev_log!("Full output:\n{}", stdout);
-            Err("Authentication failed. Please check your credentials and try again.".to_string())
-        }
+async fn run_login_script(
+    runner: &dyn ProcessRunner,
+    steamcmd_path: &Path,
+    credentials: &SteamCredentials,
+    two_factor_code: Option<&str>
+) -> Result<String, SteamError> {
+    // The script is deleted as soon as SteamCMD has exited
+    let script = LoginScript::create(credentials, two_factor_code)?;
+    let limits = Limits { idle_timeout: Some(IDLE_TIMEOUT), ..Default::default() };
+    let output = runner::collect(runner.spawn(&script.spec(steamcmd_path))?, &limits, |_| {}).await?;
+
+    dev_log!("Auth attempt stdout:\n{}", output.stdout);
+    if !output.stderr.is_empty() {
+        dev_log!("Auth attempt stderr:\n{}", output.stderr);
+    }
+    Ok(output.stdout)
+}
+
+fn login_result(output: String) -> Result<(), SteamError> {
+    if output.contains("Waiting for user info...OK") {
+        Ok(())
+    } else if let Some(error) = SteamError::from_output(None, &output) {
+        Err(error)
+    } else if !output.contains("Steam Guard code provided") {
+        Err(SteamError::SteamGuardRequired)
     } else {
-        // This should never happen due to the earlier check, but just in case
-        Err("Steam Guard code required".to_string())
+        dev_log!("Authentication completed but success message not found");
+        Err(SteamError::AuthenticationFailed { output })
     }
 }
 
-pub(crate) async fn get_stored_credentials(_app: AppHandle) -> Result<Option<SteamCredentials>, String> {
+/// Loads the stored account and decrypts its password with the unlocked vault.
+pub(crate) fn load_credentials(app: &AppHandle) -> Result<Option<SteamCredentials>, SteamError> {
     match db::get_credentials() {
         Ok(Some((username, password))) => Ok(Some(SteamCredentials {
-            username,
-            password,
-            two_factor_code: None
+            password: app.state::<Vault>().decrypt(&password)?,
+            two_factor_code: None,
+            shared_secret: accounts::load_shared_secret(app, &username)?,
+            username
         })),
         Ok(None) => Ok(None),
-        Err(e) => Err(format!("Failed to get credentials: {}", e))
+        Err(e) => Err(e.into())
     }
 }
 
-pub(crate) async fn clear_stored_credentials(_app: AppHandle) -> Result<(), String> {
-    db::clear_credentials()
-        .map_err(|e| format!("Failed to clear credentials: {}", e))
-} 
\ No newline at end of file
+pub(crate) async fn get_stored_credentials(app: AppHandle) -> Result<Option<SteamCredentials>, SteamError> {
+    load_credentials(&app)
+}
+
+pub(crate) async fn clear_stored_credentials(_app: AppHandle) -> Result<(), SteamError> {
+    Ok(db::clear_credentials()?)
+} 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::fake::{FakeRunner, FakeSteamCmd};
+
+    const ANONYMOUS_SUCCESS: &str = include_str!("../testdata/transcripts/anonymous_update_success.txt");
+    const ANONYMOUS_NO_SUBSCRIPTION: &str = include_str!("../testdata/transcripts/anonymous_no_subscription.txt");
+    const ANONYMOUS_INVALID_PLATFORM: &str = include_str!("../testdata/transcripts/anonymous_invalid_platform.txt");
+    const RUNSCRIPT_LOGIN: &str = include_str!("../testdata/transcripts/runscript_login.txt");
+    const RUNSCRIPT_GUARD_PROMPT: &str = include_str!("../testdata/transcripts/runscript_guard_prompt.txt");
+    const RUNSCRIPT_EMAIL_PROMPT: &str = include_str!("../testdata/transcripts/runscript_email_prompt.txt");
+    const RUNSCRIPT_INVALID_PASSWORD: &str = include_str!("../testdata/transcripts/runscript_invalid_password.txt");
+
+    const STEAMCMD: &str = "/opt/steamcmd/steamcmd.sh";
+
+    fn credentials(two_factor_code: Option<&str>) -> SteamCredentials {
+        SteamCredentials {
+            username: "venue_bot".to_string(),
+            password: "hunter2".to_string(),
+            two_factor_code: two_factor_code.map(str::to_string),
+            shared_secret: None,
+        }
+    }
+
+    async fn no_code() -> Result<Option<String>, SteamError> {
+        Ok(None)
+    }
+
+    async fn anonymous_update(
+        runner: &dyn ProcessRunner,
+        steamcmd_path: &Path,
+        app_id: u32,
+        options: &UpdateOptions,
+        lines: &mut Vec<String>
+    ) -> Result<String, SteamError> {
+        let spec = anonymous_update_spec(steamcmd_path, app_id, Some(Path::new("/srv/games/740")), options, None);
+        let output = runner::collect(runner.spawn(&spec)?, &Limits::default(), |line| lines.push(line.to_string())).await?;
+        anonymous_update_result(app_id, output).map_err(|e| update_error(e, app_id, options, None))
+    }
+
+    #[test]
+    fn anonymous_update_sets_the_install_dir_before_logging_in() {
+        let spec = anonymous_update_spec(Path::new(STEAMCMD), 740, Some(Path::new("/srv/games/740")), &UpdateOptions::default(), None);
+
+        assert_eq!(spec.args, vec![
+            "+force_install_dir", "/srv/games/740",
+            "+login", "anonymous",
+            "+app_update", "740",
+            "+quit",
+        ]);
+        assert_eq!(spec.current_dir.as_deref(), Some(Path::new("/opt/steamcmd")));
+    }
+
+    #[test]
+    fn validate_is_only_passed_when_asked_for() {
+        let validate = UpdateOptions { validate: true, ..Default::default() };
+
+        let spec = anonymous_update_spec(Path::new(STEAMCMD), 740, None, &validate, None);
+
+        assert_eq!(spec.args, vec!["+login", "anonymous", "+app_update", "740", "validate", "+quit"]);
+        assert_eq!(validate.app_update(740, None), "app_update 740 validate");
+        assert_eq!(UpdateOptions::default().app_update(740, None), "app_update 740");
+    }
+
+    #[test]
+    fn updates_follow_the_configured_branch() {
+        let previous = BetaBranch { name: "previous_build".to_string(), password: None };
+        let tournament = BetaBranch { name: "tournament".to_string(), password: Some("major 2023".to_string()) };
+
+        let spec = anonymous_update_spec(Path::new(STEAMCMD), 740, None, &UpdateOptions::default(), Some(&previous));
+
+        assert_eq!(spec.args, vec!["+login", "anonymous", "+app_update", "740", "-beta", "previous_build", "+quit"]);
+        assert_eq!(
+            UpdateOptions { validate: true, ..Default::default() }.app_update(740, Some(&tournament)),
+            "app_update 740 -beta tournament -betapassword \"major 2023\" validate"
+        );
+    }
+
+    #[test]
+    fn branch_passwords_go_into_the_script_not_the_arguments() {
+        let tournament = BetaBranch { name: "tournament".to_string(), password: Some("major 2023".to_string()) };
+
+        let lines = anonymous_update_script(740, Some(Path::new("/srv/games/740")), &UpdateOptions::default(), Some(&tournament));
+
+        assert_eq!(lines, vec![
+            "force_install_dir /srv/games/740",
+            "login anonymous",
+            "app_update 740 -beta tournament -betapassword \"major 2023\"",
+            "quit",
+        ]);
+    }
+
+    #[test]
+    fn rejected_passwords_during_updates_blame_the_branch() {
+        let tournament = BetaBranch { name: "tournament".to_string(), password: Some("major".to_string()) };
+        let public = None;
+
+        assert_eq!(
+            update_error(SteamError::InvalidPassword, 740, &UpdateOptions::default(), Some(&tournament)),
+            SteamError::InvalidBranchPassword { app_id: 740, branch: "tournament".to_string() }
+        );
+        assert_eq!(
+            update_error(SteamError::InvalidPassword, 740, &UpdateOptions::default(), public),
+            SteamError::InvalidPassword
+        );
+    }
+
+    #[test]
+    fn content_log_failures_of_other_apps_are_not_counted() {
+        let log = b"[2023-10-12 15:03:21] AppID 740 validation found 2 corrupt files\n\
+            [2023-10-12 15:03:22] AppID 896660 validation found 5 corrupt files\n\
+            [2023-10-12 15:03:23] 3 files failed to validate and will be reacquired.\n\
+            [2023-10-12 15:03:24] AppID 740 state changed : Update Required,Validating,\n";
+
+        assert_eq!(corrupted_files_in_log(log, 740), 5);
+    }
+
+    #[tokio::test]
+    async fn anonymous_update_succeeds() {
+        let runner = FakeRunner::new(&[ANONYMOUS_SUCCESS]);
+        let mut lines = Vec::new();
+
+        let result = anonymous_update(&runner, Path::new(STEAMCMD), 740, &UpdateOptions::default(), &mut lines).await;
+
+        assert_eq!(result, Ok("Successfully updated app 740".to_string()));
+        assert!(lines.iter().any(|line| line.contains("downloading, progress: 35.80")));
+    }
+
+    #[tokio::test]
+    async fn anonymous_update_without_a_subscription_fails() {
+        let runner = FakeRunner::new(&[ANONYMOUS_NO_SUBSCRIPTION]);
+
+        let result = anonymous_update(&runner, Path::new(STEAMCMD), 730, &UpdateOptions::default(), &mut Vec::new()).await;
+
+        assert_eq!(result, Err(SteamError::NoSubscription { app_id: 730 }));
+    }
+
+    #[tokio::test]
+    async fn anonymous_update_on_the_wrong_platform_fails() {
+        let runner = FakeRunner::new(&[ANONYMOUS_INVALID_PLATFORM]);
+
+        let result = anonymous_update(&runner, Path::new(STEAMCMD), 1829350, &UpdateOptions::default(), &mut Vec::new()).await;
+
+        assert_eq!(result, Err(SteamError::InvalidPlatform {
+            app_id: 1829350,
+            platform: Platform::host().as_str().to_string(),
+            bitness: None,
+        }));
+    }
+
+    #[tokio::test]
+    async fn forced_platforms_are_set_before_logging_in_and_named_in_errors() {
+        let runner = FakeRunner::new(&[ANONYMOUS_INVALID_PLATFORM]);
+        let options = UpdateOptions { platform: Some(Platform::Macos), bitness: Some(64), ..Default::default() };
+
+        let result = anonymous_update(&runner, Path::new(STEAMCMD), 1829350, &options, &mut Vec::new()).await;
+
+        assert_eq!(result, Err(SteamError::InvalidPlatform {
+            app_id: 1829350,
+            platform: "macos".to_string(),
+            bitness: Some(64),
+        }));
+        assert_eq!(runner.spawned()[0].args, vec![
+            "+force_install_dir", "/srv/games/740",
+            "+@sSteamCmdForcePlatformType", "macos",
+            "+@sSteamCmdForcePlatformBitness", "64",
+            "+login", "anonymous",
+            "+app_update", "1829350",
+            "+quit",
+        ]);
+    }
+
+    #[test]
+    fn only_32_and_64_bit_are_accepted() {
+        assert!(UpdateOptions { bitness: Some(32), ..Default::default() }.check().is_ok());
+        assert!(UpdateOptions { bitness: Some(128), ..Default::default() }.check().is_err());
+    }
+
+    #[test]
+    fn authenticated_update_reports_failures_it_does_not_recognise() {
+        let output = "ERROR! Failed to install app '730' (Missing configuration)\n".to_string();
+
+        let result = authenticated_update_result(730, output.clone());
+
+        assert_eq!(result, Err(SteamError::UpdateFailed { app_id: 730, output }));
+    }
+
+    #[tokio::test]
+    async fn login_without_steam_guard_needs_one_run() {
+        let runner = FakeRunner::new(&[RUNSCRIPT_LOGIN]);
+
+        let result = login(&runner, Path::new(STEAMCMD), &credentials(None), no_code).await;
+
+        assert_eq!(result, Ok(()));
+        let spawned = runner.spawned();
+        assert_eq!(spawned.len(), 1);
+        assert_eq!(spawned[0].args[0], "+runscript");
+        assert!(spawned[0].args.iter().all(|arg| !arg.contains("hunter2")));
+    }
+
+    #[tokio::test]
+    async fn login_with_a_provided_code_skips_the_probe() {
+        let runner = FakeRunner::new(&[RUNSCRIPT_LOGIN]);
+
+        let result = login(&runner, Path::new(STEAMCMD), &credentials(Some("F7K2M")), no_code).await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(runner.spawned().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn mobile_prompt_is_answered_with_a_generated_code() {
+        let runner = FakeRunner::new(&[RUNSCRIPT_GUARD_PROMPT, RUNSCRIPT_LOGIN]);
+
+        let result = login(&runner, Path::new(STEAMCMD), &credentials(None), || async {
+            Ok(Some("2BXQ7".to_string()))
+        }).await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(runner.spawned().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn mobile_prompt_without_a_shared_secret_asks_for_a_code() {
+        let runner = FakeRunner::new(&[RUNSCRIPT_GUARD_PROMPT]);
+
+        let result = login(&runner, Path::new(STEAMCMD), &credentials(None), no_code).await;
+
+        assert_eq!(result, Err(SteamError::SteamGuardRequired));
+        assert_eq!(runner.spawned().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn email_prompt_asks_for_a_code_without_generating_one() {
+        let runner = FakeRunner::new(&[RUNSCRIPT_EMAIL_PROMPT]);
+        let mut generated = false;
+
+        let result = login(&runner, Path::new(STEAMCMD), &credentials(None), || {
+            generated = true;
+            no_code()
+        }).await;
+
+        assert_eq!(result, Err(SteamError::SteamGuardRequired));
+        assert!(!generated);
+    }
+
+    #[tokio::test]
+    async fn login_with_a_wrong_password_fails() {
+        let runner = FakeRunner::new(&[RUNSCRIPT_INVALID_PASSWORD]);
+
+        let result = login(&runner, Path::new(STEAMCMD), &credentials(None), no_code).await;
+
+        assert_eq!(result, Err(SteamError::InvalidPassword));
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_runs_an_anonymous_update() {
+        let steamcmd = FakeSteamCmd::install("anonymous", "success");
+        let mut lines = Vec::new();
+
+        let result = anonymous_update(&SystemRunner, &steamcmd.entry_point(), 740, &UpdateOptions::default(), &mut lines).await;
+
+        assert_eq!(result, Ok("Successfully updated app 740".to_string()));
+        let progress: Vec<_> = lines.iter().filter_map(|line| progress::parse_line(line)).collect();
+        assert!(progress.len() > 20);
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_reports_files_that_failed_validation() {
+        let steamcmd = FakeSteamCmd::install("corrupt-files", "corrupt_files");
+        let mut lines = Vec::new();
+
+        let result = anonymous_update(&SystemRunner, &steamcmd.entry_point(), 740, &UpdateOptions { validate: true, ..Default::default() }, &mut lines).await;
+
+        assert!(result.is_ok());
+        let corrupted: u64 = lines.iter().filter_map(|line| progress::parse_validation_failures(line)).sum();
+        assert_eq!(corrupted, 3);
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_runs_a_branch_update_script() {
+        let steamcmd = FakeSteamCmd::install("branch-script", "success");
+        let tournament = BetaBranch { name: "tournament".to_string(), password: Some("major 2023".to_string()) };
+        let lines = anonymous_update_script(740, None, &UpdateOptions::default(), Some(&tournament));
+        let script = LoginScript::commands(&lines).unwrap();
+
+        let spawned = SystemRunner.spawn(&script.spec(&steamcmd.entry_point())).unwrap();
+        let output = runner::collect(spawned, &Limits::default(), |_| {}).await.unwrap();
+
+        assert!(output.stdout.contains("Success! App '740' fully installed."));
+        assert!(anonymous_update_result(740, output).is_ok());
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_fills_the_disk() {
+        let steamcmd = FakeSteamCmd::install("disk-full", "disk_full");
+
+        let result = anonymous_update(&SystemRunner, &steamcmd.entry_point(), 740, &UpdateOptions::default(), &mut Vec::new()).await;
+
+        assert_eq!(result, Err(SteamError::DiskFull { app_id: 740 }));
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_drops_the_network_halfway() {
+        let steamcmd = FakeSteamCmd::install("network-drop", "network_drop");
+        let mut lines = Vec::new();
+
+        let result = anonymous_update(&SystemRunner, &steamcmd.entry_point(), 740, &UpdateOptions::default(), &mut lines).await;
+
+        assert_eq!(result, Err(SteamError::NetworkUnavailable));
+        assert!(lines.iter().any(|line| line.contains("progress: 50.00")));
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_takes_a_generated_mobile_code() {
+        let steamcmd = FakeSteamCmd::install("mobile", "steam_guard_mobile");
+
+        let result = login(&SystemRunner, &steamcmd.entry_point(), &credentials(None), || async {
+            Ok(Some("2BXQ7".to_string()))
+        }).await;
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_asks_for_an_email_code() {
+        let steamcmd = FakeSteamCmd::install("email", "steam_guard_email");
+
+        let asked = login(&SystemRunner, &steamcmd.entry_point(), &credentials(None), no_code).await;
+        let answered = login(&SystemRunner, &steamcmd.entry_point(), &credentials(Some("F7K2M")), no_code).await;
+
+        assert_eq!(asked, Err(SteamError::SteamGuardRequired));
+        assert_eq!(answered, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn fake_steamcmd_rate_limits_the_login() {
+        let steamcmd = FakeSteamCmd::install("rate-limit", "rate_limit");
+
+        let result = login(&SystemRunner, &steamcmd.entry_point(), &credentials(None), no_code).await;
+
+        assert_eq!(result, Err(SteamError::RateLimited));
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha1::Sha1;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_http::reqwest;
+use crate::error::SteamError;
+use crate::queue::now;
+
+/// Steam Guard codes use this alphabet instead of decimal digits.
+const ALPHABET: &[u8; 26] = b"23456789BCDFGHJKMNPQRTVWXY";
+const CODE_LENGTH: usize = 5;
+/// Seconds each code stays valid.
+const PERIOD: i64 = 30;
+const QUERY_TIME_URL: &str = "https://api.steampowered.com/ITwoFactorService/QueryTime/v0001";
+/// How long to wait for Steam's time before falling back to the local clock.
+const QUERY_TIME_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[TOTP] {}", format!($($arg)*));
+    }
+}
+
+/// Difference between Steam's clock and ours, learned once per run.
+///
+/// Codes are only accepted within a window or two of Steam's time, so a
+/// machine whose clock drifts would otherwise produce rejected codes.
+#[derive(Default)]
+pub struct SteamClock {
+    offset: Mutex<Option<i64>>,
+}
+
+impl SteamClock {
+    fn offset(&self) -> Option<i64> {
+        self.offset.lock().ok().and_then(|offset| *offset)
+    }
+
+    fn set_offset(&self, value: Option<i64>) {
+        if let Ok(mut offset) = self.offset.lock() {
+            *offset = value;
+        }
+    }
+}
+
+/// Generates the Steam Guard code for `shared_secret` at `time` (Unix seconds).
+pub(crate) fn generate_code(shared_secret: &str, time: i64) -> Result<String, SteamError> {
+    let secret = decode_secret(shared_secret)?;
+
+    let counter = (time / PERIOD) as u64;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&secret)
+        .map_err(|e| SteamError::Crypto { reason: format!("Invalid shared secret: {}", e) })?;
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation as in RFC 4226, then base-26 digits instead of decimal
+    let start = (hash[19] & 0x0f) as usize;
+    let mut value = u32::from_be_bytes([hash[start], hash[start + 1], hash[start + 2], hash[start + 3]]) & 0x7fff_ffff;
+
+    let mut code = String::with_capacity(CODE_LENGTH);
+    for _ in 0..CODE_LENGTH {
+        code.push(ALPHABET[value as usize % ALPHABET.len()] as char);
+        value /= ALPHABET.len() as u32;
+    }
+    Ok(code)
+}
+
+/// Checks that a shared secret is the base64 value from a mobile authenticator export.
+pub(crate) fn decode_secret(shared_secret: &str) -> Result<Vec<u8>, SteamError> {
+    BASE64.decode(shared_secret.trim())
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .ok_or_else(|| SteamError::InvalidInput { reason: "Shared secret is not valid base64".to_string() })
+}
+
+/// The current code for `shared_secret`, using Steam's clock when it can be reached.
+pub(crate) async fn current_code(app: &AppHandle, shared_secret: &str) -> Result<String, SteamError> {
+    let clock = app.state::<SteamClock>();
+    let offset = match clock.offset() {
+        Some(offset) => offset,
+        None => sync_clock(app).await,
+    };
+    generate_code(shared_secret, now() + offset)
+}
+
+/// Asks Steam for its time again, e.g. after a generated code was rejected.
+///
+/// Falls back to the local clock if Steam cannot be reached; the offset is
+/// then left unset so the next code tries again.
+pub(crate) async fn sync_clock(app: &AppHandle) -> i64 {
+    let clock = app.state::<SteamClock>();
+    match query_server_time().await {
+        Ok(server_time) => {
+            let offset = server_time - now();
+            dev_log!("Steam clock offset is {}s", offset);
+            clock.set_offset(Some(offset));
+            offset
+        }
+        Err(e) => {
+            dev_log!("Failed to query Steam time, using the local clock: {}", e);
+            clock.set_offset(None);
+            0
+        }
+    }
+}
+
+async fn query_server_time() -> Result<i64, SteamError> {
+    let client = reqwest::Client::builder()
+        .timeout(QUERY_TIME_TIMEOUT)
+        .build()
+        .map_err(|e| SteamError::internal("Failed to create HTTP client", e))?;
+    let body = client
+        .post(QUERY_TIME_URL)
+        .header("Content-Length", "0")
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|_| SteamError::NetworkUnavailable)?
+        .text()
+        .await
+        .map_err(|_| SteamError::NetworkUnavailable)?;
+    let response: Value = serde_json::from_str(&body)
+        .map_err(|e| SteamError::internal("Failed to read Steam time", e))?;
+
+    // `server_time` is a string in the response, accept a number as well
+    let server_time = &response["response"]["server_time"];
+    server_time.as_str()
+        .and_then(|time| time.parse().ok())
+        .or_else(|| server_time.as_i64())
+        .ok_or_else(|| SteamError::Internal { reason: "Steam time response has no server_time".to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// base64 of "updateio-test-secret"
+    const SECRET: &str = "dXBkYXRlaW8tdGVzdC1zZWNyZXQ=";
+
+    #[test]
+    fn generates_known_codes() {
+        assert_eq!(generate_code(SECRET, 0).unwrap(), "T82VQ");
+        assert_eq!(generate_code(SECRET, 30).unwrap(), "FWVWD");
+        assert_eq!(generate_code(SECRET, 1_700_000_009).unwrap(), "PDWB9");
+        assert_eq!(generate_code(SECRET, 1_700_000_010).unwrap(), "H6NVP");
+    }
+
+    #[test]
+    fn codes_last_one_period() {
+        assert_eq!(generate_code(SECRET, 29).unwrap(), generate_code(SECRET, 0).unwrap());
+        assert_eq!(generate_code(SECRET, 1_700_000_039).unwrap(), "H6NVP");
+    }
+
+    #[test]
+    fn decodes_valid_secrets() {
+        assert_eq!(decode_secret(SECRET).unwrap(), b"updateio-test-secret");
+        assert_eq!(decode_secret(&format!(" {}\n", SECRET)).unwrap(), b"updateio-test-secret");
+    }
+
+    #[test]
+    fn rejects_invalid_secrets() {
+        assert!(matches!(decode_secret("not base64!!"), Err(SteamError::InvalidInput { .. })));
+        assert!(matches!(decode_secret(""), Err(SteamError::InvalidInput { .. })));
+        assert!(generate_code("not base64!!", 0).is_err());
+    }
+}
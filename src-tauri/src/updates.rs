@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use serde::Serialize;
+use tauri::AppHandle;
+use crate::error::SteamError;
+use crate::library::{self, InstalledGame};
+use crate::processes;
+use crate::runner::{self, Limits, ProcessRunner, ProcessSpec, SystemRunner};
+use crate::steam;
+use crate::vdf::{self, KeyValues};
+
+/// Fetching app info is one request per app, but a cold SteamCMD updates itself first.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Updates] {}", format!($($arg)*));
+    }
+}
+
+/// An installed app whose configured branch has moved on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutdatedApp {
+    pub app_id: u32,
+    pub name: String,
+    pub install_dir: String,
+    /// The branch the remote build is from.
+    pub branch: String,
+    pub local_build_id: u64,
+    pub remote_build_id: u64,
+    /// Unix time the branch got the new build, when Steam says.
+    pub remote_updated: Option<i64>,
+}
+
+/// Build of one of an app's branches, as `app_info_print` reports it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RemoteBuild {
+    build_id: u64,
+    updated: Option<i64>,
+}
+
+/// Lists the installed apps whose local build differs from their configured branch.
+///
+/// Only app info is fetched, nothing is downloaded. `app_ids` limits the
+/// check to those apps; apps Steam returns no build for are left out.
+/// Password-protected branches still list their build id.
+pub(crate) async fn check_updates(app: AppHandle, app_ids: Option<Vec<u32>>) -> Result<Vec<OutdatedApp>, SteamError> {
+    processes::ensure_steamcmd_idle(&app).await?;
+
+    let steamcmd_path = steam::get_steamcmd_path(&app)?;
+    if !steamcmd_path.exists() {
+        return Err(SteamError::SteamCmdMissing { path: steamcmd_path.display().to_string() });
+    }
+
+    let mut installed = library::installed_games(app).await?;
+    if let Some(app_ids) = &app_ids {
+        installed.retain(|game| app_ids.contains(&game.app_id));
+    }
+    if installed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut requested: Vec<u32> = installed.iter().map(|game| game.app_id).collect();
+    requested.dedup();
+    let remote = remote_builds(&SystemRunner, &steamcmd_path, &requested).await?;
+
+    Ok(outdated(&installed, &remote))
+}
+
+/// Compares every installed copy with the remote build of the branch it is configured for.
+fn outdated(installed: &[InstalledGame], remote: &HashMap<(u32, String), RemoteBuild>) -> Vec<OutdatedApp> {
+    installed.iter()
+        .filter_map(|game| {
+            let Some(remote) = remote.get(&(game.app_id, game.configured_branch.clone())) else {
+                dev_log!("No {} build for app {}", game.configured_branch, game.app_id);
+                return None;
+            };
+            (remote.build_id != game.build_id).then(|| OutdatedApp {
+                app_id: game.app_id,
+                name: game.name.clone(),
+                install_dir: game.install_dir.clone(),
+                branch: game.configured_branch.clone(),
+                local_build_id: game.build_id,
+                remote_build_id: remote.build_id,
+                remote_updated: remote.updated,
+            })
+        })
+        .collect()
+}
+
+/// Asks Steam for the builds of every branch of `app_ids` in a single anonymous SteamCMD run.
+async fn remote_builds(
+    runner: &dyn ProcessRunner,
+    steamcmd_path: &Path,
+    app_ids: &[u32]
+) -> Result<HashMap<(u32, String), RemoteBuild>, SteamError> {
+    let spawned = runner.spawn(&app_info_spec(steamcmd_path, app_ids))?;
+    let limits = Limits { idle_timeout: Some(IDLE_TIMEOUT), ..Default::default() };
+    let output = runner::collect(spawned, &limits, |_| {}).await?;
+
+    // A failed run may have printed nothing useful, which must not read as "up to date"
+    if !output.success() {
+        return Err(SteamError::from_output(None, &output.stdout).unwrap_or_else(|| SteamError::CommandFailed {
+            program: steamcmd_path.display().to_string(),
+            exit_code: output.exit_code,
+            output: format!("{}{}", output.stdout, output.stderr),
+        }));
+    }
+
+    let mut builds = HashMap::new();
+    for &app_id in app_ids {
+        let Some(info) = app_info(&output.stdout, app_id) else {
+            continue;
+        };
+        let Some(branches) = info.get("depots").and_then(|depots| depots.get("branches")) else {
+            continue;
+        };
+        for (branch, details) in branches.children() {
+            if let Some(build_id) = details.u64("buildid") {
+                builds.insert((app_id, branch.clone()), RemoteBuild {
+                    build_id,
+                    updated: details.u64("timeupdated").map(|updated| updated as i64),
+                });
+            }
+        }
+    }
+    Ok(builds)
+}
+
+fn app_info_spec(steamcmd_path: &Path, app_ids: &[u32]) -> ProcessSpec {
+    // app_info_update refreshes SteamCMD's cache, otherwise the print may show a stale build
+    let mut spec = ProcessSpec::new(steamcmd_path)
+        .arg("+login").arg("anonymous")
+        .arg("+app_info_update").arg("1");
+    for app_id in app_ids {
+        spec = spec.arg("+app_info_print").arg(app_id.to_string());
+    }
+    spec.arg("+quit")
+}
+
+/// Cuts the `"<app_id>" { ... }` document for one app out of everything SteamCMD printed.
+fn app_info(output: &str, app_id: u32) -> Option<KeyValues> {
+    // Start at the app's own header, a depot of another app may share its number
+    let header = output.find(&format!("AppID : {},", app_id)).unwrap_or(0);
+    let key = format!("\"{}\"", app_id);
+    let start = output[header..].lines()
+        .scan(header, |offset, line| {
+            let start = *offset;
+            *offset += line.len() + 1;
+            Some((start, line))
+        })
+        .find(|(_, line)| line.trim() == key)
+        .map(|(start, _)| start)?;
+
+    let document = &output[start..];
+    let end = document_end(document)?;
+    match vdf::parse(&document[..end]) {
+        Ok((_, info)) => Some(info),
+        Err(e) => {
+            dev_log!("Unreadable app info for app {}: {}", app_id, e);
+            None
+        }
+    }
+}
+
+/// Where the first top-level `{ ... }` of `text` closes, skipping braces inside strings.
+fn document_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (index, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::fake::FakeRunner;
+
+    const APP_INFO: &str = include_str!("../testdata/transcripts/app_info_print.txt");
+
+    fn installed(app_id: u32, build_id: u64) -> InstalledGame {
+        InstalledGame {
+            app_id,
+            name: format!("App {}", app_id),
+            build_id,
+            install_dir: format!("/srv/games/{}", app_id),
+            size_on_disk: 0,
+            state_flags: 4,
+            last_updated: 0,
+            library: "/srv/games".to_string(),
+            branch: "public".to_string(),
+            configured_branch: "public".to_string(),
+            branch_mismatch: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_public_builds_from_one_run() {
+        let runner = FakeRunner::new(&[APP_INFO]);
+
+        let remote = remote_builds(&runner, Path::new("/opt/steamcmd/steamcmd.sh"), &[740, 896660, 4000]).await.unwrap();
+
+        assert_eq!(remote.get(&(740, "public".to_string())), Some(&RemoteBuild { build_id: 14512400, updated: Some(1697122800) }));
+        assert_eq!(remote.get(&(896660, "public".to_string())), Some(&RemoteBuild { build_id: 12187530, updated: Some(1696930211) }));
+        assert_eq!(remote.get(&(740, "beta".to_string())), Some(&RemoteBuild { build_id: 14600001, updated: Some(1697200000) }));
+        assert!(remote.keys().all(|(app_id, _)| *app_id != 4000));
+        assert_eq!(runner.spawned()[0].args, vec![
+            "+login", "anonymous", "+app_info_update", "1",
+            "+app_info_print", "740", "+app_info_print", "896660", "+app_info_print", "4000", "+quit",
+        ]);
+        assert_eq!(runner.spawned()[0].program, Path::new("/opt/steamcmd/steamcmd.sh"));
+    }
+
+    #[tokio::test]
+    async fn a_failed_run_is_an_error_not_an_empty_result() {
+        let runner = FakeRunner::new(&["< Loading Steam API...OK\n< Segmentation fault (core dumped)\n= 139\n"]);
+
+        let result = remote_builds(&runner, Path::new("/opt/steamcmd/steamcmd.sh"), &[740]).await;
+
+        match result {
+            Err(SteamError::CommandFailed { program, exit_code, output }) => {
+                assert_eq!(program, "/opt/steamcmd/steamcmd.sh");
+                assert_eq!(exit_code, Some(139));
+                assert!(output.contains("Segmentation fault"));
+            }
+            other => panic!("Expected CommandFailed, got {:?}", other.map(|builds| builds.len())),
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_only_apps_behind_the_public_branch() {
+        let runner = FakeRunner::new(&[APP_INFO]);
+        let remote = remote_builds(&runner, Path::new("steamcmd.sh"), &[740, 896660, 4000]).await.unwrap();
+
+        let outdated = outdated(&[installed(740, 14512334), installed(896660, 12187530), installed(4000, 1)], &remote);
+
+        assert_eq!(outdated, vec![OutdatedApp {
+            app_id: 740,
+            name: "App 740".to_string(),
+            install_dir: "/srv/games/740".to_string(),
+            branch: "public".to_string(),
+            local_build_id: 14512334,
+            remote_build_id: 14512400,
+            remote_updated: Some(1697122800),
+        }]);
+    }
+
+    #[tokio::test]
+    async fn compares_with_the_configured_branch() {
+        let runner = FakeRunner::new(&[APP_INFO]);
+        let remote = remote_builds(&runner, Path::new("steamcmd.sh"), &[740]).await.unwrap();
+        let mut on_beta = installed(740, 14512400);
+        on_beta.configured_branch = "beta".to_string();
+
+        let outdated = outdated(&[on_beta], &remote);
+
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].branch, "beta");
+        assert_eq!(outdated[0].remote_build_id, 14600001);
+    }
+
+    #[test]
+    fn braces_inside_strings_do_not_end_the_document() {
+        let output = "\"10\"\n{\n\t\"common\"\n\t{\n\t\t\"name\"\t\t\"Brace } yourself\"\n\t}\n}\nSteam>";
+
+        let info = app_info(output, 10).unwrap();
+
+        assert_eq!(info.get("common").and_then(|common| common.str("name")), Some("Brace } yourself"));
+    }
+}
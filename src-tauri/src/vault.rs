@@ -0,0 +1,437 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use crate::db;
+use crate::error::SteamError;
+
+/// Prefix of every value encrypted by the vault, bumped if the format changes.
+const CIPHERTEXT_PREFIX: &str = "enc:v1:";
+/// Known plaintext used to check a master password without touching real secrets.
+const CHECK_PLAINTEXT: &[u8] = b"updateio-vault";
+const KEY_FILE_NAME: &str = "vault.key";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+// Helper macro for development logging
+macro_rules! dev_log {
+    ($($arg:tt)*) => {
+        #[cfg(debug_assertions)]
+        println!("[Vault] {}", format!($($arg)*));
+    }
+}
+
+/// Where the vault key comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VaultMode {
+    /// Derived from a master password the user types in to unlock.
+    Password,
+    /// Random key stored in the app's data directory, unlocked automatically.
+    KeyFile,
+}
+
+impl VaultMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VaultMode::Password => "password",
+            VaultMode::KeyFile => "keyfile",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "password" => Some(VaultMode::Password),
+            "keyfile" => Some(VaultMode::KeyFile),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultStatus {
+    pub initialized: bool,
+    pub mode: Option<VaultMode>,
+    pub locked: bool,
+    /// The key file is missing or no longer opens the vault. The stored
+    /// secrets can't be recovered, only dropped with `vault_reset`.
+    pub needs_reset: bool,
+}
+
+/// Stored alongside the credentials so the vault can be reopened.
+pub struct VaultMeta {
+    pub mode: VaultMode,
+    pub salt: Option<String>,
+    pub check_value: String,
+}
+
+/// Holds the key while the vault is unlocked.
+#[derive(Default)]
+pub struct Vault {
+    key: Mutex<Option<[u8; 32]>>,
+}
+
+impl Vault {
+    fn set_key(&self, key: Option<[u8; 32]>) -> Result<(), SteamError> {
+        *self.key.lock().map_err(|e| SteamError::internal("Failed to lock vault", e))? = key;
+        Ok(())
+    }
+
+    fn key(&self) -> Result<[u8; 32], SteamError> {
+        self.key.lock()
+            .map_err(|e| SteamError::internal("Failed to lock vault", e))?
+            .ok_or(SteamError::VaultLocked)
+    }
+
+    fn is_locked(&self) -> bool {
+        self.key.lock().map(|key| key.is_none()).unwrap_or(true)
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, SteamError> {
+        encrypt_with(&self.key()?, plaintext.as_bytes())
+    }
+
+    pub fn decrypt(&self, value: &str) -> Result<String, SteamError> {
+        let plaintext = decrypt_with(&self.key()?, value)?;
+        String::from_utf8(plaintext).map_err(|_| SteamError::Crypto {
+            reason: "Decrypted value is not valid UTF-8".to_string(),
+        })
+    }
+}
+
+pub(crate) fn is_encrypted(value: &str) -> bool {
+    value.starts_with(CIPHERTEXT_PREFIX)
+}
+
+fn encrypt_with(key: &[u8; 32], plaintext: &[u8]) -> Result<String, SteamError> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, plaintext)
+        .map_err(|_| SteamError::Crypto { reason: "Failed to encrypt value".to_string() })?;
+
+    let mut payload = nonce.to_vec();
+    payload.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", CIPHERTEXT_PREFIX, BASE64.encode(payload)))
+}
+
+fn decrypt_with(key: &[u8; 32], value: &str) -> Result<Vec<u8>, SteamError> {
+    let encoded = value.strip_prefix(CIPHERTEXT_PREFIX)
+        .ok_or_else(|| SteamError::Crypto { reason: "Value is not encrypted by the vault".to_string() })?;
+    let payload = BASE64.decode(encoded)
+        .map_err(|e| SteamError::Crypto { reason: format!("Encrypted value is corrupt: {}", e) })?;
+    if payload.len() < NONCE_LEN {
+        return Err(SteamError::Crypto { reason: "Encrypted value is corrupt: too short".to_string() });
+    }
+
+    let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| SteamError::Crypto { reason: "Failed to decrypt value, the vault key is wrong".to_string() })
+}
+
+fn derive_key(master_password: &str, salt: &[u8]) -> Result<[u8; 32], SteamError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(master_password.as_bytes(), salt, &mut key)
+        .map_err(|e| SteamError::Crypto { reason: format!("Failed to derive vault key: {}", e) })?;
+    Ok(key)
+}
+
+fn key_file_path(app: &AppHandle) -> Result<PathBuf, SteamError> {
+    let data_dir = app.path().app_data_dir()
+        .map_err(|e| SteamError::io("Failed to get app data directory", e))?;
+    Ok(data_dir.join(KEY_FILE_NAME))
+}
+
+fn read_key_file(app: &AppHandle) -> Result<[u8; 32], SteamError> {
+    let path = key_file_path(app)?;
+    let encoded = fs::read_to_string(&path)
+        .map_err(|e| SteamError::io(&format!("Failed to read vault key file {:?}", path), e))?;
+    BASE64.decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| SteamError::Crypto { reason: format!("Vault key file {:?} is corrupt", path) })
+}
+
+fn write_key_file(app: &AppHandle, key: &[u8; 32]) -> Result<(), SteamError> {
+    let path = key_file_path(app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| SteamError::io("Failed to create app data directory", e))?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    use std::io::Write;
+    let mut file = options.open(&path)
+        .map_err(|e| SteamError::io(&format!("Failed to create vault key file {:?}", path), e))?;
+    file.write_all(BASE64.encode(key).as_bytes())
+        .map_err(|e| SteamError::io(&format!("Failed to write vault key file {:?}", path), e))
+}
+
+fn load_meta() -> Result<Option<VaultMeta>, SteamError> {
+    Ok(db::get_vault_meta()?)
+}
+
+/// Creates a new key together with the metadata needed to reopen it.
+fn create_key(app: &AppHandle, master_password: Option<&str>) -> Result<(VaultMeta, [u8; 32]), SteamError> {
+    let (mode, salt, key) = match master_password {
+        Some(password) => {
+            if password.is_empty() {
+                return Err(SteamError::InvalidInput { reason: "Master password must not be empty".to_string() });
+            }
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(password, &salt)?;
+            (VaultMode::Password, Some(BASE64.encode(salt)), key)
+        }
+        None => {
+            let mut key = [0u8; 32];
+            OsRng.fill_bytes(&mut key);
+            write_key_file(app, &key)?;
+            (VaultMode::KeyFile, None, key)
+        }
+    };
+
+    let meta = VaultMeta {
+        mode,
+        salt,
+        check_value: encrypt_with(&key, CHECK_PLAINTEXT)?,
+    };
+    Ok((meta, key))
+}
+
+/// Encrypts any credential rows still stored in plaintext.
+fn migrate_plaintext_credentials(vault: &Vault) -> Result<usize, SteamError> {
+    let rows = db::list_credential_secrets()?;
+
+    let mut migrated = 0;
+    for (id, password) in rows {
+        if is_encrypted(&password) {
+            continue;
+        }
+        db::update_credential_secret(id, &vault.encrypt(&password)?)?;
+        migrated += 1;
+    }
+
+    if migrated > 0 {
+        dev_log!("Encrypted {} plaintext credential row(s)", migrated);
+    }
+    Ok(migrated)
+}
+
+/// Opens the vault at startup.
+///
+/// A fresh install gets a key-file vault so secrets are never written in
+/// plaintext; a key-file vault is unlocked right away, a password vault
+/// stays locked until `vault_unlock` is called.
+pub(crate) fn init(app: &AppHandle) -> Result<(), SteamError> {
+    let vault = app.state::<Vault>();
+
+    match load_meta()? {
+        None => {
+            let (meta, key) = create_key(app, None)?;
+            db::save_vault_meta(&meta)?;
+            vault.set_key(Some(key))?;
+        }
+        Some(meta) if meta.mode == VaultMode::KeyFile => match open_key_file(app, &meta) {
+            Ok(key) => vault.set_key(Some(key))?,
+            // The app must still start, the vault stays locked until it is reset
+            Err(e) => {
+                dev_log!("Vault key file does not open the vault, it needs a reset: {}", e);
+                return Ok(());
+            }
+        },
+        Some(_) => return Ok(()),
+    }
+
+    migrate_plaintext_credentials(&vault)?;
+    Ok(())
+}
+
+/// Reads the key file and checks that it still opens the vault.
+fn open_key_file(app: &AppHandle, meta: &VaultMeta) -> Result<[u8; 32], SteamError> {
+    let key = read_key_file(app)?;
+    decrypt_with(&key, &meta.check_value)?;
+    Ok(key)
+}
+
+pub(crate) async fn status(app: AppHandle) -> Result<VaultStatus, SteamError> {
+    let meta = load_meta()?;
+    let locked = app.state::<Vault>().is_locked();
+    let needs_reset = match &meta {
+        Some(meta) if locked && meta.mode == VaultMode::KeyFile => open_key_file(&app, meta).is_err(),
+        _ => false,
+    };
+    Ok(VaultStatus {
+        initialized: meta.is_some(),
+        mode: meta.map(|meta| meta.mode),
+        locked,
+        needs_reset,
+    })
+}
+
+/// The key `master_password` derives for a password vault.
+fn password_key(meta: &VaultMeta, master_password: &str) -> Result<[u8; 32], SteamError> {
+    let salt = meta.salt.as_deref()
+        .and_then(|salt| BASE64.decode(salt).ok())
+        .ok_or_else(|| SteamError::Crypto { reason: "Vault metadata is corrupt: missing salt".to_string() })?;
+    derive_key(master_password, &salt)
+}
+
+/// Checks `current_password` against a password vault. A key-file vault has
+/// no password to confirm, being unlocked is all it takes.
+fn confirm_password(meta: Option<&VaultMeta>, current_password: Option<&str>) -> Result<(), SteamError> {
+    let Some(meta) = meta.filter(|meta| meta.mode == VaultMode::Password) else {
+        return Ok(());
+    };
+    let current_password = current_password.ok_or(SteamError::InvalidMasterPassword)?;
+    decrypt_with(&password_key(meta, current_password)?, &meta.check_value)
+        .map(|_| ())
+        .map_err(|_| SteamError::InvalidMasterPassword)
+}
+
+pub(crate) async fn unlock(app: AppHandle, master_password: String) -> Result<VaultStatus, SteamError> {
+    let meta = load_meta()?
+        .ok_or(SteamError::VaultNotInitialized)?;
+
+    let key = match meta.mode {
+        VaultMode::Password => password_key(&meta, &master_password)?,
+        VaultMode::KeyFile => read_key_file(&app)?,
+    };
+
+    decrypt_with(&key, &meta.check_value)
+        .map_err(|_| SteamError::InvalidMasterPassword)?;
+
+    let vault = app.state::<Vault>();
+    vault.set_key(Some(key))?;
+    migrate_plaintext_credentials(&vault)?;
+
+    status(app.clone()).await
+}
+
+pub(crate) async fn lock(app: AppHandle) -> Result<VaultStatus, SteamError> {
+    app.state::<Vault>().set_key(None)?;
+    status(app.clone()).await
+}
+
+fn decrypt_all(vault: &Vault, rows: Vec<(i64, String)>) -> Result<Vec<(i64, String)>, SteamError> {
+    rows.into_iter()
+        .map(|(id, value)| {
+            let plaintext = if is_encrypted(&value) { vault.decrypt(&value)? } else { value };
+            Ok((id, plaintext))
+        })
+        .collect()
+}
+
+fn encrypt_all(key: &[u8; 32], rows: Vec<(i64, String)>) -> Result<Vec<(i64, String)>, SteamError> {
+    rows.into_iter()
+        .map(|(id, plaintext)| Ok((id, encrypt_with(key, plaintext.as_bytes())?)))
+        .collect()
+}
+
+/// Switches the vault to a master password, or back to a key file when
+/// `master_password` is `None`, re-encrypting every stored secret.
+///
+/// A vault that already has a master password only changes it when
+/// `current_password` is that password, even while unlocked.
+pub(crate) async fn set_master_password(
+    app: AppHandle,
+    current_password: Option<String>,
+    master_password: Option<String>
+) -> Result<VaultStatus, SteamError> {
+    let vault = app.state::<Vault>();
+    let old_meta = load_meta()?;
+    confirm_password(old_meta.as_ref(), current_password.as_deref())?;
+
+    let passwords = decrypt_all(&vault, db::list_credential_secrets()?)?;
+    let shared_secrets = decrypt_all(&vault, db::list_shared_secrets()?)?;
+    let branch_passwords = decrypt_all(&vault, db::list_branch_passwords()?)?;
+
+    let (meta, key) = create_key(&app, master_password.as_deref())?;
+    db::replace_vault_meta(
+        &meta,
+        &encrypt_all(&key, passwords)?,
+        &encrypt_all(&key, shared_secrets)?,
+        &encrypt_all(&key, branch_passwords)?
+    )?;
+    vault.set_key(Some(key))?;
+
+    if meta.mode == VaultMode::Password
+        && old_meta.map(|old| old.mode) == Some(VaultMode::KeyFile)
+    {
+        // The old key file no longer matches anything stored
+        if let Ok(path) = key_file_path(&app) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    dev_log!("Vault switched to {} mode", meta.mode.as_str());
+    status(app.clone()).await
+}
+
+/// Starts over with a new key-file vault when the old one can't be opened.
+///
+/// Every stored secret is dropped: accounts and their app mappings are
+/// removed, beta branches lose their passwords. An unlocked vault is
+/// refused, its secrets can still be carried over with `set_master_password`.
+pub(crate) async fn reset(app: AppHandle) -> Result<VaultStatus, SteamError> {
+    let vault = app.state::<Vault>();
+    if !vault.is_locked() {
+        return Err(SteamError::InvalidInput {
+            reason: "The vault is unlocked, change its password instead of resetting it".to_string(),
+        });
+    }
+
+    let (meta, key) = create_key(&app, None)?;
+    db::reset_vault(&meta)?;
+    vault.set_key(Some(key))?;
+
+    dev_log!("Vault reset, stored secrets were dropped");
+    status(app.clone()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password_vault(master_password: &str) -> VaultMeta {
+        let salt = [7u8; SALT_LEN];
+        let key = derive_key(master_password, &salt).unwrap();
+        VaultMeta {
+            mode: VaultMode::Password,
+            salt: Some(BASE64.encode(salt)),
+            check_value: encrypt_with(&key, CHECK_PLAINTEXT).unwrap(),
+        }
+    }
+
+    #[test]
+    fn changing_the_master_password_needs_the_current_one() {
+        let meta = password_vault("correct horse");
+
+        assert!(confirm_password(Some(&meta), Some("correct horse")).is_ok());
+        assert!(matches!(confirm_password(Some(&meta), Some("battery staple")), Err(SteamError::InvalidMasterPassword)));
+        assert!(matches!(confirm_password(Some(&meta), None), Err(SteamError::InvalidMasterPassword)));
+    }
+
+    #[test]
+    fn a_key_file_vault_has_no_password_to_confirm() {
+        let meta = VaultMeta { mode: VaultMode::KeyFile, salt: None, check_value: String::new() };
+
+        assert!(confirm_password(Some(&meta), None).is_ok());
+        assert!(confirm_password(None, None).is_ok());
+    }
+}
@@ -0,0 +1,210 @@
+use crate::error::SteamError;
+
+/// A node of a Valve KeyValues (VDF) document, the format of `appmanifest_*.acf`
+/// and `libraryfolders.vdf`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyValues {
+    Value(String),
+    /// Children in file order; keys may repeat.
+    Object(Vec<(String, KeyValues)>),
+}
+
+impl KeyValues {
+    /// The first child called `key`. Valve treats keys case-insensitively.
+    pub fn get(&self, key: &str) -> Option<&KeyValues> {
+        match self {
+            KeyValues::Object(children) => children.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value),
+            KeyValues::Value(_) => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            KeyValues::Value(value) => Some(value),
+            KeyValues::Object(_) => None,
+        }
+    }
+
+    /// Reads `key` as a string value.
+    pub fn str(&self, key: &str) -> Option<&str> {
+        self.get(key)?.as_str()
+    }
+
+    /// Reads `key` as an unsigned number.
+    pub fn u64(&self, key: &str) -> Option<u64> {
+        self.str(key)?.trim().parse().ok()
+    }
+
+    pub fn children(&self) -> &[(String, KeyValues)] {
+        match self {
+            KeyValues::Object(children) => children,
+            KeyValues::Value(_) => &[],
+        }
+    }
+}
+
+/// Parses a document into its root key and value, e.g. `"AppState" { ... }`.
+pub(crate) fn parse(text: &str) -> Result<(String, KeyValues), SteamError> {
+    let mut parser = Parser { tokens: tokenize(text)?, position: 0 };
+    let key = match parser.next() {
+        Some(Token::Text(key)) => key,
+        _ => return Err(invalid("Expected a root key")),
+    };
+    let value = parser.value()?;
+    if parser.next().is_some() {
+        return Err(invalid("Unexpected content after the root object"));
+    }
+    Ok((key, value))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Text(String),
+    Open,
+    Close,
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn value(&mut self) -> Result<KeyValues, SteamError> {
+        match self.next() {
+            Some(Token::Text(value)) => Ok(KeyValues::Value(value)),
+            Some(Token::Open) => {
+                let mut children = Vec::new();
+                loop {
+                    match self.next() {
+                        Some(Token::Close) => return Ok(KeyValues::Object(children)),
+                        Some(Token::Text(key)) => children.push((key, self.value()?)),
+                        Some(Token::Open) => return Err(invalid("Expected a key before '{'")),
+                        None => return Err(invalid("Unclosed '{'")),
+                    }
+                }
+            }
+            Some(Token::Close) => Err(invalid("Unexpected '}'")),
+            None => Err(invalid("Missing value at the end of the document")),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, SteamError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '{' => tokens.push(Token::Open),
+            '}' => tokens.push(Token::Close),
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            // Platform conditionals like [$WIN32] apply to the previous key; every platform is kept
+            '[' => {
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(c) => value.push(c),
+                            None => return Err(invalid("Unterminated string")),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err(invalid("Unterminated string")),
+                    }
+                }
+                tokens.push(Token::Text(value));
+            }
+            c => {
+                let mut value = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '{' | '}' | '"') {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Text(value));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn invalid(reason: &str) -> SteamError {
+    SteamError::InvalidInput { reason: format!("Invalid KeyValues document: {}", reason) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = include_str!("../testdata/manifests/appmanifest_740.acf");
+
+    #[test]
+    fn parses_an_app_manifest() {
+        let (key, state) = parse(MANIFEST).unwrap();
+
+        assert_eq!(key, "AppState");
+        assert_eq!(state.u64("appid"), Some(740));
+        assert_eq!(state.str("name"), Some("Counter-Strike Global Offensive - Dedicated Server"));
+        assert_eq!(state.u64("buildid"), Some(14512334));
+        assert_eq!(state.get("InstalledDepots").unwrap().children().len(), 2);
+        assert_eq!(state.get("UserConfig").and_then(|config| config.str("language")), Some("english"));
+    }
+
+    #[test]
+    fn keys_are_case_insensitive() {
+        let (_, state) = parse(MANIFEST).unwrap();
+
+        assert_eq!(state.u64("StateFlags"), state.u64("stateflags"));
+    }
+
+    #[test]
+    fn reads_escapes_comments_and_unquoted_tokens() {
+        let (_, root) = parse("root // the root\n{\n  path \"C:\\\\Games\\\\\\\"Lib\\\"\"\n  flag 1 [$WIN32]\n}\n").unwrap();
+
+        assert_eq!(root.str("path"), Some("C:\\Games\\\"Lib\""));
+        assert_eq!(root.u64("flag"), Some(1));
+    }
+
+    #[test]
+    fn keeps_repeated_keys() {
+        let (_, root) = parse("\"root\" { \"a\" \"1\" \"a\" \"2\" }").unwrap();
+
+        assert_eq!(root.children().len(), 2);
+        assert_eq!(root.str("a"), Some("1"));
+    }
+
+    #[test]
+    fn rejects_truncated_documents() {
+        assert!(parse("\"AppState\"\n{\n\t\"appid\"\t\t\"740\"\n").is_err());
+        assert!(parse("\"AppState\"\n{\n\t\"name\"\t\t\"Counter").is_err());
+        assert!(parse("").is_err());
+    }
+}
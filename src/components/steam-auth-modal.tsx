@@ -32,6 +32,7 @@ export default function SteamAuthModal({
     username: "",
     password: "",
     two_factor_code: "",
+    shared_secret: "",
   })
 
   // Reset form when modal is closed
@@ -41,6 +42,7 @@ export default function SteamAuthModal({
         username: "",
         password: "",
         two_factor_code: "",
+        shared_secret: "",
       })
       setAuthState('initial')
     }
@@ -65,7 +67,8 @@ export default function SteamAuthModal({
         credentials: {
           username: credentials.username,
           password: credentials.password,
-          two_factor_code: authState === 'two_factor' ? credentials.two_factor_code : undefined
+          two_factor_code: authState === 'two_factor' ? credentials.two_factor_code : undefined,
+          shared_secret: credentials.shared_secret.trim() || undefined
         }
       }) as string
 
@@ -79,7 +82,7 @@ export default function SteamAuthModal({
         onOpenChange(false)
       }
     } catch (error: any) {
-      if (error.toString().includes("Steam Guard code required")) {
+      if (error?.code === 'STEAM_GUARD_REQUIRED') {
         setAuthState('two_factor')
         toast({
           title: 'Требуется двухфакторная аутентификация',
@@ -90,7 +93,7 @@ export default function SteamAuthModal({
 
       toast({
         title: 'Ошибка',
-        description: `Не удалось привязать аккаунт: ${error}`,
+        description: `Не удалось привязать аккаунт: ${error?.message ?? error}`,
       })
       setAuthState('initial')
     }
@@ -153,6 +156,22 @@ export default function SteamAuthModal({
                   disabled={isLoading}
                 />
               </div>
+              <div className="space-y-2">
+                <label htmlFor="sharedSecret" className="text-sm font-medium">
+                  Shared secret (необязательно)
+                </label>
+                <Input
+                  id="sharedSecret"
+                  type="password"
+                  value={credentials.shared_secret}
+                  onChange={handleInputChange('shared_secret')}
+                  autoComplete="off"
+                  disabled={isLoading}
+                />
+                <p className="text-sm text-muted-foreground">
+                  Коды Steam Guard будут генерироваться автоматически
+                </p>
+              </div>
             </>
           ) : (
             <div className="space-y-2">
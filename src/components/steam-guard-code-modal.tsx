@@ -1,23 +1,62 @@
-import { Dialog, DialogContent, DialogHeader, DialogTitle } from '@/components/ui/dialog'
+import { Dialog, DialogContent, DialogDescription, DialogHeader, DialogTitle } from '@/components/ui/dialog'
 import Button from './ui/button'
 import Input from './ui/input'
-import { useState } from 'react'
+import { useEffect, useState } from 'react'
 import { invoke } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import { useToast } from '@/hooks/use-toast'
 import { LoaderCircle } from 'lucide-react'
 
+type GuardChallenge = {
+  id: number
+  username: string
+  kind: 'email' | 'mobile'
+  created_at: number
+  expires_at: number
+}
+
+const descriptionMap = {
+  'email': 'Steam отправил код на почту, привязанную к аккаунту',
+  'mobile': 'Откройте мобильное приложение Steam и введите код из аутентификатора',
+} as const
+
 export default function SteamGuardCodeModal() {
-  const [open, setOpen] = useState(false)
+  const [challenges, setChallenges] = useState<GuardChallenge[]>([])
   const [loading, setLoading] = useState(false)
   const { toast } = useToast()
-  const onOpenChange = (open: boolean) => setOpen(open)
+  const challenge = challenges[0]
+
+  useEffect(() => {
+    invoke<GuardChallenge[]>('list_2fa_challenges').then(setChallenges).catch(() => {})
+
+    const required = listen<GuardChallenge>('steam-guard-required', (event) => {
+      setChallenges((current) => [...current.filter((c) => c.id !== event.payload.id), event.payload])
+    })
+    const resolved = listen<number>('steam-guard-resolved', (event) => {
+      setChallenges((current) => current.filter((c) => c.id !== event.payload))
+    })
+
+    return () => {
+      required.then((unlisten) => unlisten())
+      resolved.then((unlisten) => unlisten())
+    }
+  }, [])
+
+  const dismiss = (id: number) => setChallenges((current) => current.filter((c) => c.id !== id))
+
+  const onOpenChange = async (open: boolean) => {
+    if (open || !challenge) return
+    dismiss(challenge.id)
+    await invoke('cancel_2fa_challenge', { challengeId: challenge.id }).catch(() => {})
+  }
 
   const handleSubmit = async (e: React.FormEvent<HTMLFormElement>) => {
     e.preventDefault()
+    if (!challenge) return
     try {
       setLoading(true)
-      await invoke('submit_2fa_code', { code: e.currentTarget.code.value })
-      setOpen(false)
+      await invoke('submit_2fa_code', { challengeId: challenge.id, code: e.currentTarget.code.value })
+      dismiss(challenge.id)
     } catch (error) {
       toast({
         title: 'Ошибка',
@@ -29,15 +68,20 @@ export default function SteamGuardCodeModal() {
   }
 
   return (
-    <Dialog open={open} onOpenChange={onOpenChange}>
+    <Dialog open={!!challenge} onOpenChange={onOpenChange}>
       <DialogContent>
         <DialogHeader>
           <DialogTitle>
             Введите код двухфакторной аутентификации
           </DialogTitle>
+          {challenge && (
+            <DialogDescription>
+              {challenge.username}: {descriptionMap[challenge.kind]}
+            </DialogDescription>
+          )}
         </DialogHeader>
-        <form onSubmit={handleSubmit} className="space-y-4">
-          <Input type="text" placeholder="Код" />
+        <form key={challenge?.id} onSubmit={handleSubmit} className="space-y-4">
+          <Input name="code" type="text" placeholder="Код" autoComplete="one-time-code" autoFocus />
           <Button type="submit" disabled={loading}>
             {loading ? <LoaderCircle className="animate-spin" /> : 'Отправить'}
           </Button>
@@ -2,7 +2,8 @@ import { Dialog, DialogContent, DialogDescription, DialogTitle, DialogFooter, Di
 import Button from "@/components/ui/button";
 import getSettings from "@/entities/settings/api/getSettings";
 import { useMutation, useQuery } from "@tanstack/react-query";
-import { updateGame } from "@/entities/game/api/updateGame";
+import { runUpdate } from "@/entities/game/api/updateGame";
+import { Settings } from "@/entities/settings/settings.entity";
 import { useToast } from "@/hooks/use-toast";
 import { LoaderCircle } from "lucide-react";
 import { useUpdateQueue } from '@/entities/update/store/updateQueue'
@@ -30,7 +31,7 @@ export default function UpdateGameModal({ open, onOpenChange, app, game }: Updat
   })
 
   const updateGameMutation = useMutation({
-    mutationFn: async (command: number) => {
+    mutationFn: async (settings: Settings) => {
       // Create queue item and get its ID
       const queueId = crypto.randomUUID();
       addToQueue({
@@ -41,7 +42,7 @@ export default function UpdateGameModal({ open, onOpenChange, app, game }: Updat
       });
       
       try {
-        const result = await updateGame(command);
+        const result = await runUpdate(settings);
         updateStatus(queueId, 'completed', result);
         return result;
       } catch (error) {
@@ -69,9 +70,9 @@ export default function UpdateGameModal({ open, onOpenChange, app, game }: Updat
   });
 
   const handleUpdateGame = () => {
-    const command = settings?.data[0]?.updateCommand
-    if (!command) return
-    updateGameMutation.mutate(+command)
+    const record = settings?.data[0]
+    if (!record) return
+    updateGameMutation.mutate(record)
   }
 
   return (
@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/core'
 import { getSteamCredentials } from '@/entities/steam/storage'
+import { Settings } from '@/entities/settings/settings.entity'
 
 export async function updateGame(appId: number): Promise<string> {
   const credentials = getSteamCredentials();
@@ -14,4 +15,8 @@ export async function updateGame(appId: number): Promise<string> {
     appId,
     credentials 
   })
-} 
\ No newline at end of file
+} 
+
+export async function runUpdate(settings: Settings): Promise<string> {
+  return invoke('run_update', { settings })
+}
@@ -20,6 +20,7 @@ import {
   Home,
 } from 'lucide-react'
 import { UpdateQueueBell } from '@/components/update-queue-bell'
+import SteamGuardCodeModal from '@/components/steam-guard-code-modal'
 import { RouteContext } from '@/lib/types'
 
 function Layout() {
@@ -112,6 +113,7 @@ function Layout() {
           <Outlet />
         </main>
       </SidebarProvider>
+      <SteamGuardCodeModal />
     </>
   )
 }